@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::commands::run::inst_compile_run;
+use crate::utils::debugger_cache::*;
+use crate::utils::debugee_project_info::get_program_info;
+use crate::instrument::*;
+use crate::output::*;
+
+/// Run the program with each input and show how often each line was executed
+///
+/// Prints the source with the hit counts and writes them in the lcov format to `lcov_path`. Without inputs, the configured input is used
+///
+/// Each hit is logged, which costs about 100 compute units, see `inst_ast_coverage`
+pub(crate) async fn process_coverage(input_paths: Vec<PathBuf>, lcov_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if !get_cache_dir().is_dir() {
+        Err("Cache directory does not exist. Run 'init' to create it")?
+    }
+    let config: DebuggerConfig = DebuggerConfig::load_from_file(&get_config_path())?;
+    config.validate()?;
+
+    let input_paths = match input_paths.is_empty() {
+        true => vec![config.input_path.clone()],
+        false => input_paths,
+    };
+    for input_path in &input_paths {
+        if !input_path.is_dir() {
+            Err(format!("Input is not a directory: {}", input_path.display()))?
+        }
+    }
+
+    let debugee_project_info = get_program_info(&config.program_path)?;
+
+    let run_output = inst_compile_run(&debugee_project_info, InstProjectSpec::Coverage, CallStackInst::Off, &input_paths, false).await?;
+
+    let mut hits = CoverageHits::default();
+    for (input_path, program_output) in input_paths.iter().zip(&run_output.outputs) {
+        parse_coverage_hits(program_output.logs.clone(), &mut hits)?;
+        if let Some(tx_error) = &program_output.tx_error {
+            eprintln!("Transaction of {} failed: {}", input_path.display(), tx_error);
+        }
+    }
+
+    // All files of the program, also the ones that were never executed
+    let source_path = debugee_project_info.program_path.join("src");
+    let mut files = Vec::new();
+    for (path, ast) in parse_source_files(&source_path)? {
+        // Same as the file logged with the hits, e.g. `src/processor.rs`
+        let file = Path::new("src").join(path.strip_prefix(&source_path)?).to_string_lossy().to_string();
+        let lines = get_coverage_lines(ast);
+        if lines.is_empty() {
+            continue;
+        }
+        let file_hits = hits.files.remove(&file).unwrap_or_default();
+        let line_hits: BTreeMap<usize, usize> = lines.into_iter()
+            .map(|line| (line, file_hits.get(&line).copied().unwrap_or(0)))
+            .collect();
+        files.push((file, path, line_hits));
+    }
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (file, path, line_hits) in &files {
+        print_annotated_source(file, path, line_hits)?;
+        println!();
+    }
+
+    println!("{:<40} {:>6} {:>6} {:>7}", "File", "Lines", "Hit", "Cover");
+    let (mut total_lines, mut total_hit) = (0, 0);
+    for (file, _, line_hits) in &files {
+        let hit = line_hits.values().filter(|count| **count > 0).count();
+        println!("{:<40} {:>6} {:>6} {:>7}", file, line_hits.len(), hit, get_percentage(hit, line_hits.len()));
+        total_lines += line_hits.len();
+        total_hit += hit;
+    }
+    println!("{:<40} {:>6} {:>6} {:>7}", "Total", total_lines, total_hit, get_percentage(total_hit, total_lines));
+
+    if hits.truncated {
+        eprintln!();
+        eprintln!("The log was truncated, so the hit counts are incomplete");
+    }
+
+    write_lcov(lcov_path, &files)?;
+    eprintln!();
+    eprintln!("Wrote {}", lcov_path.display());
+
+    Ok(())
+}
+
+/// Lines that were never executed are marked with `#####`, lines without a statement have no count
+fn print_annotated_source(file: &str, path: &Path, line_hits: &BTreeMap<usize, usize>) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", file);
+    let source = fs::read_to_string(path)?;
+    for (i, source_line) in source.lines().enumerate() {
+        let count = match line_hits.get(&(i + 1)) {
+            Some(0) => "#####".to_string(),
+            Some(count) => count.to_string(),
+            None => String::new(),
+        };
+        println!("{:>8} {:>5} | {}", count, i + 1, source_line);
+    }
+    Ok(())
+}
+
+fn get_percentage(hit: usize, total: usize) -> String {
+    match total {
+        0 => "-".to_string(),
+        _ => format!("{:.1}%", hit as f64 * 100.0 / total as f64),
+    }
+}
+
+/// See the tracefile format of `geninfo`
+fn write_lcov(lcov_path: &Path, files: &[(String, PathBuf, BTreeMap<usize, usize>)]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut output = fs::File::create(lcov_path)?;
+    for (_, path, line_hits) in files {
+        writeln!(output, "SF:{}", path.display())?;
+        for (line, count) in line_hits {
+            writeln!(output, "DA:{},{}", line, count)?;
+        }
+        writeln!(output, "LF:{}", line_hits.len())?;
+        writeln!(output, "LH:{}", line_hits.values().filter(|count| **count > 0).count())?;
+        writeln!(output, "end_of_record")?;
+    }
+    Ok(())
+}
@@ -0,0 +1,155 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use colored::*;
+use crate::commands::run::inst_compile_run;
+use crate::commands::var::*;
+use crate::utils::debugger_cache::*;
+use crate::utils::debugee_project_info::get_program_info;
+use crate::instrument::*;
+use crate::output::*;
+
+/// Longer values are cut, so each variable stays on one line
+const VALUE_MAX_LEN: usize = 40;
+
+/// Run the program with two inputs and show where the executions diverge first
+///
+/// All lines of the program are traced. The hits are aligned by location, see `find_divergence`. The first hits at other locations (control flow)
+/// and the first hits with other values are shown separately. Changes of `ignored_fields` don't count
+pub(crate) async fn process_diff_run(input_path_a: PathBuf, input_path_b: PathBuf, ignored_fields: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    if !get_cache_dir().is_dir() {
+        Err("Cache directory does not exist. Run 'init' to create it")?
+    }
+    let config: DebuggerConfig = DebuggerConfig::load_from_file(&get_config_path())?;
+    config.validate()?;
+
+    for input_path in [&input_path_a, &input_path_b] {
+        if !input_path.is_dir() {
+            Err(format!("Input is not a directory: {}", input_path.display()))?
+        }
+    }
+
+    let debugee_project_info = get_program_info(&config.program_path)?;
+    let program_path = &debugee_project_info.program_path;
+
+    let files = parse_source_files(&program_path.join("src"))?.into_iter().map(|(path, _)| path).collect();
+    let inst_spec = InstProjectSpec::Record { files };
+    let run_output = inst_compile_run(&debugee_project_info, inst_spec, CallStackInst::Off, &[input_path_a.clone(), input_path_b.clone()], false).await?;
+    let [output_a, output_b] = <[ProgramOutput; 2]>::try_from(run_output.outputs).map_err(|_| "Expected an output for each input")?;
+
+    let line_vars_a = parse_program_output(output_a.logs.clone())?;
+    let line_vars_b = parse_program_output(output_b.logs.clone())?;
+
+    println!();
+    println!("A: {}", input_path_a.display());
+    println!("B: {}", input_path_b.display());
+    println!();
+
+    let divergence = find_divergence(&line_vars_a, &line_vars_b, ignored_fields);
+    if divergence.control_flow.is_none() && divergence.values.is_none() {
+        println!("The executions are the same ({} lines)", line_vars_a.len());
+    }
+
+    if let Some(control_flow) = &divergence.control_flow {
+        print_control_flow(program_path, control_flow, &line_vars_a, &line_vars_b);
+    }
+
+    if let Some(values) = &divergence.values {
+        if divergence.control_flow.is_some() {
+            println!();
+        }
+        let item = &line_vars_a[values.index_a];
+        println!("{} at {} (line {} of A, line {} of B)", "Values diverge".bold(), get_location_string(item), values.index_a + 1, values.index_b + 1);
+        println!("{:>6} | {}", item.line_num, get_source_line(program_path, item));
+        println!();
+        print_changes(&values.changes);
+        println!();
+        print_call_stack(item);
+    }
+
+    if output_a.tx_error != output_b.tx_error {
+        println!();
+        println!("Transaction results differ");
+        for (label, program_output) in [("A", &output_a), ("B", &output_b)] {
+            match &program_output.tx_error {
+                Some(tx_error) => println!("  {}: failed: {}", label, tx_error),
+                None => println!("  {}: success", label),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_control_flow(program_path: &Path, control_flow: &ControlFlowDivergence, line_vars_a: &[LineVars], line_vars_b: &[LineVars]) {
+    let ControlFlowDivergence { index_a, index_b, rejoin } = *control_flow;
+    // The last line both executions had in common
+    match index_a.checked_sub(1).map(|last| &line_vars_a[last]) {
+        Some(last_item) => {
+            println!("The executions run the same {} lines, until {}", index_a, get_location_string(last_item));
+            println!("{:>6} | {}", last_item.line_num, get_source_line(program_path, last_item));
+        }
+        None => println!("The executions differ from the first line"),
+    }
+    println!();
+
+    match (line_vars_a.get(index_a), line_vars_b.get(index_b)) {
+        (Some(item_a), Some(item_b)) => {
+            println!("{}", "Control flow diverges".bold());
+            print_location("A", program_path, item_a);
+            print_location("B", program_path, item_b);
+        }
+        (None, Some(item)) | (Some(item), None) => {
+            let (ended, other) = match index_a == line_vars_a.len() {
+                true => ("A", "B"),
+                false => ("B", "A"),
+            };
+            println!("{}", format!("Execution {} ends, {} continues", ended, other).bold());
+            print_location(other, program_path, item);
+        }
+        (None, None) => {}
+    }
+
+    if let Some((rejoin_a, rejoin_b)) = rejoin {
+        println!();
+        println!("They run the same lines again after {} lines of A and {} lines of B, from {}", rejoin_a - index_a, rejoin_b - index_b, get_location_string(&line_vars_a[rejoin_a]));
+    }
+}
+
+fn print_location(label: &str, program_path: &Path, item: &LineVars) {
+    println!("  {}: {}", label, get_location_string(item));
+    println!("  {:>6} | {}", item.line_num, get_source_line(program_path, item));
+}
+
+/// The values of both executions, side by side
+fn print_changes(changes: &[NodeChange]) {
+    let rows: Vec<(&str, String, String)> = changes.iter().map(|change| match change {
+        NodeChange::Added { path, node } => (path.as_str(), "-".to_string(), get_compact_value(node, VALUE_MAX_LEN)),
+        NodeChange::Removed { path, node } => (path.as_str(), get_compact_value(node, VALUE_MAX_LEN), "-".to_string()),
+        NodeChange::Changed { path, old_value, new_value } => (path.as_str(), get_compact_str(old_value, VALUE_MAX_LEN), get_compact_str(new_value, VALUE_MAX_LEN)),
+    }).collect();
+
+    let path_width = rows.iter().map(|(path, _, _)| path.chars().count()).chain(["Variable".len()]).max().unwrap_or(0);
+    let value_width = rows.iter().map(|(_, value_a, _)| value_a.chars().count()).chain(["A".len()]).max().unwrap_or(0);
+    println!("{:<path_width$}  {:<value_width$}  B", "Variable", "A");
+    for (path, value_a, value_b) in &rows {
+        // Pad before coloring, the escape codes would count as characters
+        println!("{}  {}  {}", format!("{:<path_width$}", path).bright_yellow(), format!("{:<value_width$}", value_a).bright_purple(), value_b.bright_purple());
+    }
+}
+
+/// E.g. `src/processor.rs:42 (Processor::process_transfer)`
+fn get_location_string(item: &LineVars) -> String {
+    let file = item.file.as_deref().unwrap_or("?");
+    match item.call_stack.last() {
+        Some(frame) => format!("{}:{} ({})", file, item.line_num, frame.function),
+        None => format!("{}:{}", file, item.line_num),
+    }
+}
+
+/// The logged file is relative to the program, see `inst_source`
+fn get_source_line(program_path: &Path, item: &LineVars) -> String {
+    item.file.as_ref()
+        .and_then(|file| fs::read_to_string(program_path.join(file)).ok())
+        .and_then(|source| source.lines().nth(item.line_num - 1).map(|line| line.trim().to_string()))
+        .unwrap_or_default()
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::fs;
+use colored::*;
+use crate::commands::run::inst_compile_run;
+use crate::commands::step::*;
+use crate::commands::var::*;
+use crate::utils::debugger_cache::*;
+use crate::utils::debugee_project_info::get_program_info;
+use crate::instrument::*;
+use crate::output::*;
+
+/// Longer values are cut, so each hit stays on one line
+const VALUE_MAX_LEN: usize = 100;
+
+/// Run the program and show the value of a variable at each line of a function where it is in scope
+///
+/// Lines in a loop are shown once per iteration, with the number of the iteration
+pub(crate) async fn process_history(var: &str, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !get_cache_dir().is_dir() {
+        Err("Cache directory does not exist. Run 'init' to create it")?
+    }
+    let config: DebuggerConfig = DebuggerConfig::load_from_file(&get_config_path())?;
+    config.validate()?;
+
+    let debugee_project_info = get_program_info(&config.program_path)?;
+
+    let (file, fn_range) = resolve_fn(&debugee_project_info, name)?;
+    let location_ast = parse_location_file(&file)?;
+    let pat_constants = get_package_pat_constants(&debugee_project_info, &file)?;
+
+    // Only the points where the variable is in scope
+    let mut points = Vec::new();
+    let mut names_in_scope = Vec::new();
+    for point in get_fn_points(&location_ast, &fn_range) {
+        let scope = get_scope(&location_ast, point, pat_constants.clone()).unwrap_or_default();
+        if scope.iter().any(|variable| is_variable_name(&variable.name, var)) {
+            points.push(point);
+        }
+        names_in_scope.extend(scope.into_iter().map(|variable| variable.name));
+    }
+    if points.is_empty() {
+        let suggestion = get_suggestion(var, names_in_scope.iter().map(|name| name.as_str()));
+        Err(format!("Variable {} is not in scope anywhere in {}{}", var, fn_range.name, suggestion))?
+    }
+
+    let inst_spec = InstProjectSpec::Points { file: file.clone(), points };
+    let mut run_output = inst_compile_run(&debugee_project_info, inst_spec, CallStackInst::Location, std::slice::from_ref(&config.input_path), false).await?;
+    let program_output = run_output.outputs.remove(0);
+
+    let line_vars = parse_program_output(program_output.logs.clone())?;
+
+    let source = fs::read_to_string(&file)?;
+    let source_lines: Vec<&str> = source.lines().collect();
+
+    println!();
+    println!("{} in {} ({})", var.bold().bright_yellow(), fn_range.name, get_display_path(&debugee_project_info, &file));
+    if line_vars.is_empty() {
+        println!();
+        println!("The function was never called");
+    }
+
+    for (i, call) in get_calls(&line_vars).into_iter().enumerate() {
+        println!();
+        println!("Call {}", i + 1);
+        println!("{:>6} {:>5}  Value", "Line", "Pass");
+        // The pass is the iteration of the loop the line is in
+        let mut passes: HashMap<usize, usize> = HashMap::new();
+        let mut previous_value: Option<String> = None;
+        for item in call {
+            let pass = passes.entry(item.line_num).or_default();
+            *pass += 1;
+            let Some(node) = item.nodes.iter().find(|node| is_variable_name(&node.name, var)) else {
+                continue;
+            };
+            let value = get_compact_value(node, VALUE_MAX_LEN);
+            // Unchanged values are dimmed, so the changes stand out
+            let value_str = match previous_value.as_ref() == Some(&value) {
+                true => value.dimmed(),
+                false => value.bright_purple(),
+            };
+            // E.g. ` (line 41)` after the variable is shadowed
+            let binding_str = node.name[var.len()..].italic();
+            let source_line = source_lines.get(item.line_num - 1).map(|line| line.trim()).unwrap_or_default();
+            let return_str = match is_return(item) {
+                true => " (return)",
+                false => "",
+            };
+            println!("{:>6} {:>5}  {}{}    {}{}", item.line_num, pass, value_str, binding_str, source_line.dimmed(), return_str.italic());
+            previous_value = Some(value);
+        }
+    }
+
+    if let Some(tx_error) = &program_output.tx_error {
+        println!();
+        println!("Transaction failed: {}", tx_error);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use crate::commands::var::*;
+use crate::instrument::*;
+use crate::utils::debugger_cache::*;
+use crate::utils::debugee_project_info::get_program_info;
+
+/// How many lines are shown around `LINE` if it's not inside a function
+const CONTEXT_LINES: usize = 5;
+
+/// Show the source of a file. Lines marked with `*` can be used as location for `var`, followed by the number of variables in scope
+///
+/// With a line, only the function that contains it is shown
+pub(crate) fn process_list(file: &str, line: Option<usize>) -> Result<(), Box<dyn std::error::Error>> {
+    if !get_cache_dir().is_dir() {
+        Err("Cache directory does not exist. Run 'init' to create it")?
+    }
+    let config: DebuggerConfig = DebuggerConfig::load_from_file(&get_config_path())?;
+    config.validate()?;
+
+    let debugee_project_info = get_program_info(&config.program_path)?;
+
+    let file_path = resolve_location_file(&debugee_project_info, file)?;
+    let source = fs::read_to_string(&file_path)?;
+    let source_lines: Vec<&str> = source.lines().collect();
+    let location_ast = parse_location_file(&file_path)?;
+    let pat_constants = get_package_pat_constants(&debugee_project_info, &file_path)?;
+
+    let fn_ranges = get_fn_ranges(&location_ast);
+
+    let (first_line, last_line) = match line {
+        Some(line) if line == 0 || line > source_lines.len() => {
+            Err(format!("Line {} is not in {} ({} lines)", line, file, source_lines.len()))?
+        }
+        Some(line) => {
+            // The innermost function that contains the line
+            let fn_range = fn_ranges.iter()
+                .rev()
+                .find(|fn_range| fn_range.start_line <= line && line <= fn_range.end_line);
+            match fn_range {
+                Some(fn_range) => (fn_range.start_line, fn_range.end_line),
+                None => (line.saturating_sub(CONTEXT_LINES).max(1), (line + CONTEXT_LINES).min(source_lines.len())),
+            }
+        }
+        None => (1, source_lines.len()),
+    };
+
+    println!("{}", get_display_path(&debugee_project_info, &file_path));
+
+    for current_line in first_line..=last_line {
+        if let Some(fn_range) = fn_ranges.iter().find(|fn_range| fn_range.start_line == current_line) {
+            println!();
+            println!("{:>12} | fn {}", "", fn_range.name);
+        }
+
+        let selected = match line == Some(current_line) {
+            true => ">",
+            false => " ",
+        };
+        // Only lines where a statement starts (or a block ends) can be used as location
+        let point = resolve_injection_point(&location_ast, current_line, None)
+            .filter(|point| point.line() == current_line);
+        let annotation = match point {
+            Some(point) => {
+                let scope = get_scope(&location_ast, point, pat_constants.clone()).unwrap_or_default();
+                format!("* {:>3}", format!("({})", scope.len()))
+            }
+            None => String::new(),
+        };
+        println!("{} {:>4} {:<5} | {}", selected, current_line, annotation, source_lines[current_line - 1]);
+    }
+
+    Ok(())
+}
@@ -1,3 +1,14 @@
 pub mod init;
 pub mod var;
-pub mod status;
\ No newline at end of file
+pub mod status;
+pub mod scope;
+pub mod list;
+pub mod run;
+pub mod coverage;
+pub mod profile;
+pub mod record;
+pub mod query;
+pub mod step;
+pub mod history;
+pub mod diff_run;
+pub mod snapshot;
\ No newline at end of file
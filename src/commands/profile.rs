@@ -0,0 +1,124 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use crate::commands::run::inst_compile_run;
+use crate::utils::debugger_cache::*;
+use crate::utils::debugee_project_info::get_program_info;
+use crate::instrument::*;
+use crate::output::*;
+
+/// Width of the bars of the heatmap
+const BAR_WIDTH: usize = 20;
+
+/// Run the program with compute unit probes and show the cost of each function, and of each line of one function
+///
+/// `function`: the function of the heatmap. Defaults to the one with the highest exclusive cost.
+/// The folded call stacks are written to `folded_path`, e.g. for `flamegraph.pl` or `inferno-flamegraph`
+pub(crate) async fn process_profile(function: Option<String>, folded_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    if !get_cache_dir().is_dir() {
+        Err("Cache directory does not exist. Run 'init' to create it")?
+    }
+    let config: DebuggerConfig = DebuggerConfig::load_from_file(&get_config_path())?;
+    config.validate()?;
+
+    let debugee_project_info = get_program_info(&config.program_path)?;
+
+    let mut run_output = inst_compile_run(&debugee_project_info, InstProjectSpec::Profile, CallStackInst::Off, std::slice::from_ref(&config.input_path), false).await?;
+    let program_output = run_output.outputs.remove(0);
+
+    let profile = parse_profile(&program_output.logs)?;
+    if profile.functions.is_empty() {
+        Err("No function of the program was executed")?
+    }
+
+    //
+    // Functions
+    //
+
+    println!("Compute units, probe overhead ({} per probe) subtracted", profile.probe_overhead);
+    println!();
+    println!("{:>10} {:>10} {:>6}  Function", "Inclusive", "Exclusive", "Calls");
+    let mut functions: Vec<_> = profile.functions.iter().collect();
+    functions.sort_by(|a, b| b.1.inclusive.cmp(&a.1.inclusive).then(b.1.exclusive.cmp(&a.1.exclusive)));
+    for ((name, file), function_profile) in &functions {
+        println!("{:>10} {:>10} {:>6}  {} ({})", function_profile.inclusive, function_profile.exclusive, function_profile.calls, name, file);
+    }
+
+    let consumed: u64 = profile.consumed.iter().sum();
+    if consumed > 0 {
+        println!();
+        println!("Consumed {} units with probes, about {} without", consumed, consumed.saturating_sub(profile.total_overhead));
+    }
+
+    //
+    // Heatmap
+    //
+
+    let ((name, file), function_profile) = match &function {
+        Some(function) => *functions.iter()
+            .find(|((name, _), _)| name == function || name.ends_with(&format!("::{}", function)))
+            .ok_or(format!("Function {} was not executed", function))?,
+        None => *functions.iter().max_by_key(|(_, function_profile)| function_profile.exclusive).unwrap(),
+    };
+    println!();
+    print_heatmap(&debugee_project_info.program_path.join(file), name, file, function_profile)?;
+
+    write_folded(folded_path, &profile)?;
+    eprintln!();
+    eprintln!("Wrote {}", folded_path.display());
+
+    if let Some(tx_error) = &program_output.tx_error {
+        println!();
+        println!("Transaction failed: {}", tx_error);
+    }
+
+    Ok(())
+}
+
+/// The source of the function with the cost of each line, including the functions called on it
+fn print_heatmap(path: &Path, name: &str, file: &str, function_profile: &FunctionProfile) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{} ({})", name, file);
+    let source = fs::read_to_string(path)?;
+    let source_lines: Vec<&str> = source.lines().collect();
+    let location_ast = syn::parse_file(&source)?;
+
+    let Some(first_line) = function_profile.lines_inclusive.keys().next().copied() else {
+        println!("No lines were executed");
+        return Ok(());
+    };
+    // The innermost function with this name that contains the lines
+    let (start_line, end_line) = get_fn_ranges(&location_ast).into_iter()
+        .rev()
+        .find(|fn_range| fn_range.name == name && fn_range.start_line <= first_line && first_line <= fn_range.end_line)
+        .map(|fn_range| (fn_range.start_line, fn_range.end_line))
+        .unwrap_or((first_line, function_profile.lines_inclusive.keys().last().copied().unwrap_or(first_line)));
+
+    let max_cost = function_profile.lines_inclusive.values().copied().max().unwrap_or(0).max(1);
+    for line in start_line..=end_line.min(source_lines.len()) {
+        let (cost, percentage, bar) = match function_profile.lines_inclusive.get(&line) {
+            Some(cost) => {
+                let percentage = match function_profile.inclusive {
+                    0 => 0.0,
+                    inclusive => *cost as f64 * 100.0 / inclusive as f64,
+                };
+                let bar_len = (*cost as usize * BAR_WIDTH).div_ceil(max_cost as usize);
+                (cost.to_string(), format!("{:.1}%", percentage), "#".repeat(bar_len))
+            }
+            None => (String::new(), String::new(), String::new()),
+        };
+        println!("{:>8} {:>6} {:<width$} {:>5} | {}", cost, percentage, bar, line, source_lines[line - 1], width = BAR_WIDTH);
+    }
+
+    Ok(())
+}
+
+/// One line per call stack: `outer;inner COST`
+fn write_folded(folded_path: &Path, profile: &Profile) -> Result<(), Box<dyn std::error::Error>> {
+    let mut output = fs::File::create(folded_path)?;
+    for (stack, cost) in &profile.folded {
+        if *cost > 0 {
+            writeln!(output, "{} {}", stack, cost)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use crate::commands::record::*;
+use crate::commands::var::*;
+use crate::utils::debugger_cache::*;
+use crate::utils::debugee_project_info::get_program_info;
+use crate::instrument::resolve_injection_point;
+
+/// Show the variables at a location from the last recording, without running the program
+pub(crate) fn process_query(var_location: VarLocation, variable_filter: VariableFilter) -> Result<(), Box<dyn std::error::Error>> {
+    if !get_cache_dir().is_dir() {
+        Err("Cache directory does not exist. Run 'init' to create it")?
+    }
+    let config: DebuggerConfig = DebuggerConfig::load_from_file(&get_config_path())?;
+    config.validate()?;
+
+    let debugee_project_info = get_program_info(&config.program_path)?;
+
+    // Only lines are recorded
+    match var_location {
+        VarLocation::Line { .. } | VarLocation::FnLine { .. } => {}
+        _ => Err("Returns and errors are not recorded. Use `var` for them")?,
+    }
+    let (file, point) = resolve_var_location(&debugee_project_info, &var_location)?;
+    let display_path = get_display_path(&debugee_project_info, &file);
+
+    // Hits don't have a column: each line is recorded at the point it resolves to without one, see `get_line_injection_points`
+    if let VarLocation::Line { column: Some(_), .. } = &var_location {
+        if resolve_injection_point(&parse_location_file(&file)?, point.line(), None) != Some(point) {
+            Err(format!("Only the first point of line {} is recorded. Use `var` for this column", point.line()))?
+        }
+    }
+
+    if !get_recording_path().is_file() {
+        Err("No recording. Run 'record' to create one")?
+    }
+    // The hits are logged with the display path, see `process_record`
+    let recording = Recording::load_line_from_file(&get_recording_path(), &display_path, point.line())
+        .map_err(|e| format!("Can't read the recording ({}). Run 'record' again", e))?;
+
+    let recorded_file = recording.files.iter()
+        .find(|recorded_file| recorded_file.path == file)
+        .ok_or(format!("{} was not recorded. Run `record {}`", display_path, display_path))?;
+    if get_content_hash(&file)? != recorded_file.content_hash {
+        eprintln!("{} changed since it was recorded. Run `record` again to update the recording", display_path);
+    }
+
+    check_variable_names(&debugee_project_info, &file, &point, &variable_filter)?;
+
+    let line_vars = recording.line_vars;
+    print_location_vars(&line_vars, &display_path, &point, &variable_filter);
+
+    Ok(())
+}
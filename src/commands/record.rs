@@ -0,0 +1,196 @@
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use solana_sdk::hash::hash;
+use crate::commands::run::inst_compile_run;
+use crate::commands::var::*;
+use crate::utils::debugger_cache::*;
+use crate::utils::debugee_project_info::get_program_info;
+use crate::instrument::*;
+use crate::output::*;
+
+/// The variables at every line of the recorded files, from one run
+///
+/// Stored as a single JSON file at `get_recording_path` that each `record` rewrites in full.
+/// `query` only keeps the hits at its line when reading it, see `load_line_from_file`
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Recording {
+    pub files: Vec<RecordedFile>,
+    /// All hits, in the order of execution
+    pub line_vars: Vec<LineVars>,
+    pub tx_error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RecordedFile {
+    pub path: PathBuf,
+    /// The file as logged with the hits: relative to the program, or to the workspace root for other packages. See `inst_source`
+    pub logged_path: String,
+    /// To detect changes after the recording, see `get_content_hash`
+    pub content_hash: String,
+}
+
+impl Recording {
+    pub fn write_to_file(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    /// Load the recording with only the hits at `line_num` of the file logged as `logged_path`.
+    /// The other hits are read one at a time and dropped, so a large recording isn't held in memory
+    pub fn load_line_from_file(path: &Path, logged_path: &str, line_num: usize) -> Result<Recording, Box<dyn std::error::Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        let recording = RecordingLineSeed(LineFilter { logged_path, line_num }).deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(recording)
+    }
+}
+
+/// The hits to keep when loading a recording
+#[derive(Clone, Copy)]
+struct LineFilter<'a> {
+    logged_path: &'a str,
+    line_num: usize,
+}
+
+impl<'de> DeserializeSeed<'de> for LineFilter<'_> {
+    type Value = Vec<LineVars>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for LineFilter<'_> {
+    type Value = Vec<LineVars>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of hits")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut line_vars = Vec::new();
+        while let Some(item) = seq.next_element::<LineVars>()? {
+            if item.file.as_deref() == Some(self.logged_path) && item.line_num == self.line_num {
+                line_vars.push(item);
+            }
+        }
+        Ok(line_vars)
+    }
+}
+
+/// A `Recording` with the hits filtered by `LineFilter`
+struct RecordingLineSeed<'a>(LineFilter<'a>);
+
+impl<'de> DeserializeSeed<'de> for RecordingLineSeed<'_> {
+    type Value = Recording;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RecordingLineSeed<'_> {
+    type Value = Recording;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a recording")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut files = None;
+        let mut line_vars = None;
+        let mut tx_error = None;
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "files" => files = Some(map.next_value()?),
+                "line_vars" => line_vars = Some(map.next_value_seed(self.0)?),
+                "tx_error" => tx_error = Some(map.next_value()?),
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(Recording {
+            files: files.ok_or_else(|| de::Error::missing_field("files"))?,
+            line_vars: line_vars.ok_or_else(|| de::Error::missing_field("line_vars"))?,
+            tx_error: tx_error.ok_or_else(|| de::Error::missing_field("tx_error"))?,
+        })
+    }
+}
+
+/// Run the program once and store the variables at every line of the files, so `query` can show them without running it again
+///
+/// Without files, all files of the program are recorded
+pub(crate) async fn process_record(files: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    if !get_cache_dir().is_dir() {
+        Err("Cache directory does not exist. Run 'init' to create it")?
+    }
+    let config: DebuggerConfig = DebuggerConfig::load_from_file(&get_config_path())?;
+    config.validate()?;
+
+    let debugee_project_info = get_program_info(&config.program_path)?;
+
+    let mut file_paths = Vec::new();
+    for file in &files {
+        file_paths.push(resolve_location_file(&debugee_project_info, file)?);
+    }
+    if file_paths.is_empty() {
+        file_paths = parse_source_files(&debugee_project_info.program_path.join("src"))?.into_iter().map(|(path, _)| path).collect();
+    }
+    file_paths.sort();
+    file_paths.dedup();
+
+    let inst_spec = InstProjectSpec::Record { files: file_paths.clone() };
+    let mut run_output = inst_compile_run(&debugee_project_info, inst_spec, CallStackInst::Off, std::slice::from_ref(&config.input_path), false).await?;
+    let inst_workspace_root = run_output.inst_workspace_root;
+    let program_output = run_output.outputs.remove(0);
+
+    let line_vars = parse_program_output(program_output.logs.clone())?;
+
+    let mut recorded_files = Vec::new();
+    for path in file_paths {
+        recorded_files.push(RecordedFile {
+            logged_path: get_display_path(&debugee_project_info, &path),
+            content_hash: get_content_hash(&path)?,
+            path,
+        });
+    }
+
+    println!();
+    println!("Recorded {} hits", line_vars.len());
+    for file in &recorded_files {
+        let hits = line_vars.iter().filter(|item| item.file.as_deref() == Some(file.logged_path.as_str())).count();
+        println!("  {}: {}", get_display_path(&debugee_project_info, &file.path), hits);
+    }
+
+    if let Some(mut panic) = find_program_panic(&program_output.logs) {
+        map_panic_to_original(&mut panic, &inst_workspace_root);
+        println!();
+        println!("Program panicked at {}:{}", panic.file, panic.line);
+    }
+    if let Some(tx_error) = &program_output.tx_error {
+        println!();
+        println!("Transaction failed: {}", tx_error);
+    }
+
+    let recording = Recording {
+        files: recorded_files,
+        line_vars,
+        tx_error: program_output.tx_error,
+    };
+    recording.write_to_file(&get_recording_path())?;
+
+    Ok(())
+}
+
+/// SHA-256 of the file, so it stays the same across builds of the debugger
+pub(crate) fn get_content_hash(file: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(hash(&fs::read(file)?).to_string())
+}
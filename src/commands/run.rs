@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+use crate::compile::project::{compile_project, CompileProjectArgs};
+use crate::utils::debugger_cache::*;
+use crate::utils::debugee_project_info::DebugeeProjectInfo;
+use crate::utils::program_input::*;
+use crate::instrument::*;
+use crate::output::*;
+
+/// The result of `inst_compile_run`
+pub(crate) struct RunOutput {
+    /// The root of the instrumented copy, e.g. to map panics back with `map_panic_to_original`
+    pub inst_workspace_root: PathBuf,
+    /// The output for each input, in the same order
+    pub outputs: Vec<ProgramOutput>,
+}
+
+/// Instrument the program, compile it and run it with each input
+///
+/// `call_stack`: where to log function entries and exits, see `CallStackInst`
+///
+/// `fetch_accounts`: also fetch the accounts of each transaction after it ran
+pub(crate) async fn inst_compile_run(
+    debugee_project_info: &DebugeeProjectInfo,
+    inst_spec: InstProjectSpec,
+    call_stack: CallStackInst,
+    input_paths: &[PathBuf],
+    fetch_accounts: bool,
+) -> Result<RunOutput, Box<dyn std::error::Error>> {
+
+    // Must be set before load_input_from_folder
+    let output_log = set_output_logger()?;
+
+    let mut program_inputs = Vec::new();
+    for input_path in input_paths {
+        program_inputs.push(load_input_from_folder(input_path).await?);
+    }
+    //dbg!(&program_inputs);
+
+    //
+    // Instrument
+    //
+
+    eprintln!("Instrument...");
+
+    let project_type = match debugee_project_info.is_workspace {
+        false => InstInputProjectType::Package { program_path: debugee_project_info.program_path.clone() },
+        true => InstInputProjectType::Workspace {
+            root_path: debugee_project_info.workspace_root.clone(),
+            program_path: debugee_project_info.program_path.clone(),
+        }
+    };
+
+    let inst_args = InstProjectArgs {
+        output_dir: get_build_dir(),
+        input_project: InstInputProject {
+            project_type,
+            target_dir: debugee_project_info.target_directory.clone(),
+        },
+        inst_spec,
+        call_stack,
+    };
+
+    let inst_info = inst_project(inst_args)?;
+    let inst_workspace_root = inst_info.workspace_root.clone();
+
+    //dbg!(&inst_info);
+
+    //
+    // Compile
+    //
+
+    //rm_target_dir();
+
+    eprintln!("Compile...");
+
+    let compile_args = CompileProjectArgs {
+        program_path: inst_info.program_path,
+        workspace_root: inst_info.workspace_root,
+        target_dir: Some(get_target_dir())
+    };
+
+    compile_project(compile_args).await?;
+
+    //
+    // Output
+    //
+
+    eprintln!("Output...");
+
+    let mut outputs = Vec::new();
+    for program_input in program_inputs {
+        // Each output only contains the logs of its own run
+        output_log.write().unwrap().clear();
+        let program_output = generate_program_output(
+            &get_target_so_dir(),
+            &debugee_project_info.target_name,
+            program_input,
+            output_log.clone(),
+            fetch_accounts,
+        ).await?;
+        //dbg!(&program_output);
+        outputs.push(program_output);
+    }
+
+    Ok(RunOutput { inst_workspace_root, outputs })
+}
@@ -0,0 +1,37 @@
+use crate::commands::var::*;
+use crate::instrument::InjectionPoint;
+use crate::utils::debugger_cache::*;
+use crate::utils::debugee_project_info::get_program_info;
+
+/// Show the variables that `var` can inspect at a location, without compiling the program
+pub(crate) fn process_scope(var_location: VarLocation) -> Result<(), Box<dyn std::error::Error>> {
+    if !get_cache_dir().is_dir() {
+        Err("Cache directory does not exist. Run 'init' to create it")?
+    }
+    let config: DebuggerConfig = DebuggerConfig::load_from_file(&get_config_path())?;
+    config.validate()?;
+
+    let debugee_project_info = get_program_info(&config.program_path)?;
+
+    let (file, point) = resolve_var_location(&debugee_project_info, &var_location)?;
+    let scope = get_location_scope(&debugee_project_info, &file, &point)?;
+
+    let location_suffix = match point {
+        InjectionPoint::FnReturn { .. } => " (return)",
+        _ => "",
+    };
+    println!("{}:{}{}", get_display_path(&debugee_project_info, &file), point.line(), location_suffix);
+    println!();
+
+    if scope.is_empty() {
+        println!("No variables in scope");
+    }
+    for variable in scope {
+        match variable.ty {
+            Some(ty) => println!("{}: {}", variable.name, ty),
+            None => println!("{}", variable.name),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::commands::step::print_node_change;
+use crate::commands::var::*;
+use crate::output::*;
+
+/// What `var` does with the variables besides showing them
+#[derive(Debug)]
+pub enum SnapshotAction {
+    None,
+    /// `--snapshot PATH`: write them to a JSON file
+    Write(PathBuf),
+    /// `--check PATH`: compare them with a file written by `--snapshot`. See `is_ignored_path` for the fields
+    ///
+    /// `diff_json`: where to write the differences as JSON, `-` for stdout
+    Check { path: PathBuf, ignored_fields: Vec<String>, diff_json: Option<PathBuf> },
+}
+
+/// The differences to a snapshot, written by `--diff-json`
+#[derive(Debug, Serialize)]
+struct SnapshotDiff {
+    expected_hits: usize,
+    actual_hits: usize,
+    /// Only the hits that differ
+    hits: Vec<HitDiff>,
+}
+
+#[derive(Debug, Serialize)]
+struct HitDiff {
+    /// Counted from 1, like in the output
+    hit: usize,
+    line: usize,
+    /// From the snapshot to the actual values
+    changes: Vec<NodeChange>,
+}
+
+impl SnapshotDiff {
+    fn difference_count(&self) -> usize {
+        let hit_count_differs = self.expected_hits != self.actual_hits;
+        hit_count_differs as usize + self.hits.iter().map(|hit| hit.changes.len()).sum::<usize>()
+    }
+}
+
+/// Write or check the snapshot of the hits, with only the selected variables
+pub(crate) fn process_snapshot(snapshot_action: &SnapshotAction, mut line_vars: Vec<LineVars>, variable_filter: &VariableFilter) -> Result<(), Box<dyn std::error::Error>> {
+    if let VariableFilter::Select(vars) = variable_filter {
+        for item in &mut line_vars {
+            item.nodes.retain(|node| vars.iter().any(|var| is_variable_name(&node.name, var)));
+        }
+    }
+    match snapshot_action {
+        SnapshotAction::None => {}
+        SnapshotAction::Write(path) => {
+            let writer = BufWriter::new(File::create(path)?);
+            serde_json::to_writer_pretty(writer, &line_vars)?;
+            eprintln!();
+            eprintln!("Wrote snapshot {}", path.display());
+        }
+        SnapshotAction::Check { path, ignored_fields, diff_json } => check_snapshot(path, &line_vars, ignored_fields, diff_json.as_deref())?,
+    }
+    Ok(())
+}
+
+/// Only the values are compared, so the snapshot still matches when the lines of the location move
+fn check_snapshot(path: &Path, line_vars: &[LineVars], ignored_fields: &[String], diff_json: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
+    let reader = BufReader::new(File::open(path).map_err(|e| format!("Can't open snapshot {}: {}", path.display(), e))?);
+    let expected: Vec<LineVars> = serde_json::from_reader(reader)?;
+    let diff = get_snapshot_diff(&expected, line_vars, ignored_fields);
+
+    let is_json_stdout = diff_json == Some(Path::new("-"));
+    if !is_json_stdout {
+        print_snapshot_diff(&diff);
+    }
+    match diff_json {
+        Some(_) if is_json_stdout => {
+            let mut stdout = std::io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &diff)?;
+            writeln!(stdout)?;
+        }
+        Some(diff_path) => {
+            let writer = BufWriter::new(File::create(diff_path)?);
+            serde_json::to_writer_pretty(writer, &diff)?;
+            eprintln!("Wrote differences {}", diff_path.display());
+        }
+        None => {}
+    }
+
+    match diff.difference_count() {
+        0 => {
+            if !is_json_stdout {
+                println!("Snapshot {} matches", path.display());
+            }
+            Ok(())
+        }
+        difference_count => Err(format!("{} differences to the snapshot {}", difference_count, path.display()).into()),
+    }
+}
+
+fn get_snapshot_diff(expected: &[LineVars], line_vars: &[LineVars], ignored_fields: &[String]) -> SnapshotDiff {
+    let mut hits = Vec::new();
+    for (i, (expected_item, item)) in expected.iter().zip(line_vars).enumerate() {
+        let mut changes = diff_debug_nodes(&expected_item.nodes, &item.nodes);
+        // `diff_debug_nodes` doesn't report variables that went out of scope. Here they are missing
+        for expected_node in &expected_item.nodes {
+            if !item.nodes.iter().any(|node| node.name == expected_node.name) {
+                changes.push(NodeChange::Removed { path: expected_node.name.clone(), node: expected_node.clone() });
+            }
+        }
+        changes.retain(|change| !is_ignored_path(change.path(), ignored_fields));
+        if !changes.is_empty() {
+            hits.push(HitDiff { hit: i + 1, line: item.line_num, changes });
+        }
+    }
+    SnapshotDiff { expected_hits: expected.len(), actual_hits: line_vars.len(), hits }
+}
+
+fn print_snapshot_diff(diff: &SnapshotDiff) {
+    println!();
+    if diff.expected_hits != diff.actual_hits {
+        println!("Expected {} hits, got {}", diff.expected_hits, diff.actual_hits);
+    }
+    for hit in &diff.hits {
+        println!("Hit {} (line {}), expected -> actual:", hit.hit, hit.line);
+        for change in &hit.changes {
+            print_node_change(change);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs;
+use colored::*;
+use crate::commands::run::inst_compile_run;
+use crate::commands::var::*;
+use crate::utils::debugger_cache::*;
+use crate::utils::debugee_project_info::get_program_info;
+use crate::instrument::*;
+use crate::output::*;
+
+/// Run the program and show each line of the function as it is executed, with the variables that changed since the line before
+///
+/// Every call of the function is shown. A call ends when the function returns, unless its return can't be instrumented (e.g. a `const fn`)
+pub(crate) async fn process_step(name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !get_cache_dir().is_dir() {
+        Err("Cache directory does not exist. Run 'init' to create it")?
+    }
+    let config: DebuggerConfig = DebuggerConfig::load_from_file(&get_config_path())?;
+    config.validate()?;
+
+    let debugee_project_info = get_program_info(&config.program_path)?;
+
+    let (file, fn_range) = resolve_fn(&debugee_project_info, name)?;
+    let location_ast = parse_location_file(&file)?;
+
+    let points = get_fn_points(&location_ast, &fn_range);
+    if points.is_empty() {
+        Err(format!("Function {} has no code to step through", fn_range.name))?
+    }
+
+    let inst_spec = InstProjectSpec::Points { file: file.clone(), points };
+    let mut run_output = inst_compile_run(&debugee_project_info, inst_spec, CallStackInst::Location, std::slice::from_ref(&config.input_path), false).await?;
+    let program_output = run_output.outputs.remove(0);
+
+    let line_vars = parse_program_output(program_output.logs.clone())?;
+
+    let source = fs::read_to_string(&file)?;
+    let source_lines: Vec<&str> = source.lines().collect();
+
+    println!();
+    println!("{} ({})", fn_range.name, get_display_path(&debugee_project_info, &file));
+    if line_vars.is_empty() {
+        println!();
+        println!("The function was never called");
+    }
+
+    for (i, call) in get_calls(&line_vars).into_iter().enumerate() {
+        println!();
+        println!("Call {}", i + 1);
+        let mut previous_nodes: &[DebugNode] = &[];
+        for item in call {
+            let source_line = source_lines.get(item.line_num - 1).map(|line| line.trim()).unwrap_or_default();
+            match is_return(item) {
+                true => println!("{:>6} | {} {}", item.line_num, source_line, "(return)".italic()),
+                false => println!("{:>6} | {}", item.line_num, source_line),
+            }
+            for change in diff_debug_nodes(previous_nodes, &item.nodes) {
+                print_node_change(&change);
+            }
+            previous_nodes = &item.nodes;
+        }
+    }
+
+    if let Some(tx_error) = &program_output.tx_error {
+        println!();
+        println!("Transaction failed: {}", tx_error);
+    }
+
+    Ok(())
+}
+
+/// The lines of the function without the functions nested in it, followed by its return if it can be instrumented
+pub(crate) fn get_fn_points(location_ast: &syn::File, fn_range: &FnRange) -> Vec<InjectionPoint> {
+    let nested_ranges: Vec<FnRange> = get_fn_ranges(location_ast).into_iter()
+        .filter(|other| other.start_line > fn_range.start_line && other.end_line <= fn_range.end_line)
+        .collect();
+    let mut points: Vec<InjectionPoint> = get_line_injection_points(location_ast).into_iter()
+        .filter(|point| fn_range.start_line <= point.line() && point.line() <= fn_range.end_line)
+        .filter(|point| !nested_ranges.iter().any(|nested| nested.start_line <= point.line() && point.line() <= nested.end_line))
+        .collect();
+    if points.is_empty() {
+        return points;
+    }
+    if let Ok(return_point) = resolve_fn_return_point(location_ast, fn_range.start_line) {
+        points.push(return_point);
+    }
+    points
+}
+
+/// Split the hits of a function into its calls. A call ends after the return, or when the hits are in another frame, e.g. of a recursive call
+pub(crate) fn get_calls(line_vars: &[LineVars]) -> Vec<&[LineVars]> {
+    let mut calls = Vec::new();
+    let mut start = 0;
+    for i in 1..line_vars.len() {
+        let previous = &line_vars[i - 1];
+        if is_return(previous) || previous.call_stack.len() != line_vars[i].call_stack.len() {
+            calls.push(&line_vars[start..i]);
+            start = i;
+        }
+    }
+    if start < line_vars.len() {
+        calls.push(&line_vars[start..]);
+    }
+    calls
+}
+
+/// The return is logged together with the return value, see `inst_fn_block`
+pub(crate) fn is_return(item: &LineVars) -> bool {
+    item.nodes.iter().any(|node| node.name == "(return value)")
+}
+
+pub(crate) fn print_node_change(change: &NodeChange) {
+    let indent = " ".repeat(9);
+    match change {
+        NodeChange::Added { path, node } if node.children.is_empty() => {
+            println!("{}{} {}: {} {}", indent, "+".green(), path.bold().bright_yellow(), node.value.bright_purple(), format!("({})", node.full_type).italic().cyan());
+        }
+        NodeChange::Added { path, node } => {
+            println!("{}{} {}: {}", indent, "+".green(), path.bold().bright_yellow(), format!("({})", node.full_type).italic().cyan());
+            for child in &node.children {
+                print_debug_node_colored(child, 6);
+            }
+        }
+        NodeChange::Removed { path, .. } => {
+            println!("{}{} {}", indent, "-".red(), path.bold().bright_yellow());
+        }
+        NodeChange::Changed { path, old_value, new_value } => {
+            println!("{}{} {}: {} -> {}", indent, "~".bright_blue(), path.bold().bright_yellow(), old_value.bright_purple(), new_value.bright_purple());
+        }
+    }
+}
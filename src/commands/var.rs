@@ -1,9 +1,16 @@
-use crate::compile::project::{compile_project, CompileProjectArgs};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use crate::commands::run::inst_compile_run;
+use crate::commands::snapshot::*;
 use crate::utils::debugger_cache::*;
-use crate::utils::debugee_project_info::get_program_info;
-use crate::utils::program_input::*;
+use crate::utils::debugee_project_info::{get_program_info, DebugeeProjectInfo};
 use crate::instrument::*;
 use crate::output::*;
+use crate::output::spl_token::get_token_state;
 
 #[derive(Debug)]
 pub enum VariableFilter {
@@ -11,7 +18,31 @@ pub enum VariableFilter {
     Select(Vec<String>)
 }
 
-pub(crate) async fn process_var(location: &str, line: usize, variable_filter: VariableFilter) -> Result<(), Box<dyn std::error::Error>> {
+/// Where to inspect variables
+///
+/// Files are relative to `src/` or the program (e.g. `lib.rs` or `src/lib.rs`), the current directory or the workspace. See `resolve_location_file`
+///
+/// Function names can be qualified by a type or a module path, e.g. `process`, `Processor::process` or `processor::process`
+#[derive(Debug)]
+pub enum VarLocation {
+    /// `FILE:LINE` or `FILE:LINE:COLUMN`
+    Line { file: String, line: usize, column: Option<usize> },
+    /// `NAME` or `NAME:+OFFSET`: the line `OFFSET` lines after the `fn` keyword of the function with this name
+    FnLine { name: String, offset: usize },
+    /// `FILE:LINE --on-return`: the return of the function that contains the line
+    FnReturnAtLine { file: String, line: usize },
+    /// `fn:NAME`: the return of the function with this name
+    FnReturn { name: String },
+    /// `--break-on-error`: where the first error is produced with `?` or `return Err(..)`
+    FirstError,
+}
+
+/// `call_stack`: where to log the call stack that is shown with the variables, see `CallStackInst`
+///
+/// `show_accounts`: show the accounts after the transaction, with the state of token accounts decoded
+///
+/// `snapshot_action`: write the variables to a snapshot or compare them with one, see `process_snapshot`
+pub(crate) async fn process_var(var_location: VarLocation, variable_filter: VariableFilter, call_stack: CallStackInst, show_accounts: bool, snapshot_action: SnapshotAction) -> Result<(), Box<dyn std::error::Error>> {
 
     //
     // Input Validation
@@ -24,129 +55,392 @@ pub(crate) async fn process_var(location: &str, line: usize, variable_filter: Va
     //dbg!(&config);
     config.validate()?;
 
-    // Validate location
-    let location_path = config.program_path.join(location);
-    if !location_path.is_file() {
-        Err(format!("Debug location {} does not exist", location_path.display()))?
-    }
-
-    // Must be set before load_input_from_folder
-    let output_log = set_output_logger()?;
-
-    let program_input = load_input_from_folder(&config.input_path).await?;
-    //dbg!(&program_input);
-
     let debugee_project_info = get_program_info(&config.program_path)?;
     //dbg!(&debugee_project_info);
 
-    //
-    // Instrument
-    //
-
-    eprintln!("Instrument...");
-
-    let project_type = match debugee_project_info.is_workspace {
-        false => InstInputProjectType::Package { program_path: debugee_project_info.program_path.clone() },
-        true => InstInputProjectType::Workspace {
-            root_path: debugee_project_info.workspace_root.clone(),
-            program_path: debugee_project_info.program_path.clone(),
-        }
+    // For a single location: the file and the place in it
+    let location = match &var_location {
+        VarLocation::FirstError => None,
+        _ => Some(resolve_var_location(&debugee_project_info, &var_location)?),
     };
 
-    let inst_args = InstProjectArgs {
-        output_dir: get_build_dir(),
-        input_project: InstInputProject {
-            project_type,
-            target_dir: debugee_project_info.target_directory.clone(),
-        },
-        inst_spec: InstProjectSpec::SingleLine { file: location_path, line },
-    };
-
-    let inst_info = inst_project(inst_args)?;
-
-    //dbg!(&inst_info);
-
-    //return Ok(());
+    // Catch typos before the long build
+    if let Some((file, point)) = &location {
+        check_variable_names(&debugee_project_info, file, point, &variable_filter)?;
+    }
 
-    //
-    // Compile
-    //
+    let inst_spec = match &location {
+        Some((file, point)) => InstProjectSpec::SingleLine { file: file.clone(), point: *point },
+        None => InstProjectSpec::BreakOnError,
+    };
+    let mut run_output = inst_compile_run(&debugee_project_info, inst_spec, call_stack, std::slice::from_ref(&config.input_path), show_accounts).await?;
+    let inst_workspace_root = run_output.inst_workspace_root;
+    let program_output = run_output.outputs.remove(0);
 
-    //rm_target_dir();
+    let line_vars = parse_program_output(program_output.logs.clone())?;
 
-    eprintln!("Compile...");
+    match &location {
+        Some((file, point)) => print_location_vars(&line_vars, &get_display_path(&debugee_project_info, file), point, &variable_filter),
+        None => print_first_error_vars(&line_vars, &variable_filter),
+    }
 
-    let compile_args = CompileProjectArgs {
-        program_path: inst_info.program_path,
-        workspace_root: inst_info.workspace_root,
-        target_dir: Some(get_target_dir())
-    };
+    if let Some(mut panic) = find_program_panic(&program_output.logs) {
+        map_panic_to_original(&mut panic, &inst_workspace_root);
+        print_program_panic(&panic, &line_vars);
+    }
 
-    compile_project(compile_args).await?;
+    if let Some(tx_error) = &program_output.tx_error {
+        println!();
+        println!("Transaction failed: {}", tx_error);
+    }
 
-    //
-    // Output
-    //
+    if let Some(accounts) = &program_output.accounts {
+        print_accounts(accounts);
+    }
 
-    eprintln!("Output...");
+    process_snapshot(&snapshot_action, line_vars, &variable_filter)?;
 
-    let program_output = generate_program_output(
-        &get_target_so_dir(),
-        &debugee_project_info.target_name,
-        program_input,
-        output_log
-    ).await?;
+    Ok(())
+}
 
-    //dbg!(&program_output);
+fn print_accounts(accounts: &[(Pubkey, Account)]) {
+    println!();
+    println!("Accounts after the transaction:");
+    for (pubkey, account) in accounts {
+        let owner = account.owner.to_string();
+        let mut children = vec![
+            new_account_field("lamports", "u64", account.lamports.to_string()),
+            new_account_field("owner", "Pubkey", owner.clone()),
+            new_account_field("data", "Vec<u8>", format!("len={}", account.data.len())),
+        ];
+        children.extend(get_token_state(&owner, &account.data));
+        let node = DebugNode {
+            node_type: DebugNodeType::Complex,
+            name: pubkey.to_string(),
+            full_type: "Account".to_string(),
+            value: String::new(),
+            children,
+        };
+        println!();
+        print_debug_node_colored(&node, 0);
+    }
+}
 
-    let line_vars = parse_program_output(program_output)?;
+fn new_account_field(name: &str, full_type: &str, value: String) -> DebugNode {
+    DebugNode {
+        node_type: DebugNodeType::Primitive,
+        name: name.to_string(),
+        full_type: full_type.to_string(),
+        value,
+        children: vec![],
+    }
+}
 
+pub(crate) fn print_location_vars(line_vars: &[LineVars], file: &str, point: &InjectionPoint, variable_filter: &VariableFilter) {
     if line_vars.is_empty() {
         eprintln!("No variables data (location was never hit)");
-        return Ok(());
+        return;
     }
 
+    let location_suffix = match point {
+        InjectionPoint::FnReturn { .. } => " (return)",
+        _ => "",
+    };
+
     println!();
     for (j, item) in line_vars.iter().enumerate() {
         if line_vars.len() > 1 {
-            println!("{}:{} ({})", location, item.line_num, j + 1);
+            println!("{}:{}{} ({})", file, item.line_num, location_suffix, j + 1);
             println!();
         } else {
-            println!("{}:{}", location, item.line_num);
+            println!("{}:{}{}", file, item.line_num, location_suffix);
             println!();
         }
 
-        match &variable_filter {
-            VariableFilter::All => {
-                for (i, node) in item.nodes.iter().enumerate() {
-                    print_debug_node_colored(node, 0);
-                    if i < item.nodes.len() - 1 {
-                        println!();
-                    }
+        print_call_stack(item);
+        print_vars(item, variable_filter);
+
+        if j < line_vars.len() - 1 {
+            println!();
+        }
+    }
+}
+
+fn print_program_panic(panic: &ProgramPanic, line_vars: &[LineVars]) {
+    println!();
+    match panic.is_original_line {
+        true => println!("Program panicked at {}:{}", panic.file, panic.line),
+        false => println!("Program panicked at {}:{} (location in the instrumented code)", panic.file, panic.line),
+    }
+    if !panic.message.is_empty() {
+        println!("{}", panic.message);
+    }
+    if let Some(last) = line_vars.last() {
+        let location = match &last.file {
+            Some(file) => format!("{}:{}", file, last.line_num),
+            None if line_vars.len() > 1 => format!("line {} ({})", last.line_num, line_vars.len()),
+            None => format!("line {}", last.line_num),
+        };
+        println!("The last state before the panic is the one at {} above", location);
+    }
+}
+
+/// The first hit is where the error was produced. The other hits are the `?`s it went through
+fn print_first_error_vars(line_vars: &[LineVars], variable_filter: &VariableFilter) {
+    let Some(first) = line_vars.first() else {
+        eprintln!("No error was produced with `?` or `return Err(..)`");
+        return;
+    };
+
+    println!();
+    println!("{}:{} (first error)", first.file.as_deref().unwrap_or_default(), first.line_num);
+    println!();
+    print_call_stack(first);
+    print_vars(first, variable_filter);
+
+    if line_vars.len() > 1 {
+        println!();
+        println!("Then passed through:");
+        for item in &line_vars[1..] {
+            println!("  {}:{}", item.file.as_deref().unwrap_or_default(), item.line_num);
+        }
+    }
+}
+
+/// The innermost function first, like a backtrace
+pub(crate) fn print_call_stack(item: &LineVars) {
+    if item.call_stack.is_empty() {
+        return;
+    }
+    match item.cpi_depth {
+        1 => println!("Call stack:"),
+        cpi_depth => println!("Call stack (CPI depth {}):", cpi_depth),
+    }
+    for frame in item.call_stack.iter().rev() {
+        match frame.line {
+            Some(line) => println!("  {} ({}:{})", frame.function, frame.file, line),
+            None => println!("  {} ({})", frame.function, frame.file),
+        }
+        for arg in &frame.args {
+            print_debug_node_colored(arg, 2);
+        }
+    }
+    println!();
+}
+
+fn print_vars(item: &LineVars, variable_filter: &VariableFilter) {
+    match variable_filter {
+        VariableFilter::All => {
+            for (i, node) in item.nodes.iter().enumerate() {
+                print_debug_node_colored(node, 0);
+                if i < item.nodes.len() - 1 {
+                    println!();
                 }
             }
-            VariableFilter::Select(vars) => {
-                let vars_len = vars.len();
-                for (i, var) in vars.iter().enumerate() {
-                    match item.nodes.iter().find(|&n| *var == n.name) {
-                        Some(node) => {
-                            print_debug_node_colored(node, 0);
-                        }
-                        None => {
-                            println!("Variable {} not available", var);
-                        }
+        }
+        VariableFilter::Select(vars) => {
+            let vars_len = vars.len();
+            for (i, var) in vars.iter().enumerate() {
+                match item.nodes.iter().find(|&n| is_variable_name(&n.name, var)) {
+                    Some(node) => {
+                        print_debug_node_colored(node, 0);
                     }
-                    if i < vars_len - 1 {
-                        println!();
+                    None => {
+                        let names = item.nodes.iter().map(|node| node.name.as_str());
+                        println!("Variable {} not available{}", var, get_suggestion(var, names));
                     }
                 }
+                if i < vars_len - 1 {
+                    println!();
+                }
             }
         }
+    }
+}
 
-        if j < line_vars.len() - 1 {
-            println!();
+/// Check that the selected variables are in scope at the point
+pub(crate) fn check_variable_names(debugee_project_info: &DebugeeProjectInfo, file: &Path, point: &InjectionPoint, variable_filter: &VariableFilter) -> Result<(), Box<dyn std::error::Error>> {
+    let VariableFilter::Select(vars) = variable_filter else {
+        return Ok(());
+    };
+    let scope = get_location_scope(debugee_project_info, file, point)?;
+    for var in vars.iter().filter(|var| !var.starts_with('(')) {
+        if !scope.iter().any(|variable| is_variable_name(&variable.name, var)) {
+            let names = scope.iter().map(|variable| variable.name.as_str());
+            Err(format!("Variable {} is not in scope at {}:{}{}", var, get_display_path(debugee_project_info, file), point.line(), get_suggestion(var, names)))?
         }
     }
-
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Get the file and the place in it to instrument
+pub(crate) fn resolve_var_location(project_info: &DebugeeProjectInfo, var_location: &VarLocation) -> Result<(PathBuf, InjectionPoint), Box<dyn std::error::Error>> {
+    match var_location {
+        VarLocation::Line { file: file_str, line, column } => {
+            let file = resolve_location_file(project_info, file_str)?;
+            let location_ast = parse_location_file(&file)?;
+            let point = resolve_injection_point(&location_ast, *line, *column)
+                .ok_or(format!("No code to inspect at {}:{} (the line must be inside a function body)", file_str, line))?;
+            // Refuse instead of silently using another line, so the build isn't wasted on an unexpected location
+            if point.line() != *line {
+                Err(format!("Line {} can't be instrumented, the next line that can is {}. Run `list {}:{}` to see all", line, point.line(), file_str, line))?
+            }
+            Ok((file, point))
+        }
+        VarLocation::FnLine { name, offset } => {
+            let found = find_in_packages(project_info, |file, module_path| find_fn_lines(file, module_path, name, *offset))?;
+            let (file, line) = get_single_fn(project_info, found, name, |line| *line.as_ref().unwrap_or(&0))?;
+            let line = line?;
+            let point = resolve_injection_point(&parse_location_file(&file)?, line, None)
+                .ok_or(format!("Function {} has no code to inspect", name))?;
+            // Without an offset, the opening brace is resolved to the first statement. With one, refuse like for `FILE:LINE`
+            if *offset > 0 && point.line() != line {
+                let display_path = get_display_path(project_info, &file);
+                Err(format!("Line {} of {} ({}:{}) can't be instrumented, the next line that can is {}. Run `list {}:{}` to see all", offset, name, display_path, line, point.line(), display_path, line))?
+            }
+            Ok((file, point))
+        }
+        VarLocation::FnReturnAtLine { file, line } => {
+            let file = resolve_location_file(project_info, file)?;
+            let location_ast = parse_location_file(&file)?;
+            let point = resolve_fn_return_point(&location_ast, *line)?;
+            Ok((file, point))
+        }
+        VarLocation::FirstError => Err("No single location to resolve".into()),
+        VarLocation::FnReturn { name } => {
+            let found = find_in_packages(project_info, |file, module_path| find_fn_return_points(file, module_path, name))?;
+            let (file, point) = get_single_fn(project_info, found, name, |point| point.as_ref().map_or(0, |point| point.line()))?;
+            Ok((file, point?))
+        }
+    }
+}
+
+/// Find the function with this name in the program or the other workspace packages
+pub(crate) fn resolve_fn(project_info: &DebugeeProjectInfo, name: &str) -> Result<(PathBuf, FnRange), Box<dyn std::error::Error>> {
+    let found = find_in_packages(project_info, |file, module_path| find_fn_ranges(file, module_path, name))?;
+    get_single_fn(project_info, found, name, |fn_range| fn_range.start_line)
+}
+
+/// Find the file of a `FILE:LINE` location. It must be in the `src` folder of the program or of another workspace package
+pub(crate) fn resolve_location_file(project_info: &DebugeeProjectInfo, file: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let program_path = &project_info.program_path;
+    // `lib.rs` is short for `src/lib.rs`
+    let candidates = [
+        program_path.join("src").join(file),
+        program_path.join(file),
+        PathBuf::from(file),
+        project_info.workspace_root.join(file),
+    ];
+    let path = candidates.iter()
+        .find(|path| path.is_file())
+        .ok_or(format!("Debug location {} does not exist", file))?
+        .canonicalize()?;
+
+    let is_source_file = project_info.member_paths.iter()
+        .chain([program_path])
+        .any(|package_path| path.starts_with(package_path.join("src")));
+    if !is_source_file {
+        Err(format!("{} is not in the `src` folder of the program or of a workspace package", path.display()))?
+    }
+    Ok(path)
+}
+
+/// Search the source files of the program. If nothing is found, search the other workspace packages
+///
+/// `find` gets each file and its module path, see `get_module_path`
+fn find_in_packages<T>(project_info: &DebugeeProjectInfo, find: impl Fn(&syn::File, &str) -> Vec<T>) -> Result<Vec<(PathBuf, T)>, Box<dyn std::error::Error>> {
+    let other_packages = project_info.member_paths.iter().filter(|&path| *path != project_info.program_path);
+    for package_path in [&project_info.program_path].into_iter().chain(other_packages) {
+        let source_path = package_path.join("src");
+        if !source_path.is_dir() {
+            continue;
+        }
+        let mut found = Vec::new();
+        for (path, file) in parse_source_files(&source_path)? {
+            let module_path = get_module_path(&source_path, &path);
+            found.extend(find(&file, &module_path).into_iter().map(|item| (path.clone(), item)));
+        }
+        if !found.is_empty() {
+            return Ok(found);
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// A function name must match exactly one function
+fn get_single_fn<T>(project_info: &DebugeeProjectInfo, mut found: Vec<(PathBuf, T)>, name: &str, get_line: impl Fn(&T) -> usize) -> Result<(PathBuf, T), Box<dyn std::error::Error>> {
+    match found.len() {
+        0 => Err(format!("Function {} not found", name).into()),
+        1 => Ok(found.remove(0)),
+        _ => {
+            let locations: Vec<String> = found.iter().map(|(file, item)| format!("{}:{}", get_display_path(project_info, file), get_line(item))).collect();
+            Err(format!("Function {} is ambiguous, found at {}. Use a longer path like `Type::{}` or `module::{}`, or FILE:LINE", name, locations.join(", "), name, name).into())
+        }
+    }
+}
+
+/// E.g. `src/lib.rs` for the program or `crates/utils/src/lib.rs` for another workspace package
+pub(crate) fn get_display_path(project_info: &DebugeeProjectInfo, file: &Path) -> String {
+    file.strip_prefix(&project_info.program_path)
+        .or_else(|_| file.strip_prefix(&project_info.workspace_root))
+        .unwrap_or(file)
+        .display()
+        .to_string()
+}
+
+/// Get the variables in scope at the location, see `get_scope`
+pub(crate) fn get_location_scope(project_info: &DebugeeProjectInfo, file: &Path, point: &InjectionPoint) -> Result<Vec<ScopeVariable>, Box<dyn std::error::Error>> {
+    let pat_constants = get_package_pat_constants(project_info, file)?;
+    let scope = get_scope(&parse_location_file(file)?, *point, pat_constants)
+        .ok_or(format!("No code to inspect at {}:{}", get_display_path(project_info, file), point.line()))?;
+    Ok(scope)
+}
+
+/// The constants of the package of the file. They are needed to tell bindings from constants in patterns, see `get_pat_constants`
+pub(crate) fn get_package_pat_constants(project_info: &DebugeeProjectInfo, file: &Path) -> Result<Rc<HashSet<String>>, Box<dyn std::error::Error>> {
+    let package_path = project_info.member_paths.iter()
+        .find(|package_path| file.starts_with(package_path.join("src")))
+        .unwrap_or(&project_info.program_path);
+    let source_files: Vec<syn::File> = parse_source_files(&package_path.join("src"))?.into_iter().map(|(_, file)| file).collect();
+    Ok(Rc::new(get_pat_constants(&source_files)))
+}
+
+/// E.g. ` (did you mean amount?)` for `amout`. Empty if no name is similar
+pub(crate) fn get_suggestion<'a>(var: &str, names: impl Iterator<Item = &'a str>) -> String {
+    // Shadowing variables are named like `amount (line 41)`
+    let mut similar: Vec<(usize, &str)> = names
+        .map(|name| name.split(" (line ").next().unwrap_or(name))
+        .map(|name| (get_edit_distance(var, name), name))
+        .filter(|(distance, _)| *distance <= (var.chars().count() / 3).max(1))
+        .collect();
+    similar.sort();
+    similar.dedup_by_key(|(_, name)| *name);
+    match similar.is_empty() {
+        true => String::new(),
+        false => format!(" (did you mean {}?)", similar.iter().map(|(_, name)| *name).collect::<Vec<_>>().join(" or ")),
+    }
+}
+
+/// Levenshtein distance
+fn get_edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+pub(crate) fn parse_location_file(file: &Path) -> Result<syn::File, Box<dyn std::error::Error>> {
+    Ok(syn::parse_file(&fs::read_to_string(file)?)?)
+}
+
+/// Variables that shadow an earlier one are named like `amount (line 41)`
+pub(crate) fn is_variable_name(node_name: &str, var: &str) -> bool {
+    node_name == var || node_name.strip_prefix(var).is_some_and(|rest| rest.starts_with(" (line "))
+}
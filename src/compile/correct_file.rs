@@ -4,10 +4,12 @@ use std::io::Write;
 use std::path::Path;
 use proc_macro2::TokenStream;
 use quote::quote;
-use syn::{Expr, ExprPath, Item, Stmt};
+use syn::{parse_quote, Expr, Item, Stmt, Token};
+use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 use syn::visit_mut::VisitMut;
 use crate::compile::sbf_with_errors::CompileError;
+use crate::instrument::{map_to_original_line, write_source_map};
 
 struct CorrectContext {
     errors: Vec<CompileError>,
@@ -30,7 +32,9 @@ pub fn correct_file(path: &Path, errors: Vec<CompileError>) -> Result<(), Box<dy
 
     if !ctx.errors.is_empty() {
         eprintln!("Some errors were not corrected:");
-        dbg!(&ctx.errors);
+        for err in &ctx.errors {
+            eprintln!("{}", format_compile_error(err, path));
+        }
         Err("Unrecoverable compile error")?
     }
 
@@ -38,59 +42,101 @@ pub fn correct_file(path: &Path, errors: Vec<CompileError>) -> Result<(), Box<dy
     //eprintln!("Write file: {}", path.display());
     let mut output_file = File::create(path)?;
     output_file.write_all(output.as_bytes())?;
+    write_source_map(&ast, &input, &output, path, true)?;
 
     Ok(())
 }
 
+/// E.g. `src/lib.rs:17: error[E0308]: mismatched types`, with the line of the original file if possible
+fn format_compile_error(err: &CompileError, path: &Path) -> String {
+    let location = match map_to_original_line(path, err.line) {
+        Some(line) => format!("{}:{}", err.file_path, line),
+        None => format!("{}:{} (location in the instrumented code)", err.file_path, err.line),
+    };
+    format!("{}: error[{}]: {}", location, err.error_code, err.error_message)
+}
+
 impl VisitMut for CorrectContext {
     fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
-        if let Stmt::Expr(expr, _) = stmt {
-            if is_solana_debugger_serialize_call(expr) {
-                //dbg!(&expr);
-                let stmt_span = stmt.span();
-                let mut err_cov = vec![];
-                let mut err_uncov = vec![];
-                for err in self.errors.clone() {
-                    let cov_source_span = err.source_spans.iter().find(|source_span|
-                        stmt_span.byte_range().contains(&source_span.start) &&
-                        stmt_span.byte_range().contains(&source_span.end)
-                    );
-                    if cov_source_span.is_some() {
-                        err_cov.push(err);
-                    } else {
-                        err_uncov.push(err);
-                    }
+        if is_solana_debugger_serialize_call(stmt) {
+            //dbg!(&stmt);
+            let stmt_span = stmt.span();
+            let mut err_cov = vec![];
+            let mut err_uncov = vec![];
+            for err in self.errors.clone() {
+                let cov_source_span = err.source_spans.iter().find(|source_span|
+                    stmt_span.byte_range().contains(&source_span.start) &&
+                    stmt_span.byte_range().contains(&source_span.end)
+                );
+                if cov_source_span.is_some() {
+                    err_cov.push(err);
+                } else {
+                    err_uncov.push(err);
                 }
+            }
 
-                if !err_cov.is_empty() {
-                    /*
-                    eprintln!("Remove serialize statement at line {}", stmt_span.start().line);
-                    eprintln!("{}", quote!(#stmt));
-                    for err in err_cov {
-                        eprintln!("{}", err.error_message);
-                    }
-                     */
-                    *stmt = Stmt::Item(Item::Verbatim(TokenStream::new()));
+            if !err_cov.is_empty() {
+                /*
+                eprintln!("Remove serialize statement at line {}", stmt_span.start().line);
+                eprintln!("{}", quote!(#stmt));
+                for err in err_cov {
+                    eprintln!("{}", err.error_message);
                 }
-                self.errors = err_uncov
+                 */
+                *stmt = get_unavailable_stmt(stmt, &err_cov[0].error_message);
             }
+            self.errors = err_uncov
         }
         syn::visit_mut::visit_stmt_mut(self, stmt);
     }
 }
 
-fn is_solana_debugger_serialize_call(expr: &Expr) -> bool {
-    let serialize_path: ExprPath = syn::parse2::<ExprPath>(
-        quote!(crate::_solana_debugger_serialize::_SolanaDebuggerSerialize::_solana_debugger_serialize)
+/// Log that the value is not available instead, so it doesn't silently disappear from the output, e.g. a parameter that the function moved
+fn get_unavailable_stmt(stmt: &Stmt, reason: &str) -> Stmt {
+    let (attrs, mac) = match stmt {
+        Stmt::Macro(stmt_macro) => (&stmt_macro.attrs, &stmt_macro.mac),
+        Stmt::Expr(Expr::Macro(expr_macro), _) => (&expr_macro.attrs, &expr_macro.mac),
+        _ => return Stmt::Item(Item::Verbatim(TokenStream::new())),
+    };
+    // The arguments are the reference to the value and its name
+    let name = mac.parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated).ok()
+        .and_then(|args| args.into_iter().nth(1));
+    match name {
+        Some(name) => parse_quote! {
+            #(#attrs)*
+            crate::_solana_debugger_serialize::_solana_debugger_serialize_unavailable(#name, #reason);
+        },
+        None => Stmt::Item(Item::Verbatim(TokenStream::new())),
+    }
+}
+
+fn is_solana_debugger_serialize_call(stmt: &Stmt) -> bool {
+    let serialize_path: syn::Path = syn::parse2::<syn::Path>(
+        quote!(crate::_solana_debugger_serialize::_solana_debugger_serialize_value)
     ).unwrap();
 
-    match expr {
-        Expr::Call(call) => {
-            match &*call.func {
-                Expr::Path(path) if path.eq(&serialize_path) => true,
-                _ => false
-            }
-        }
+    match stmt {
+        Stmt::Macro(stmt_macro) => stmt_macro.mac.path == serialize_path,
+        Stmt::Expr(Expr::Macro(expr_macro), _) => expr_macro.mac.path == serialize_path,
         _ => false
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unavailable_stmt() {
+        let stmt: Stmt = parse_quote! {
+            #[cfg(feature = "x")]
+            crate::_solana_debugger_serialize::_solana_debugger_serialize_value!(&v, "v");
+        };
+        assert!(is_solana_debugger_serialize_call(&stmt));
+        let unavailable_stmt = get_unavailable_stmt(&stmt, "borrow of moved value: `v`");
+        assert_eq!(
+            quote!(#unavailable_stmt).to_string(),
+            "# [cfg (feature = \"x\")] crate :: _solana_debugger_serialize :: _solana_debugger_serialize_unavailable (\"v\" , \"borrow of moved value: `v`\") ;"
+        );
+    }
+}
@@ -7,10 +7,10 @@ use cargo_metadata::Message;
 #[derive(Debug, Clone)]
 pub struct CompileError {
     pub file_path: String,
-    #[allow(dead_code)]
+    /// Line of the primary span
+    pub line: usize,
     pub error_code: String,
     pub source_spans: Vec<Range<usize>>,
-    #[allow(dead_code)]
     pub error_message: String,
 }
 
@@ -78,11 +78,14 @@ pub async fn compile_sbf_with_errors(program_path: &Path, target_dir: Option<&Pa
                 }
                 let prim_span = msg.spans.iter().find(|x| x.is_primary).ok_or("No primary span found")?;
                 let file_path = prim_span.file_name.clone();
+                let line = prim_span.line_start;
                 let source_spans = msg.spans.iter().map(|x| x.byte_start as usize..x.byte_end as usize).collect::<Vec<_>>();
-                let error_message = msg.rendered.unwrap_or("N/A".into()).trim().to_string();
+                // Not `msg.rendered`: it contains the location in the instrumented code
+                let error_message = msg.message.trim().to_string();
 
                 errs.push(CompileError {
                     file_path,
+                    line,
                     error_code,
                     source_spans,
                     error_message
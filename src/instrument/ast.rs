@@ -1,19 +1,57 @@
+use std::collections::HashSet;
+use std::rc::Rc;
 use syn::{parse_quote, File, Item};
-use crate::instrument::{inst_ast_general, inst_ast_custom_types};
+use crate::instrument::{inst_ast_general, inst_ast_points, inst_ast_errors, inst_ast_call_stack, inst_ast_coverage, inst_ast_profile, inst_ast_custom_types, inst_ast_line_macros, CallStackInst, CrateFns, InjectionPoint};
+
+/// What is logged in a file
+#[derive(Debug, Clone)]
+pub enum InstMode {
+    /// Nothing, e.g. the file doesn't have the location. Its types can still be serialized
+    None,
+    /// The variables at one point, see `inst_ast_general`
+    Line(InjectionPoint),
+    /// The variables at many points, e.g. every line of the file, see `get_line_injection_points`
+    Points(Vec<InjectionPoint>),
+    /// The variables at every `?` and `return Err(..)`, see `inst_ast_errors`
+    Errors,
+    /// A hit for each executed line, see `inst_ast_coverage`
+    Coverage,
+    /// The remaining compute units, see `inst_ast_profile`
+    Profile,
+}
 
 #[derive(Debug)]
 pub struct InstAstSpec {
     pub mod_fixed_serialization: bool,
     pub feature_min_specialization: bool,
-    #[allow(dead_code)]
+    /// Relative to the program path, e.g. `src/lib.rs`
     pub debugee_file_path: String,
-    pub line_inst: Option<usize>,
+    pub mode: InstMode,
+    pub call_stack: CallStackInst,
+    /// Functions of the crate, see `get_crate_fns`
+    pub crate_fns: Rc<CrateFns>,
+    /// Unit variants and constants of the crate, see `get_pat_constants`
+    pub pat_constants: Rc<HashSet<String>>,
     pub custom_type_serialization: bool
 }
 
 pub fn inst_ast(mut input: File, spec: &InstAstSpec) -> File {
-    if let Some(line) = &spec.line_inst {
-        input = inst_ast_general(input, *line);
+    input = inst_ast_line_macros(input);
+    match &spec.mode {
+        InstMode::None => {}
+        InstMode::Line(point) => input = inst_ast_general(input, *point, spec.pat_constants.clone()),
+        InstMode::Points(points) => input = inst_ast_points(input, points.clone(), &spec.debugee_file_path, spec.pat_constants.clone()),
+        InstMode::Errors => input = inst_ast_errors(input, &spec.debugee_file_path, spec.pat_constants.clone()),
+        InstMode::Coverage => input = inst_ast_coverage(input, &spec.debugee_file_path),
+        InstMode::Profile => input = inst_ast_profile(input, &spec.debugee_file_path),
+    }
+    let call_stack_args = match spec.call_stack {
+        CallStackInst::Location if matches!(spec.mode, InstMode::Line(_) | InstMode::Points(_)) => Some(false),
+        CallStackInst::All { args } => Some(args),
+        _ => None,
+    };
+    if let Some(call_stack_args) = call_stack_args {
+        input = inst_ast_call_stack(input, &spec.debugee_file_path, call_stack_args, spec.crate_fns.clone(), spec.pat_constants.clone());
     }
     if spec.custom_type_serialization {
         input = inst_ast_custom_types(input);
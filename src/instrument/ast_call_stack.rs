@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+use proc_macro2::{Delimiter, Span, TokenStream, TokenTree};
+use syn::fold::Fold;
+use syn::visit::Visit;
+use syn::*;
+use crate::instrument::ast_fn_body::*;
+use crate::instrument::get_bindings_from_fn_sig;
+
+#[derive(Clone, Debug)]
+struct InstContext {
+    /// Logged with the function names, e.g. `src/processor.rs`
+    file_path: String,
+    /// Log the arguments when a function is entered
+    log_args: bool,
+    /// See `get_crate_fns`
+    crate_fns: Rc<CrateFns>,
+    /// See `get_pat_constants`
+    pat_constants: Rc<HashSet<String>>,
+}
+
+/// Log when a function is entered and left, and the line of every statement that calls a function of the crate
+///
+/// This makes it possible to get the call stack at each instrumented location, see `parse_program_output`
+pub fn inst_ast_call_stack(file: File, file_path: &str, log_args: bool, crate_fns: Rc<CrateFns>, pat_constants: Rc<HashSet<String>>) -> File {
+    let mut fold = FnBodyFold::new(InstContext {
+        file_path: file_path.to_string(),
+        log_args,
+        crate_fns,
+        pat_constants,
+    });
+    fold.fold_file(file)
+}
+
+impl FnBodyInst for InstContext {
+    // A line can have several calls in separate statements
+    const ONCE_PER_LINE: bool = false;
+
+    /// The statement that logs the line of a call, or `None` if `code` doesn't call a function of the crate
+    fn get_line_stmt(&mut self, _line: usize, code: TokenStream) -> Option<Stmt> {
+        let line = get_call_line(code, &self.crate_fns)?;
+        let call_line_str = format!("-.!;AT;{}", line);
+        Some(parse_quote! {
+            solana_program::log::sol_log(#call_line_str);
+        })
+    }
+
+    /// Put the frame guard in front of the body. It logs when the function is left, see `_SolanaDebuggerFrame`
+    fn inst_fn_block(&mut self, mut block: Block, sig: &Signature, name: &str) -> Block {
+        let enter_str = format!("-.!;FN_ENTER;{};{}", name, self.file_path);
+        let mut stmts: Vec<Stmt> = vec![parse_quote! {
+            let _solana_debugger_frame = crate::_solana_debugger_serialize::_SolanaDebuggerFrame::enter(#enter_str);
+        }];
+        if self.log_args {
+            for (mut ident, _) in get_bindings_from_fn_sig(sig, &self.pat_constants) {
+                let ident_str = ident.to_string();
+                ident.set_span(Span::call_site());
+                stmts.push(parse_quote! {
+                    crate::_solana_debugger_serialize::_solana_debugger_serialize_value!(&#ident, #ident_str);
+                });
+            }
+        }
+        stmts.push(parse_quote! {
+            solana_program::log::sol_log("-.!;FN_ENTER_END");
+        });
+        stmts.extend(block.stmts);
+        block.stmts = stmts;
+        block
+    }
+
+    /// These are called while the variables are logged
+    fn is_skipped_impl(&self, node: &ItemImpl) -> bool {
+        node.trait_.as_ref()
+            .and_then(|(_, path, _)| path.segments.last())
+            .is_some_and(|segment| segment.ident == "Debug" || segment.ident == "Display")
+    }
+}
+
+/// The line of the first call of a function of the crate, e.g. `helper(..)`, `utils::helper(..)`, `Processor::process(..)` or `x.helper(..)`
+///
+/// Calls of other crates with the same name, like `Vec::new()` when the crate has a `fn new`, don't count. Types are not known,
+/// so a method call counts if the crate has a method with that name
+fn get_call_line(tokens: TokenStream, crate_fns: &CrateFns) -> Option<usize> {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            TokenTree::Ident(ident) => {
+                let is_call = matches!(tokens.get(i + 1), Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis);
+                if is_call && crate_fns.is_crate_call(&tokens[..i], &ident.to_string()) {
+                    return Some(ident.span().start().line);
+                }
+            }
+            TokenTree::Group(group) => {
+                if let Some(line) = get_call_line(group.stream(), crate_fns) {
+                    return Some(line);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The functions of the crate, i.e. the calls that get a frame in the call stack. See `get_call_line`
+#[derive(Debug, Default)]
+pub struct CrateFns {
+    /// Functions outside of impls and traits, e.g. `helper`
+    free_fns: HashSet<String>,
+    /// Functions of impls and traits by the name of the type or trait, e.g. `("Processor", "process")`
+    assoc_fns: HashSet<(String, String)>,
+    /// Functions with `self` of inherent impls and of the crate's traits. Methods of other traits, e.g. `fmt`, are not included
+    methods: HashSet<String>,
+    /// E.g. `utils` for `utils::helper(..)`
+    modules: HashSet<String>,
+}
+
+impl CrateFns {
+    /// Whether the call of `name` after the tokens `before` resolves to a function of the crate
+    fn is_crate_call(&self, before: &[TokenTree], name: &str) -> bool {
+        let is_punct = |index: usize, ch: char| matches!(before.get(index), Some(TokenTree::Punct(punct)) if punct.as_char() == ch);
+        let len = before.len();
+        if len >= 1 && is_punct(len - 1, '.') {
+            return self.methods.contains(name);
+        }
+        if len >= 2 && is_punct(len - 1, ':') && is_punct(len - 2, ':') {
+            // The segment before, e.g. `Processor` in `Processor::process(..)`. Generic types like `A::<T>::f(..)` don't count
+            let Some(TokenTree::Ident(qualifier)) = len.checked_sub(3).and_then(|index| before.get(index)) else {
+                return false;
+            };
+            let qualifier = qualifier.to_string();
+            return match qualifier.as_str() {
+                // The impl is in the crate
+                "Self" => self.assoc_fns.iter().any(|(_, assoc_fn)| assoc_fn == name),
+                "crate" | "self" | "super" => self.free_fns.contains(name),
+                _ if self.modules.contains(&qualifier) => self.free_fns.contains(name),
+                _ => self.assoc_fns.contains(&(qualifier, name.to_string())),
+            };
+        }
+        self.free_fns.contains(name)
+    }
+}
+
+/// Collect the functions of the crate, see `CrateFns`
+pub fn get_crate_fns(files: &[File]) -> CrateFns {
+    let mut collector = CrateFnsCollector::default();
+    for file in files {
+        collector.visit_file(file);
+    }
+    let mut crate_fns = collector.crate_fns;
+    // Traits can be implemented before they are declared
+    for (trait_name, method) in collector.trait_impl_methods {
+        if collector.traits.contains(&trait_name) {
+            crate_fns.methods.insert(method);
+        }
+    }
+    crate_fns
+}
+
+#[derive(Default)]
+struct CrateFnsCollector {
+    crate_fns: CrateFns,
+    traits: HashSet<String>,
+    /// Methods of trait impls by trait, they count if the trait is the crate's
+    trait_impl_methods: Vec<(String, String)>,
+}
+
+impl<'ast> Visit<'ast> for CrateFnsCollector {
+    fn visit_item_fn(&mut self, node: &'ast ItemFn) {
+        self.crate_fns.free_fns.insert(node.sig.ident.to_string());
+        syn::visit::visit_item_fn(self, node);
+    }
+
+    fn visit_item_mod(&mut self, node: &'ast ItemMod) {
+        self.crate_fns.modules.insert(node.ident.to_string());
+        syn::visit::visit_item_mod(self, node);
+    }
+
+    fn visit_item_impl(&mut self, node: &'ast ItemImpl) {
+        let get_last_ident = |path: &Path| path.segments.last().map(|segment| segment.ident.to_string());
+        let type_name = match &*node.self_ty {
+            Type::Path(type_path) => get_last_ident(&type_path.path),
+            _ => None,
+        };
+        let trait_name = node.trait_.as_ref().and_then(|(_, path, _)| get_last_ident(path));
+        for item in &node.items {
+            let ImplItem::Fn(impl_fn) = item else {
+                continue;
+            };
+            let name = impl_fn.sig.ident.to_string();
+            if let Some(type_name) = &type_name {
+                self.crate_fns.assoc_fns.insert((type_name.clone(), name.clone()));
+            }
+            if impl_fn.sig.receiver().is_some() {
+                match &trait_name {
+                    Some(trait_name) => self.trait_impl_methods.push((trait_name.clone(), name)),
+                    None => {
+                        self.crate_fns.methods.insert(name);
+                    }
+                }
+            }
+        }
+        syn::visit::visit_item_impl(self, node);
+    }
+
+    fn visit_item_trait(&mut self, node: &'ast ItemTrait) {
+        let trait_name = node.ident.to_string();
+        for item in &node.items {
+            if let TraitItem::Fn(trait_fn) = item {
+                let name = trait_fn.sig.ident.to_string();
+                self.crate_fns.assoc_fns.insert((trait_name.clone(), name.clone()));
+                if trait_fn.sig.receiver().is_some() {
+                    self.crate_fns.methods.insert(name);
+                }
+            }
+        }
+        self.traits.insert(trait_name);
+        syn::visit::visit_item_trait(self, node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_test_crate_fns() -> CrateFns {
+        let file = syn::parse_file(r#"
+mod utils {
+    pub fn helper() {}
+}
+struct Processor;
+impl Processor {
+    fn new() -> Self { Processor }
+    fn process(&self) {}
+}
+trait Check {
+    fn check(&self);
+}
+impl Check for Processor {
+    fn check(&self) {}
+}
+impl std::fmt::Display for Processor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result { Ok(()) }
+}
+"#).unwrap();
+        get_crate_fns(&[file])
+    }
+
+    fn is_crate_call(crate_fns: &CrateFns, code: &str) -> bool {
+        get_call_line(code.parse().unwrap(), crate_fns).is_some()
+    }
+
+    #[test]
+    fn test_crate_calls() {
+        let crate_fns = get_test_crate_fns();
+        for code in ["helper()", "utils::helper()", "crate::utils::helper()", "Processor::new()", "Self::new()", "p.process()", "p.check()", "Check::check(&p)", r#"msg!("{}", helper())"#] {
+            assert!(is_crate_call(&crate_fns, code), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_other_calls() {
+        let crate_fns = get_test_crate_fns();
+        for code in ["Vec::new()", "other::helper()", "String::from(s)", "x.fmt(f)", "x.len()", "Some(x)", "Vec::<u8>::new()"] {
+            assert!(!is_crate_call(&crate_fns, code), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_call_line() {
+        let crate_fns = get_test_crate_fns();
+        let tokens = "let x = Vec::new()\n    .into_iter()\n    .map(|p| p.process());".parse().unwrap();
+        assert_eq!(get_call_line(tokens, &crate_fns), Some(3));
+    }
+}
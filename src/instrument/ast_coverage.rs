@@ -0,0 +1,80 @@
+use std::collections::BTreeSet;
+use proc_macro2::TokenStream;
+use syn::fold::Fold;
+use syn::*;
+use crate::instrument::ast_fn_body::*;
+
+#[derive(Clone, Debug)]
+struct InstContext {
+    /// Logged with the lines, e.g. `src/processor.rs`
+    file_path: String,
+    /// The lines that log a hit
+    lines: BTreeSet<usize>,
+}
+
+/// Log a hit whenever a line with a statement is executed
+///
+/// Several statements on one line only log one hit. Arm and closure bodies without braces log their own, so a line like `x.map(|v| f(v))` counts both
+pub fn inst_ast_coverage(file: File, file_path: &str) -> File {
+    let mut fold = FnBodyFold::new(InstContext {
+        file_path: file_path.to_string(),
+        lines: BTreeSet::new(),
+    });
+    fold.fold_file(file)
+}
+
+/// The lines that `inst_ast_coverage` instruments, i.e. the lines that can have hits
+pub fn get_coverage_lines(file: File) -> BTreeSet<usize> {
+    let mut fold = FnBodyFold::new(InstContext {
+        file_path: String::new(),
+        lines: BTreeSet::new(),
+    });
+    fold.fold_file(file);
+    fold.inst.lines
+}
+
+impl FnBodyInst for InstContext {
+    fn get_line_stmt(&mut self, line: usize, _code: TokenStream) -> Option<Stmt> {
+        self.lines.insert(line);
+        let hit_str = format!("-.!;HIT;{};{}", line, self.file_path);
+        Some(parse_quote! {
+            solana_program::log::sol_log(#hit_str);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage_lines() {
+        let source = r#"
+const fn zero() -> u8 {
+    0
+}
+
+impl A {
+    fn f(&self, x: Option<u8>) -> u8 {
+        let a = 1; let b = 2;
+        let c = x.map(|v| v + 1);
+        match c {
+            Some(v) => v,
+            None => {
+                a + b
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn g() {
+        let c = 3;
+    }
+}
+"#;
+        let file = syn::parse_file(source).unwrap();
+        assert_eq!(get_coverage_lines(file), BTreeSet::from([8, 9, 10, 11, 13]));
+    }
+}
@@ -1,5 +1,6 @@
-use syn::{File, Item, ItemMod};
+use syn::{Block, File, Item, ItemMod, Stmt};
 use syn::fold::Fold;
+use crate::instrument::custom_types::is_cfg_test;
 
 #[derive(Clone, Debug)]
 struct InstContext {
@@ -17,6 +18,10 @@ impl Fold for InstContext {
     }
 
     fn fold_item_mod(&mut self, mut node: ItemMod) -> ItemMod {
+        // Test-only modules are not part of the program
+        if is_cfg_test(&node.attrs) {
+            return node;
+        }
         match &mut node.content {
             Some((_, items)) => {
                 insert_serialize_impl(items)
@@ -25,23 +30,129 @@ impl Fold for InstContext {
         }
         syn::fold::fold_item_mod(self, node)
     }
+
+    // Items declared inside function bodies
+    fn fold_block(&mut self, mut node: Block) -> Block {
+        let mut i: usize = 0;
+        while i < node.stmts.len() {
+            let serialize_impl = match &node.stmts[i] {
+                Stmt::Item(item) => get_serialize_impl(item),
+                _ => None
+            };
+            match serialize_impl {
+                Some(serialize_impl) => {
+                    node.stmts.insert(i+1, Stmt::Item(serialize_impl));
+                    i += 2
+                },
+                None => {
+                    i += 1
+                }
+            }
+        }
+        syn::fold::fold_block(self, node)
+    }
 }
 
 fn insert_serialize_impl(items: &mut Vec<Item>) {
     let mut i: usize = 0;
     while i < items.len() {
-        match &items[i] {
-            Item::Struct(val) => {
-                items.insert(i+1, syn::Item::Impl(crate::instrument::custom_types::structs::get_serialize_impl(val)));
-                i += 2
-            },
-            Item::Enum(val) => {
-                items.insert(i+1, syn::Item::Impl(crate::instrument::custom_types::enums::get_serialize_impl(val)));
+        match get_serialize_impl(&items[i]) {
+            Some(serialize_impl) => {
+                items.insert(i+1, serialize_impl);
                 i += 2
             },
-            _ => {
+            None => {
                 i += 1
             }
         }
     }
-}
\ No newline at end of file
+}
+
+fn get_serialize_impl(item: &Item) -> Option<Item> {
+    match item {
+        Item::Struct(val) => {
+            crate::instrument::custom_types::structs::get_serialize_impl(val).map(syn::Item::Impl)
+        },
+        Item::Enum(val) => {
+            Some(syn::Item::Impl(crate::instrument::custom_types::enums::get_serialize_impl(val)))
+        },
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::quote;
+
+    /// The types that get a serialize impl, in order
+    fn get_serialized_types(source: &str) -> Vec<String> {
+        let file = inst_ast_custom_types(syn::parse_file(source).unwrap());
+        let mut types = Vec::new();
+        let mut items: Vec<&Item> = file.items.iter().collect();
+        while let Some(item) = items.pop() {
+            match item {
+                Item::Impl(item_impl) => {
+                    let self_ty = &item_impl.self_ty;
+                    types.push(quote!(#self_ty).to_string());
+                }
+                Item::Mod(item_mod) => items.extend(item_mod.content.iter().flat_map(|(_, items)| items)),
+                _ => {}
+            }
+        }
+        types.sort();
+        types
+    }
+
+    #[test]
+    fn test_cfg_fields() {
+        let source = r#"
+struct Tuple(u8, #[cfg(feature = "x")] u16, u32);
+struct PlainTuple(u8, u32);
+struct Named { a: u8, #[cfg(feature = "x")] b: u16, c: u32 }
+"#;
+        // With the feature off, `self.2` of `Tuple` wouldn't exist
+        assert_eq!(get_serialized_types(source), vec!["Named", "PlainTuple"]);
+
+        let file = inst_ast_custom_types(syn::parse_file(source).unwrap());
+        let output = quote!(#file).to_string();
+        assert!(output.contains("# [cfg (feature = \"x\")] crate :: _solana_debugger_serialize :: _solana_debugger_serialize_value ! (& self . b , \"b\")"));
+    }
+
+    #[test]
+    fn test_cfg_test_modules() {
+        let source = r#"
+#[cfg(test)] mod a { struct A; }
+#[cfg(all(test, feature = "x"))] mod b { struct B; }
+#[cfg(any(test, feature = "x"))] mod c { struct C; }
+#[cfg(not(test))] mod d { struct D; }
+#[cfg(feature = "test")] mod e { struct E; }
+"#;
+        assert_eq!(get_serialized_types(source), vec!["C", "D", "E"]);
+    }
+
+    #[test]
+    fn test_packed_fields() {
+        let source = r#"
+#[repr(C, packed)]
+struct Packed { a: u8, b: u64 }
+#[zero_copy(unsafe)]
+struct ZeroCopyUnsafe { a: u8, b: u64 }
+#[derive(Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct PodStruct { a: u64, data: [u8; 4096] }
+#[account(zero_copy)]
+struct ZeroCopy { a: u64, data: [u8; 4096] }
+"#;
+        let file = inst_ast_custom_types(syn::parse_file(source).unwrap());
+        for item in &file.items {
+            let Item::Impl(item_impl) = item else {
+                continue;
+            };
+            let self_ty = &item_impl.self_ty;
+            let is_copied = quote!(#item_impl).to_string().contains("(& { self . a }");
+            let expected = matches!(quote!(#self_ty).to_string().as_str(), "Packed" | "ZeroCopyUnsafe");
+            assert_eq!(is_copied, expected, "{}", quote!(#self_ty));
+        }
+    }
+}
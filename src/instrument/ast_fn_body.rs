@@ -0,0 +1,194 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::fold::Fold;
+use syn::spanned::Spanned;
+use syn::*;
+use crate::instrument::custom_types::is_cfg_test;
+
+/// What a pass adds to the bodies of the functions, see `FnBodyFold`
+pub trait FnBodyInst {
+    /// Only the first statement of a line gets a statement in front of it
+    const ONCE_PER_LINE: bool = true;
+
+    /// The statement to put in front of a statement, or of an arm or closure body without braces. `None` to add nothing
+    ///
+    /// `line` is where `code` starts
+    fn get_line_stmt(&mut self, line: usize, code: TokenStream) -> Option<Stmt>;
+
+    /// Change the body of a function, e.g. to put a frame guard in front of it. Not called for `const fn`
+    ///
+    /// `name` is qualified by the impl or trait, e.g. `Processor::process`
+    fn inst_fn_block(&mut self, block: Block, _sig: &Signature, _name: &str) -> Block {
+        block
+    }
+
+    /// Whether to leave the functions of the impl as they are
+    fn is_skipped_impl(&self, _node: &ItemImpl) -> bool {
+        false
+    }
+}
+
+/// Walk the bodies of all functions and let `inst` add statements to them
+///
+/// Const contexts and `cfg(test)` modules are skipped
+pub struct FnBodyFold<T: FnBodyInst> {
+    pub inst: T,
+    /// Name of the impl or trait we're in, for the qualified names
+    parent_name: Option<String>,
+    /// Whether we're in the body of a function that is not `const`
+    in_fn: bool,
+}
+
+impl<T: FnBodyInst> FnBodyFold<T> {
+    pub fn new(inst: T) -> Self {
+        FnBodyFold {
+            inst,
+            parent_name: None,
+            in_fn: false,
+        }
+    }
+
+    /// Fold the body of a function. Functions nested in it are not qualified by the impl
+    fn fold_fn<N>(&mut self, sig: &Signature, node: N, fold: impl FnOnce(&mut Self, N) -> N) -> N {
+        let outer = (self.parent_name.take(), self.in_fn);
+        self.in_fn = sig.constness.is_none();
+        let node = fold(self, node);
+        (self.parent_name, self.in_fn) = outer;
+        node
+    }
+
+    fn inst_fn_block(&mut self, block: Block, sig: &Signature) -> Block {
+        // Functions that can run at compile time can't log
+        if sig.constness.is_some() {
+            return block;
+        }
+        let name = match &self.parent_name {
+            Some(parent) => format!("{}::{}", parent, sig.ident),
+            None => sig.ident.to_string(),
+        };
+        self.inst.inst_fn_block(block, sig, &name)
+    }
+
+    /// Put the statement in front of an arm or closure body without braces
+    fn inst_expr(&mut self, expr: Expr) -> Expr {
+        if !self.in_fn {
+            return expr;
+        }
+        if let Expr::Block(_) = expr {
+            return expr;
+        }
+        match self.inst.get_line_stmt(expr.span().start().line, quote!(#expr)) {
+            Some(line_stmt) => parse_quote! {{
+                #line_stmt
+                #expr
+            }},
+            None => expr,
+        }
+    }
+
+    fn fold_with_parent<N>(&mut self, parent_name: Option<String>, node: N, fold: impl FnOnce(&mut Self, N) -> N) -> N {
+        let outer_parent_name = std::mem::replace(&mut self.parent_name, parent_name);
+        let node = fold(self, node);
+        self.parent_name = outer_parent_name;
+        node
+    }
+}
+
+impl<T: FnBodyInst> Fold for FnBodyFold<T> {
+    fn fold_arm(&mut self, node: Arm) -> Arm {
+        let mut node = syn::fold::fold_arm(self, node);
+        node.body = Box::new(self.inst_expr(*node.body));
+        node
+    }
+
+    fn fold_block(&mut self, node: Block) -> Block {
+        let mut node = syn::fold::fold_block(self, node);
+        if !self.in_fn {
+            return node;
+        }
+        let mut stmts = Vec::with_capacity(node.stmts.len());
+        let mut last_line = None;
+        for stmt in node.stmts {
+            // Items are not executed
+            let line = stmt.span().start().line;
+            let is_seen_line = T::ONCE_PER_LINE && last_line == Some(line);
+            if !matches!(stmt, Stmt::Item(_)) && !is_seen_line {
+                if let Some(line_stmt) = self.inst.get_line_stmt(line, quote!(#stmt)) {
+                    stmts.push(line_stmt);
+                    last_line = Some(line);
+                }
+            }
+            stmts.push(stmt);
+        }
+        node.stmts = stmts;
+        node
+    }
+
+    fn fold_expr_closure(&mut self, node: ExprClosure) -> ExprClosure {
+        let mut node = syn::fold::fold_expr_closure(self, node);
+        node.body = Box::new(self.inst_expr(*node.body));
+        node
+    }
+
+    // Const contexts can't log
+    fn fold_expr_const(&mut self, node: ExprConst) -> ExprConst {
+        node
+    }
+
+    fn fold_item_const(&mut self, node: ItemConst) -> ItemConst {
+        node
+    }
+
+    fn fold_item_static(&mut self, node: ItemStatic) -> ItemStatic {
+        node
+    }
+
+    fn fold_impl_item_const(&mut self, node: ImplItemConst) -> ImplItemConst {
+        node
+    }
+
+    fn fold_item_fn(&mut self, node: ItemFn) -> ItemFn {
+        let sig = node.sig.clone();
+        let mut node = self.fold_fn(&sig, node, syn::fold::fold_item_fn);
+        node.block = Box::new(self.inst_fn_block(*node.block, &sig));
+        node
+    }
+
+    fn fold_impl_item_fn(&mut self, node: ImplItemFn) -> ImplItemFn {
+        let sig = node.sig.clone();
+        let mut node = self.fold_fn(&sig, node, syn::fold::fold_impl_item_fn);
+        node.block = self.inst_fn_block(node.block, &sig);
+        node
+    }
+
+    fn fold_trait_item_fn(&mut self, node: TraitItemFn) -> TraitItemFn {
+        let sig = node.sig.clone();
+        let mut node = self.fold_fn(&sig, node, syn::fold::fold_trait_item_fn);
+        node.default = node.default.map(|block| self.inst_fn_block(block, &sig));
+        node
+    }
+
+    fn fold_item_impl(&mut self, node: ItemImpl) -> ItemImpl {
+        if self.inst.is_skipped_impl(&node) {
+            return node;
+        }
+        let parent_name = match &*node.self_ty {
+            Type::Path(type_path) => type_path.path.segments.last().map(|segment| segment.ident.to_string()),
+            _ => None,
+        };
+        self.fold_with_parent(parent_name, node, syn::fold::fold_item_impl)
+    }
+
+    fn fold_item_trait(&mut self, node: ItemTrait) -> ItemTrait {
+        let parent_name = Some(node.ident.to_string());
+        self.fold_with_parent(parent_name, node, syn::fold::fold_item_trait)
+    }
+
+    fn fold_item_mod(&mut self, node: ItemMod) -> ItemMod {
+        // Test-only modules are not part of the program
+        if is_cfg_test(&node.attrs) {
+            return node;
+        }
+        syn::fold::fold_item_mod(self, node)
+    }
+}
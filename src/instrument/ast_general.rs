@@ -1,96 +1,504 @@
 use std::collections::HashSet;
-use proc_macro2::Ident;
+use std::rc::Rc;
+use proc_macro2::{Ident, Span};
 use quote::quote;
 use syn::fold::Fold;
+use syn::visit::Visit;
 use syn::*;
+use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
+use crate::instrument::InjectionPoint;
 
 #[derive(Clone, Debug)]
 struct InstContext {
     // We use a Vec instead of a HashSet to keep the order in which Idents are added. This makes debugging easier
-    bindings: Vec<Ident>,
-    line: usize
+    bindings: Vec<Binding>,
+    /// Where the instrumentation is inserted, see `resolve_injection_point`
+    points: Rc<Vec<InjectionPoint>>,
+    /// If set, every `?` and `return Err(..)` is instrumented
+    inst_errors: bool,
+    /// If set, the lines are logged together with this file path
+    file_path: Option<String>,
+    /// See `get_pat_constants`
+    pat_constants: Rc<HashSet<String>>,
     //file_path: String
 }
 
-/// TODO: should support execution path, a set of lines etc.
-pub fn inst_ast_general(file: File, line: usize) -> File {
+/// A variable that is in scope
+#[derive(Clone, Debug)]
+struct Binding {
+    ident: Ident,
+    /// Line of the declaration
+    line: usize,
+    /// Whether this binding shadows an earlier one with the same name
+    is_shadowing: bool,
+    /// The declared type, e.g. for `let amount: u64` or a function parameter
+    ty: Option<Type>,
+}
+
+impl Binding {
+    /// Shadowing bindings are shown with the line of their declaration, e.g. `amount (line 41)`
+    fn get_display_name(&self) -> String {
+        if self.is_shadowing {
+            format!("{} (line {})", self.ident, self.line)
+        } else {
+            self.ident.to_string()
+        }
+    }
+}
+
+pub fn inst_ast_general(file: File, point: InjectionPoint, pat_constants: Rc<HashSet<String>>) -> File {
+    let mut ctx = InstContext {
+        bindings: Vec::new(),
+        points: Rc::new(vec![point]),
+        inst_errors: false,
+        file_path: None,
+        pat_constants,
+    };
+    ctx.fold_file(file)
+}
+
+/// Log the variables in scope at each of the points
+///
+/// `file_path` is logged with the lines, e.g. `src/processor.rs`
+pub fn inst_ast_points(file: File, points: Vec<InjectionPoint>, file_path: &str, pat_constants: Rc<HashSet<String>>) -> File {
+    let mut ctx = InstContext {
+        bindings: Vec::new(),
+        points: Rc::new(points),
+        inst_errors: false,
+        file_path: Some(file_path.to_string()),
+        pat_constants,
+    };
+    ctx.fold_file(file)
+}
+
+/// Log the variables in scope wherever an error is produced with `?` or `return Err(..)`
+///
+/// `file_path` is logged with the line, e.g. `src/processor.rs`
+pub fn inst_ast_errors(file: File, file_path: &str, pat_constants: Rc<HashSet<String>>) -> File {
     let mut ctx = InstContext {
         bindings: Vec::new(),
-        line
+        points: Default::default(),
+        inst_errors: true,
+        file_path: Some(file_path.to_string()),
+        pat_constants,
     };
     ctx.fold_file(file)
 }
 
+/// A variable that is in scope at an injection point
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeVariable {
+    /// The name as shown by `var`, e.g. `amount (line 41)` for a shadowing variable
+    pub name: String,
+    /// Line of the declaration
+    pub line: usize,
+    /// The declared type, if it's written in the source
+    pub ty: Option<String>,
+}
+
+/// Get the variables that would be logged at the injection point, without instrumenting anything
+///
+/// `None` if the point is not in the file
+pub fn get_scope(file: &File, point: InjectionPoint, pat_constants: Rc<HashSet<String>>) -> Option<Vec<ScopeVariable>> {
+    let mut collector = ScopeCollector {
+        bindings: Vec::new(),
+        point,
+        pat_constants,
+        scope_at_point: None,
+    };
+    collector.visit_file(file);
+    let bindings = collector.scope_at_point?;
+    Some(bindings.iter().map(|binding| ScopeVariable {
+        name: binding.get_display_name(),
+        line: binding.line,
+        ty: binding.ty.as_ref().map(get_type_string),
+    }).collect())
+}
+
+/// E.g. `&[AccountInfo<'a>]` instead of `& [AccountInfo < 'a >]` from `quote!(#ty).to_string()`
+///
+/// prettyplease prints the type of a dummy item. It wraps long types over several lines, those are joined again
+fn get_type_string(ty: &Type) -> String {
+    let item: Item = parse_quote!(type T = #ty;);
+    let output = prettyplease::unparse(&File { shebang: None, attrs: Vec::new(), items: vec![item] });
+    let output = output.trim().strip_prefix("type T = ").and_then(|output| output.strip_suffix(';'));
+    let Some(output) = output else {
+        return quote!(#ty).to_string();
+    };
+
+    let lines: Vec<&str> = output.lines().map(str::trim).collect();
+    let mut result = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        let next_line = lines.get(i + 1);
+        // The trailing comma that is only there because of the wrapping
+        let is_closed_next = next_line.is_some_and(|next_line| next_line.starts_with(['>', ')', ']']));
+        let line = match is_closed_next {
+            true => line.strip_suffix(',').unwrap_or(line),
+            false => line,
+        };
+        result.push_str(line);
+        if next_line.is_some() && !is_closed_next && !line.ends_with(['<', '(', '[']) {
+            result.push(' ');
+        }
+    }
+    result
+}
+
+/// Add new bindings to the scope. A new binding replaces an older one with the same name
+fn add_bindings(bindings: &mut Vec<Binding>, idents: Vec<(Ident, Option<Type>)>) {
+    for (ident, ty) in idents {
+        let old_len = bindings.len();
+        bindings.retain(|binding| binding.ident != ident);
+        let is_shadowing = bindings.len() < old_len;
+        bindings.push(Binding {
+            line: ident.span().start().line,
+            ident,
+            is_shadowing,
+            ty,
+        });
+    }
+}
+
+/// Finds the bindings in scope at an injection point, see `get_scope`
+///
+/// The scopes follow the `Fold` of `InstContext`, so these are the bindings that the instrumentation would log
+struct ScopeCollector {
+    bindings: Vec<Binding>,
+    point: InjectionPoint,
+    pat_constants: Rc<HashSet<String>>,
+    scope_at_point: Option<Vec<Binding>>,
+}
+
+impl ScopeCollector {
+    fn record_if(&mut self, is_at_point: bool) {
+        if is_at_point {
+            self.scope_at_point = Some(self.bindings.clone());
+        }
+    }
+
+    /// Visit a node with the bindings as they are after `scope`, and restore them afterwards
+    fn visit_scoped(&mut self, scope: impl FnOnce(&mut Self), visit: impl FnOnce(&mut Self)) {
+        let outer_bindings = self.bindings.clone();
+        scope(self);
+        visit(self);
+        self.bindings = outer_bindings;
+    }
+
+    fn visit_fn(&mut self, sig: &Signature, visit: impl FnOnce(&mut Self)) {
+        let pat_constants = self.pat_constants.clone();
+        // Only the parameters are in scope, also for functions nested in another function
+        self.visit_scoped(|collector| {
+            collector.bindings.clear();
+            add_bindings(&mut collector.bindings, get_bindings_from_fn_sig(sig, &pat_constants));
+            collector.record_if(collector.point.is_fn_return_at(sig.ident.span().start()));
+        }, visit);
+    }
+}
+
+impl<'ast> Visit<'ast> for ScopeCollector {
+    fn visit_arm(&mut self, node: &'ast Arm) {
+        let pat_constants = self.pat_constants.clone();
+        self.visit_scoped(|collector| {
+            add_bindings(&mut collector.bindings, get_bindings_from_pat(&node.pat, &pat_constants));
+            if let Some((_, guard)) = &node.guard {
+                add_bindings(&mut collector.bindings, get_bindings_from_cond(guard, &pat_constants));
+            }
+            collector.record_if(collector.point.is_expr_at(node.body.span().start()));
+        }, |collector| syn::visit::visit_arm(collector, node));
+    }
+
+    fn visit_block(&mut self, node: &'ast Block) {
+        let outer_bindings = self.bindings.clone();
+        for stmt in &node.stmts {
+            self.record_if(self.point.is_stmt_at(stmt.span().start()));
+            let in_scope_bindings = get_in_scope_bindings_from_stmt(stmt, &self.pat_constants);
+            // The bindings of the statement's own blocks, closures etc. are not in scope afterwards
+            self.visit_scoped(|_| {}, |collector| collector.visit_stmt(stmt));
+            add_bindings(&mut self.bindings, in_scope_bindings);
+        }
+        self.record_if(self.point.is_block_end_at(node.brace_token.span.close().start()));
+        self.bindings = outer_bindings;
+    }
+
+    fn visit_expr_if(&mut self, node: &'ast ExprIf) {
+        self.visit_expr(&node.cond);
+        let pat_constants = self.pat_constants.clone();
+        self.visit_scoped(
+            |collector| add_bindings(&mut collector.bindings, get_bindings_from_cond(&node.cond, &pat_constants)),
+            |collector| collector.visit_block(&node.then_branch),
+        );
+        if let Some((_, else_branch)) = &node.else_branch {
+            self.visit_expr(else_branch);
+        }
+    }
+
+    fn visit_expr_for_loop(&mut self, node: &'ast ExprForLoop) {
+        self.visit_expr(&node.expr);
+        let pat_constants = self.pat_constants.clone();
+        self.visit_scoped(
+            |collector| add_bindings(&mut collector.bindings, get_bindings_from_pat(&node.pat, &pat_constants)),
+            |collector| collector.visit_block(&node.body),
+        );
+    }
+
+    fn visit_expr_while(&mut self, node: &'ast ExprWhile) {
+        self.visit_expr(&node.cond);
+        let pat_constants = self.pat_constants.clone();
+        self.visit_scoped(
+            |collector| add_bindings(&mut collector.bindings, get_bindings_from_cond(&node.cond, &pat_constants)),
+            |collector| collector.visit_block(&node.body),
+        );
+    }
+
+    fn visit_expr_closure(&mut self, node: &'ast ExprClosure) {
+        let pat_constants = self.pat_constants.clone();
+        self.visit_scoped(|collector| {
+            // Only the captured outer bindings, see `InstContext::fold_expr_closure`
+            let body = &node.body;
+            let mut used_idents = HashSet::new();
+            get_idents_from_tokens(quote!(#body), &mut used_idents);
+            collector.bindings.retain(|binding| used_idents.contains(&binding.ident.to_string()));
+            for input in node.inputs.iter() {
+                add_bindings(&mut collector.bindings, get_bindings_from_pat(input, &pat_constants));
+            }
+            collector.record_if(collector.point.is_expr_at(node.body.span().start()));
+        }, |collector| syn::visit::visit_expr_closure(collector, node));
+    }
+
+    fn visit_item_fn(&mut self, node: &'ast ItemFn) {
+        self.visit_fn(&node.sig, |collector| syn::visit::visit_item_fn(collector, node));
+    }
+
+    fn visit_impl_item_fn(&mut self, node: &'ast ImplItemFn) {
+        self.visit_fn(&node.sig, |collector| syn::visit::visit_impl_item_fn(collector, node));
+    }
+
+    fn visit_trait_item_fn(&mut self, node: &'ast TraitItemFn) {
+        self.visit_fn(&node.sig, |collector| syn::visit::visit_trait_item_fn(collector, node));
+    }
+}
+
+impl InstContext {
+    /// Add new bindings to the scope, see `add_bindings`
+    fn add_bindings(&mut self, idents: Vec<(Ident, Option<Type>)>) {
+        add_bindings(&mut self.bindings, idents);
+    }
+
+    fn find_point(&self, predicate: impl Fn(&InjectionPoint) -> bool) -> Option<InjectionPoint> {
+        self.points.iter().find(|point| predicate(point)).copied()
+    }
+
+    /// Get a context for the body of a function: only its parameters are in scope
+    ///
+    /// This also applies to functions nested in another function, which can't access the outer bindings
+    fn get_fn_context(&self, sig: &Signature) -> InstContext {
+        let mut ctx = InstContext {
+            bindings: Vec::new(),
+            points: self.points.clone(),
+            inst_errors: self.inst_errors,
+            file_path: self.file_path.clone(),
+            pat_constants: self.pat_constants.clone(),
+        };
+        ctx.add_bindings(get_bindings_from_fn_sig(sig, &self.pat_constants));
+        ctx
+    }
+
+    /// Get the block that logs all bindings in scope
+    ///
+    /// `value` is a temporary that is logged as well, e.g. the value of a block's tail expression
+    fn get_inst_stmt(&self, line: usize, value: Option<(&Ident, &str)>) -> Stmt {
+        self.get_inst_stmt_with_moved(line, value, &HashSet::new())
+    }
+
+    /// Like `get_inst_stmt`, but the bindings in `moved` are logged as not available
+    fn get_inst_stmt_with_moved(&self, line: usize, value: Option<(&Ident, &str)>, moved: &HashSet<String>) -> Stmt {
+        let mut inst_stmts: Vec<Stmt> = vec![];
+        let line_start_str = match &self.file_path {
+            Some(file_path) => format!("-.!;LINE_START;{};{}", line, file_path),
+            None => format!("-.!;LINE_START;{}", line),
+        };
+        inst_stmts.push(parse_quote! {
+            solana_program::log::sol_log(#line_start_str);
+        });
+        for binding in &self.bindings {
+            // The span of the declaration would make the source map point there
+            let mut ident = binding.ident.clone();
+            ident.set_span(Span::call_site());
+            let ident_str = binding.get_display_name();
+            let print_var: Stmt = if moved.contains(&ident_str) {
+                parse_quote! {
+                    crate::_solana_debugger_serialize::_solana_debugger_serialize_unavailable(#ident_str, "moved by the function");
+                }
+            } else {
+                parse_quote! {
+                    crate::_solana_debugger_serialize::_solana_debugger_serialize_value!(&#ident, #ident_str);
+                }
+            };
+            inst_stmts.push(print_var);
+        }
+        if let Some((ident, name)) = value {
+            inst_stmts.push(parse_quote! {
+                crate::_solana_debugger_serialize::_solana_debugger_serialize_value!(&#ident, #name);
+            });
+        }
+        inst_stmts.push(parse_quote! {
+            solana_program::log::sol_log("-.!;LINE_END");
+        });
+        let inst_block = Block {
+            brace_token: syn::token::Brace::default(),
+            stmts: inst_stmts
+        };
+        parse2::<Stmt>(quote!(#inst_block)).unwrap()
+    }
+
+    /// Wrap a function body to log the return value and the final state of the parameters
+    ///
+    /// The body runs in a closure, so that early `return`s and `?` also end up here.
+    /// Parameters that the body moves can't be logged afterwards, they are logged as not available (see `get_moved_params`).
+    /// `correct_file` does the same for moves that are not detected
+    fn inst_fn_block(&self, block: Block, sig: &Signature, line: usize) -> Block {
+        let return_type: Type = match &sig.output {
+            ReturnType::Default => parse_quote! { () },
+            ReturnType::Type(_, ty) => (**ty).clone(),
+        };
+        let ret_ident = Ident::new("_solana_debugger_ret", Span::call_site());
+        let ctx = self.get_fn_context(sig);
+        let moved = get_moved_params(&block, &ctx.bindings);
+        let inst_stmt = ctx.get_inst_stmt_with_moved(line, Some((&ret_ident, "(return value)")), &moved);
+        parse_quote! {{
+            #[allow(clippy::redundant_closure_call)]
+            let #ret_ident = (|| -> #return_type #block)();
+            #inst_stmt
+            #ret_ident
+        }}
+    }
+
+    /// Put the instrumentation in front of an expression without braces, e.g. a match arm body
+    fn inst_expr(&self, expr: Expr, line: usize) -> Expr {
+        let inst_stmt = self.get_inst_stmt(line, None);
+        parse_quote! {{
+            #inst_stmt
+            #expr
+        }}
+    }
+}
+
 impl Fold for InstContext {
     fn fold_arm(&mut self, node: Arm) -> Arm
     {
         let mut ctx = self.clone();
         // Get the bindings introduced by the arm's pattern
-        match &node.pat {
-            // Heuristic:
-            // If the match arm only consists of a single Pat::Ident, this means it's likely a unit variant and not a new binding
-            // So, we ignore it.
-            Pat::Ident(_ident) => {},
-            _ => {
-                ctx.bindings.extend(get_bindings_from_pat(&node.pat));
-            }
+        ctx.add_bindings(get_bindings_from_pat(&node.pat, &self.pat_constants));
+        if let Some((_, guard)) = &node.guard {
+            ctx.add_bindings(get_bindings_from_cond(guard, &self.pat_constants));
         }
 
-        syn::fold::fold_arm(&mut ctx, node)
+        let inst_point = self.find_point(|point| point.is_expr_at(node.body.span().start()));
+        let mut node = syn::fold::fold_arm(&mut ctx, node);
+        if let Some(point) = inst_point {
+            node.body = Box::new(ctx.inst_expr(*node.body, point.line()));
+        }
+        node
     }
 
     fn fold_block(&mut self, mut node: Block) -> Block {
+        let close_brace = node.brace_token.span.close().start();
+
         let mut stmts: Vec<Stmt> = vec![];
         for stmt in node.stmts {
-            let line_number = stmt.span().start().line;
-
-            // Instrumentation statements that come before stmt (but only if we're at the right line)
-            if line_number == self.line {
-                let mut inst_stmts: Vec<Stmt> = vec![];
-                let line_start_str = format!("-.!;LINE_START;{}", line_number);
-                inst_stmts.push(parse_quote! {
-                    solana_program::log::sol_log(#line_start_str);
-                });
-                for ident in &self.bindings {
-                    let ident_str = ident.to_string();
-                    let print_var: Stmt = parse_quote! {
-                        crate::_solana_debugger_serialize::_SolanaDebuggerSerialize::_solana_debugger_serialize(&#ident, #ident_str);
-                    };
-                    inst_stmts.push(print_var);
-                }
-                inst_stmts.push(parse_quote! {
-                    solana_program::log::sol_log("-.!;LINE_END");
-                });
-                let inst_block = Block {
-                    brace_token: syn::token::Brace::default(),
-                    stmts: inst_stmts
-                };
-                stmts.push(parse2::<Stmt>(quote!(#inst_block)).unwrap());
+            // Instrumentation statements that come before stmt (but only if we're at the right location)
+            if let Some(point) = self.find_point(|point| point.is_stmt_at(stmt.span().start())) {
+                stmts.push(self.get_inst_stmt(point.line(), None));
             }
 
             // Get new local bindings introduced by this statement
-            let in_scope_bindings = get_in_scope_bindings_from_stmt(&stmt);
+            let in_scope_bindings = get_in_scope_bindings_from_stmt(&stmt, &self.pat_constants);
 
             // self.clone() so that fold_stmt doesn't add its local bindings to ours
             stmts.push(self.clone().fold_stmt(stmt));
 
             // Add the new bindings to print them before the next statements
-            self.bindings.extend(in_scope_bindings);
+            self.add_bindings(in_scope_bindings);
         };
+
+        // Instrumentation at the closing brace. The tail expression is evaluated first so its value can be shown
+        if let Some(point) = self.find_point(|point| point.is_block_end_at(close_brace)) {
+            let tail: Option<Expr> = match stmts.last() {
+                Some(Stmt::Expr(expr, None)) => Some(expr.clone()),
+                Some(Stmt::Macro(stmt_macro)) if stmt_macro.semi_token.is_none() => Some(Expr::Macro(ExprMacro {
+                    attrs: stmt_macro.attrs.clone(),
+                    mac: stmt_macro.mac.clone(),
+                })),
+                _ => None,
+            };
+            match tail {
+                Some(tail) => {
+                    stmts.pop();
+                    let tail_ident = Ident::new("_solana_debugger_tail", Span::call_site());
+                    let inst_stmt = self.get_inst_stmt(point.line(), Some((&tail_ident, "(tail expression)")));
+                    // Like `dbg!`: temporaries of the scrutinee live as long as those of the tail expression, `let` would drop them
+                    stmts.push(Stmt::Expr(parse_quote! {
+                        match #tail {
+                            #tail_ident => {
+                                #inst_stmt
+                                #tail_ident
+                            }
+                        }
+                    }, None));
+                }
+                None => {
+                    stmts.push(self.get_inst_stmt(point.line(), None));
+                }
+            }
+        }
+
         node.stmts = stmts;
         node
     }
 
+    /// Break on errors: instrument `expr?` and `return Err(..)`
+    fn fold_expr(&mut self, node: Expr) -> Expr {
+        let node = syn::fold::fold_expr(self, node);
+        if !self.inst_errors {
+            return node;
+        }
+        let value_ident = Ident::new("_solana_debugger_value", Span::call_site());
+        match node {
+            Expr::Try(ExprTry { attrs, expr, question_token }) => {
+                let inst_stmt = self.get_inst_stmt(question_token.span.start().line, Some((&value_ident, "(error)")));
+                // A match keeps the temporaries of `expr` alive until the end of the statement, like `?` does
+                let checked_expr: Expr = parse_quote! {
+                    match #expr {
+                        #value_ident => {
+                            if crate::_solana_debugger_serialize::_SolanaDebuggerIsErr::_solana_debugger_is_err(&#value_ident) {
+                                #inst_stmt
+                            }
+                            #value_ident
+                        }
+                    }
+                };
+                Expr::Try(ExprTry { attrs, expr: Box::new(checked_expr), question_token })
+            }
+            Expr::Return(ExprReturn { return_token, expr: Some(expr), .. }) if is_err_call(&expr) => {
+                let inst_stmt = self.get_inst_stmt(return_token.span.start().line, Some((&value_ident, "(error)")));
+                parse_quote! {{
+                    let #value_ident = #expr;
+                    #inst_stmt
+                    return #value_ident;
+                }}
+            }
+            node => node,
+        }
+    }
+
     fn fold_expr_if(&mut self, mut node: ExprIf) -> ExprIf {
         let mut then_ctx = self.clone();
         let mut else_ctx = self.clone();
 
-        if let Expr::Let(ref expr) = *node.cond {
-            let let_bindings = get_bindings_from_pat(&*expr.pat);
-            //dbg!(&let_bindings);
-            then_ctx.bindings.extend(let_bindings);
-        }
+        let let_bindings = get_bindings_from_cond(&node.cond, &self.pat_constants);
+        //dbg!(&let_bindings);
+        then_ctx.add_bindings(let_bindings);
 
         let then_branch = node.then_branch.clone();
         node.then_branch = parse_quote!({});
@@ -99,22 +507,66 @@ impl Fold for InstContext {
         node
     }
 
+    fn fold_expr_for_loop(&mut self, mut node: ExprForLoop) -> ExprForLoop {
+        let mut body_ctx = self.clone();
+        body_ctx.add_bindings(get_bindings_from_pat(&node.pat, &self.pat_constants));
+
+        let body = node.body.clone();
+        node.body = parse_quote!({});
+        node = syn::fold::fold_expr_for_loop(self, node);
+        node.body = body_ctx.fold_block(body);
+        node
+    }
+
+    fn fold_expr_while(&mut self, mut node: ExprWhile) -> ExprWhile {
+        let mut body_ctx = self.clone();
+        body_ctx.add_bindings(get_bindings_from_cond(&node.cond, &self.pat_constants));
+
+        let body = node.body.clone();
+        node.body = parse_quote!({});
+        node = syn::fold::fold_expr_while(self, node);
+        node.body = body_ctx.fold_block(body);
+        node
+    }
+
+    fn fold_expr_closure(&mut self, node: ExprClosure) -> ExprClosure {
+        let mut ctx = self.clone();
+        // Only show outer bindings that the closure captures anyway
+        // Using others would add captures, which can move them or conflict with borrows outside the closure
+        let body = &node.body;
+        let mut used_idents = HashSet::new();
+        get_idents_from_tokens(quote!(#body), &mut used_idents);
+        ctx.bindings.retain(|binding| used_idents.contains(&binding.ident.to_string()));
+        for input in node.inputs.iter() {
+            ctx.add_bindings(get_bindings_from_pat(input, &self.pat_constants));
+        }
+        let inst_point = self.find_point(|point| point.is_expr_at(node.body.span().start()));
+        let mut node = syn::fold::fold_expr_closure(&mut ctx, node);
+        if let Some(point) = inst_point {
+            node.body = Box::new(ctx.inst_expr(*node.body, point.line()));
+        }
+        node
+    }
+
     fn fold_impl_item_fn(&mut self, node: ImplItemFn) -> ImplItemFn
     {
-        self.bindings = get_bindings_from_fn_sig(&node.sig);
-        syn::fold::fold_impl_item_fn(self, node)
-
-        /*
-        let inst_block = inst_fn_block(ctx.fold_block(node.block), &node.sig.output, &node.sig.ident, &self.file_path);
-        ImplItemFn {
-            attrs: self.fold_attributes(node.attrs),
-            vis: self.fold_visibility(node.vis),
-            defaultness: node.defaultness,
-            sig: self.fold_signature(node.sig),
-            block: inst_block,
+        let mut ctx = self.get_fn_context(&node.sig);
+        let mut node = syn::fold::fold_impl_item_fn(&mut ctx, node);
+        if let Some(point) = self.find_point(|point| point.is_fn_return_at(node.sig.ident.span().start())) {
+            node.block = self.inst_fn_block(node.block, &node.sig, point.line());
         }
-         */
-        // Idea: first do fold_block, THEN add header and footer inst for the fn
+        node
+    }
+
+    /// Default methods of traits
+    fn fold_trait_item_fn(&mut self, node: TraitItemFn) -> TraitItemFn
+    {
+        let mut ctx = self.get_fn_context(&node.sig);
+        let mut node = syn::fold::fold_trait_item_fn(&mut ctx, node);
+        if let Some(point) = self.find_point(|point| point.is_fn_return_at(node.sig.ident.span().start())) {
+            node.default = node.default.map(|block| self.inst_fn_block(block, &node.sig, point.line()));
+        }
+        node
     }
 
     /*
@@ -134,187 +586,606 @@ impl Fold for InstContext {
 
     fn fold_item_fn(&mut self, node: ItemFn) -> ItemFn
     {
-        self.bindings = get_bindings_from_fn_sig(&node.sig);
-        syn::fold::fold_item_fn(self, node)
-
-        /*
-        let inst_block = inst_fn_block(ctx.fold_block(*node.block), &node.sig.output, &node.sig.ident, &self.file_path);
-        ItemFn {
-            attrs: self.fold_attributes(node.attrs),
-            vis: self.fold_visibility(node.vis),
-            sig: self.fold_signature(node.sig),
-            block: Box::new(inst_block),
+        let mut ctx = self.get_fn_context(&node.sig);
+        let mut node = syn::fold::fold_item_fn(&mut ctx, node);
+        if let Some(point) = self.find_point(|point| point.is_fn_return_at(node.sig.ident.span().start())) {
+            node.block = Box::new(self.inst_fn_block(*node.block, &node.sig, point.line()));
         }
-         */
+        node
     }
 }
 
-fn get_bindings_from_fn_sig(sig: &Signature) -> Vec<Ident> {
+pub(crate) fn get_bindings_from_fn_sig(sig: &Signature, pat_constants: &HashSet<String>) -> Vec<(Ident, Option<Type>)> {
     let mut bindings = Vec::new();
     for arg in sig.inputs.iter() {
         match arg {
             FnArg::Receiver(receiver) => {
-                bindings.push(receiver.self_token.into());
+                bindings.push((receiver.self_token.into(), Some((*receiver.ty).clone())));
             },
-            FnArg::Typed(PatType { pat, .. }) => {
-                bindings.extend(get_bindings_from_pat(pat));
+            FnArg::Typed(pat_type) => {
+                bindings.extend(get_bindings_from_pat(&Pat::Type(pat_type.clone()), pat_constants));
             }
         }
     }
     bindings
 }
 
-fn get_bindings_from_pat(p: &Pat) -> Vec<Ident> {
-    let mut bindings = Vec::new();
-    match p {
-        Pat::Ident(PatIdent { ident, .. })  => {
-            bindings.push(ident.clone());
-        },
-        Pat::TupleStruct(PatTupleStruct { elems, .. })  => {
-            for el in elems {
-                bindings.extend(get_bindings_from_pat(el));
+/// Get the parameters that the function body may move. Parameters of references and primitive types are never moved
+///
+/// A parameter is moved if it's used as a value: assigned, passed, returned, captured by a `move` closure or the receiver of a method like `into_iter`.
+/// Borrows, field and index accesses and other method calls don't move it
+fn get_moved_params(block: &Block, params: &[Binding]) -> HashSet<String> {
+    let params = params.iter()
+        .filter(|param| !param.ty.as_ref().is_some_and(is_never_moved_type))
+        .map(|param| param.ident.to_string())
+        .collect();
+    let mut collector = MovedParamsCollector {
+        params,
+        moved: HashSet::new(),
+        in_move_closure: false,
+    };
+    collector.visit_block(block);
+    collector.moved
+}
+
+fn is_never_moved_type(ty: &Type) -> bool {
+    const COPY_TYPES: [&str; 15] = ["u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize", "bool", "char", "Pubkey"];
+    match ty {
+        Type::Reference(_) => true,
+        Type::Path(TypePath { qself: None, path }) => path.get_ident().is_some_and(|ident| COPY_TYPES.contains(&ident.to_string().as_str()) || ident == "f32" || ident == "f64"),
+        _ => false,
+    }
+}
+
+/// Methods that usually take `self` by value
+fn is_consuming_method(name: &str) -> bool {
+    ["into", "unwrap", "expect", "map", "ok", "and_then", "or_else"].iter().any(|prefix| name.starts_with(prefix)) || name == "err"
+}
+
+/// Macros that only borrow their arguments
+fn is_format_macro(path: &Path) -> bool {
+    let Some(segment) = path.segments.last() else {
+        return false;
+    };
+    let name = segment.ident.to_string();
+    ["msg", "format", "print", "println", "eprint", "eprintln", "write", "writeln", "panic"].contains(&name.as_str())
+        || name.starts_with("assert") || name.starts_with("debug_assert")
+}
+
+/// See `get_moved_params`
+struct MovedParamsCollector {
+    params: HashSet<String>,
+    moved: HashSet<String>,
+    in_move_closure: bool,
+}
+
+impl MovedParamsCollector {
+    fn get_param(&self, expr: &Expr) -> Option<String> {
+        match expr {
+            Expr::Path(ExprPath { qself: None, path, .. }) => path.get_ident()
+                .map(|ident| ident.to_string())
+                .filter(|name| self.params.contains(name)),
+            _ => None,
+        }
+    }
+
+    /// An expression whose place is used without moving it, e.g. the operand of `&`
+    fn visit_place(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Path(_) => {
+                if let Some(name) = self.get_param(expr).filter(|_| self.in_move_closure) {
+                    self.moved.insert(name);
+                }
             }
-        },
-        Pat::Type(PatType { pat, .. }) => {
-            bindings.extend(get_bindings_from_pat(&*pat));
-        },
-        Pat::Struct(PatStruct { fields, .. }) => {
-            for field in fields {
-                bindings.extend(get_bindings_from_pat(&field.pat));
+            Expr::Field(field) => self.visit_place(&field.base),
+            Expr::Index(index) => {
+                self.visit_place(&index.expr);
+                self.visit_expr(&index.index);
             }
-        },
-        Pat::Tuple(PatTuple { elems, .. }) => {
-            for el in elems {
-                bindings.extend(get_bindings_from_pat(el));
+            Expr::Paren(paren) => self.visit_place(&paren.expr),
+            _ => self.visit_expr(expr),
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for MovedParamsCollector {
+    fn visit_expr(&mut self, node: &'ast Expr) {
+        match node {
+            Expr::Path(_) => {
+                if let Some(name) = self.get_param(node) {
+                    self.moved.insert(name);
+                }
             }
-        },
-        // TODO: other cases
-        _ => {}
+            Expr::Reference(reference) => self.visit_place(&reference.expr),
+            Expr::Field(_) | Expr::Index(_) => self.visit_place(node),
+            Expr::MethodCall(call) => {
+                if is_consuming_method(&call.method.to_string()) {
+                    self.visit_expr(&call.receiver);
+                } else {
+                    self.visit_place(&call.receiver);
+                }
+                for arg in &call.args {
+                    self.visit_expr(arg);
+                }
+            }
+            Expr::Binary(binary) if matches!(binary.op, BinOp::Eq(_) | BinOp::Ne(_) | BinOp::Lt(_) | BinOp::Le(_) | BinOp::Gt(_) | BinOp::Ge(_)) => {
+                // Comparisons take references
+                self.visit_place(&binary.left);
+                self.visit_place(&binary.right);
+            }
+            Expr::Closure(closure) => {
+                let in_move_closure = self.in_move_closure;
+                self.in_move_closure |= closure.capture.is_some();
+                self.visit_expr(&closure.body);
+                self.in_move_closure = in_move_closure;
+            }
+            _ => syn::visit::visit_expr(self, node),
+        }
     }
-    bindings
 
-    /*
+    fn visit_macro(&mut self, node: &'ast Macro) {
+        match node.parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated) {
+            Ok(args) if is_format_macro(&node.path) => args.iter().for_each(|arg| self.visit_place(arg)),
+            Ok(args) => args.iter().for_each(|arg| self.visit_expr(arg)),
+            Err(_) => {
+                let mut idents = HashSet::new();
+                get_idents_from_tokens(node.tokens.clone(), &mut idents);
+                self.moved.extend(idents.intersection(&self.params).cloned());
+            }
+        }
+    }
+
+    // Nested items can't use the parameters
+    fn visit_item(&mut self, _node: &'ast Item) {}
+}
+
+/// Get the bindings of a pattern. The type is only known for `name: Type`, not for the parts of a destructured value
+fn get_bindings_from_pat(p: &Pat, pat_constants: &HashSet<String>) -> Vec<(Ident, Option<Type>)> {
+    let mut bindings = Vec::new();
     match p {
-        Pat::Ident(PatIdent { ident, subpat, .. }) => {
-            bindings.push(ident.clone());
-            // Handle optional sub-pattern (occurs in `binding @ SUBPATTERN`)
-            // Untested
+        Pat::Ident(PatIdent { ident, by_ref, mutability, subpat, .. }) => {
+            // A plain identifier can also refer to a unit variant or a constant (e.g. `None`)
+            let is_constant = pat_constants.contains(&ident.to_string());
+            if by_ref.is_some() || mutability.is_some() || subpat.is_some() || !is_constant {
+                bindings.push((ident.clone(), None));
+            }
+            // Sub-pattern in `binding @ SUBPATTERN`
             if let Some((_, subpat)) = subpat {
-                bindings.extend(get_bindings_from_pat(&*subpat));
+                bindings.extend(get_bindings_from_pat(subpat, pat_constants));
             }
         },
         Pat::TupleStruct(PatTupleStruct { elems, .. }) => {
             for el in elems {
-                bindings.extend(get_bindings_from_pat(el));
+                bindings.extend(get_bindings_from_pat(el, pat_constants));
             }
         },
-        Pat::Type(PatType { pat, .. }) => {
-            bindings.extend(get_bindings_from_pat(&*pat));
-        },
-        Pat::Const(_) => {
-            // Const patterns don't bind variables
-        },
-        Pat::Lit(_) => {
-            // Literal patterns don't bind variables
-        },
-        Pat::Macro(_) => {
-            // Can't inspect inside a macro pattern
+        Pat::Type(PatType { pat, ty, .. }) => {
+            let mut pat_bindings = get_bindings_from_pat(pat, pat_constants);
+            if let (Pat::Ident(_), [(_, binding_ty)]) = (&**pat, pat_bindings.as_mut_slice()) {
+                *binding_ty = Some((**ty).clone());
+            }
+            bindings.extend(pat_bindings);
         },
         Pat::Or(PatOr { cases, .. }) => {
-            // For alternations like `a | b`, collect bindings from all cases
-            for case in cases {
-                bindings.extend(get_bindings_from_pat(case));
+            // All cases of `a | b` must bind the same names
+            if let Some(case) = cases.first() {
+                bindings.extend(get_bindings_from_pat(case, pat_constants));
             }
         },
         Pat::Paren(PatParen { pat, .. }) => {
-            bindings.extend(get_bindings_from_pat(&*pat));
-        },
-        Pat::Path(_) => {
-            // Path patterns (like enum variants) don't bind variables
-        },
-        Pat::Range(_) => {
-            // Range patterns don't bind variables
+            bindings.extend(get_bindings_from_pat(pat, pat_constants));
         },
         Pat::Reference(PatReference { pat, .. }) => {
-            bindings.extend(get_bindings_from_pat(&*pat));
-        },
-        Pat::Rest(_) => {
-            // Rest patterns (..) don't bind variables
+            bindings.extend(get_bindings_from_pat(pat, pat_constants));
         },
         Pat::Slice(PatSlice { elems, .. }) => {
             for el in elems {
-                bindings.extend(get_bindings_from_pat(el));
+                bindings.extend(get_bindings_from_pat(el, pat_constants));
             }
         },
         Pat::Struct(PatStruct { fields, .. }) => {
             for field in fields {
-                bindings.extend(get_bindings_from_pat(&field.pat));
+                bindings.extend(get_bindings_from_pat(&field.pat, pat_constants));
             }
         },
         Pat::Tuple(PatTuple { elems, .. }) => {
             for el in elems {
-                bindings.extend(get_bindings_from_pat(el));
+                bindings.extend(get_bindings_from_pat(el, pat_constants));
             }
         },
-        Pat::Verbatim(_) => {
-            // Can't inspect inside verbatim tokens
+        // These don't bind variables
+        Pat::Const(_) | Pat::Lit(_) | Pat::Path(_) | Pat::Range(_) | Pat::Rest(_) | Pat::Wild(_) => {},
+        // Can't inspect inside macros and verbatim tokens
+        Pat::Macro(_) | Pat::Verbatim(_) => {},
+        _ => {}
+    }
+    bindings
+}
+
+/// Get the bindings introduced by the condition of `if`, `while` or a match guard
+///
+/// This handles `let` chains, e.g. `if let Some(a) = x && let Ok(b) = y`
+fn get_bindings_from_cond(cond: &Expr, pat_constants: &HashSet<String>) -> Vec<(Ident, Option<Type>)> {
+    let mut bindings = Vec::new();
+    match cond {
+        Expr::Let(expr) => {
+            bindings.extend(get_bindings_from_pat(&expr.pat, pat_constants));
         },
-        Pat::Wild(_) => {
-            // Wildcard patterns (_) don't bind variables
+        Expr::Binary(ExprBinary { left, op: BinOp::And(_), right, .. }) => {
+            bindings.extend(get_bindings_from_cond(left, pat_constants));
+            bindings.extend(get_bindings_from_cond(right, pat_constants));
         },
+        _ => {}
     }
-     */
+    bindings
 }
 
-// TODO: include this in the inst
-#[allow(dead_code)]
-fn inst_fn_block(block: Block, return_type: &ReturnType, fn_name: &Ident, file_path: &str) -> Block {
+/// Whether the expression is like `Err(..)`
+fn is_err_call(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call(ExprCall { func, .. }) => match &**func {
+            Expr::Path(ExprPath { path, .. }) => path.segments.last().is_some_and(|segment| segment.ident == "Err"),
+            _ => false,
+        },
+        _ => false,
+    }
+}
 
-    let var_type = match return_type {
-        ReturnType::Default => parse_quote! { () },
-        ReturnType::Type(_, ty) => (**ty).clone(),
-    };
+/// Get all identifiers in a token stream, including those in macro calls like `msg!("{}", x)`
+fn get_idents_from_tokens(tokens: proc_macro2::TokenStream, idents: &mut HashSet<String>) {
+    for token in tokens {
+        match token {
+            proc_macro2::TokenTree::Ident(ident) => {
+                idents.insert(ident.to_string());
+            }
+            proc_macro2::TokenTree::Group(group) => {
+                get_idents_from_tokens(group.stream(), idents);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Get new bindings introduced by stmt that are valid in its parent scope
+fn get_in_scope_bindings_from_stmt(stmt: &Stmt, pat_constants: &HashSet<String>) -> Vec<(Ident, Option<Type>)> {
+    let mut bindings = Vec::new();
+    match stmt {
+        Stmt::Local(local) => {
+            bindings.extend(get_bindings_from_pat(&local.pat, pat_constants));
+        }
+        _ => {}
+    }
+    bindings
+}
+/// Names of unit variants and constants, i.e. the identifiers that don't introduce a new binding when used as a pattern
+///
+/// `None` is always included since it's in the prelude
+pub fn get_pat_constants(files: &[File]) -> HashSet<String> {
+    let mut collector = PatConstantsCollector { names: HashSet::from(["None".to_string()]) };
+    for file in files {
+        collector.visit_file(file);
+    }
+    collector.names
+}
 
-    let fn_name_str = fn_name.to_string();
+struct PatConstantsCollector {
+    names: HashSet<String>,
+}
 
-    parse_quote! {{
-        sol_log("-.!;FN_START");
-        sol_log(#fn_name_str);
-        sol_log(#file_path);
-        let ret: #var_type = #block;
-        sol_log("-.!;FN_END");
-        ret
-    }}
+impl<'ast> Visit<'ast> for PatConstantsCollector {
+    fn visit_item_enum(&mut self, node: &'ast ItemEnum) {
+        for variant in node.variants.iter() {
+            if let Fields::Unit = variant.fields {
+                self.names.insert(variant.ident.to_string());
+            }
+        }
+        syn::visit::visit_item_enum(self, node);
+    }
 
-    /*
-    // Doesn't work since it changes the function to -> ()
-    block.stmts.insert(0, parse_quote!(
-       sol_log("-.!;FN_START");
-    ));
+    fn visit_item_const(&mut self, node: &'ast ItemConst) {
+        self.names.insert(node.ident.to_string());
+        syn::visit::visit_item_const(self, node);
+    }
 
-    block.stmts.push(parse_quote!(
-       sol_log("-.!;FN_END");
-    ));
+    fn visit_impl_item_const(&mut self, node: &'ast ImplItemConst) {
+        self.names.insert(node.ident.to_string());
+        syn::visit::visit_impl_item_const(self, node);
+    }
 
-    block
-     */
+    fn visit_item_static(&mut self, node: &'ast ItemStatic) {
+        self.names.insert(node.ident.to_string());
+        syn::visit::visit_item_static(self, node);
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::{find_fn_lines, find_fn_return_points, get_line_injection_points, resolve_fn_return_point, resolve_injection_point};
 
-/*
-TODO
-handle: if let Some(x) = y
-Expr::If
-*/
+    /// Collects the names of the variables that are serialized in the instrumentation block
+    struct SerializedNames {
+        names: Vec<String>,
+    }
 
-/// Get new bindings introduced by stmt that are valid in its parent scope
-fn get_in_scope_bindings_from_stmt(stmt: &Stmt) -> HashSet<Ident> {
-    let mut bindings = HashSet::new();
-    match stmt {
-        Stmt::Local(local) => {
-            bindings.extend(get_bindings_from_pat(&local.pat));
+    impl<'ast> Visit<'ast> for SerializedNames {
+        fn visit_macro(&mut self, node: &'ast Macro) {
+            if node.path.segments.last().is_some_and(|segment| segment.ident == "_solana_debugger_serialize_value") {
+                let args = node.parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated).unwrap();
+                if let Some(Expr::Lit(ExprLit { lit: Lit::Str(name), .. })) = args.iter().nth(1) {
+                    self.names.push(name.value());
+                }
+            }
+            syn::visit::visit_macro(self, node);
         }
-        _ => {}
     }
-    bindings
-}
\ No newline at end of file
+
+    fn get_serialized_names(source: &str, line: usize) -> Vec<String> {
+        let file = syn::parse_file(source).unwrap();
+        let point = resolve_injection_point(&file, line, None).unwrap();
+        get_serialized_names_at(source, point)
+    }
+
+    fn get_serialized_names_at(source: &str, point: InjectionPoint) -> Vec<String> {
+        let file = syn::parse_file(source).unwrap();
+        let pat_constants = get_pat_constants(std::slice::from_ref(&file));
+        let inst_file = inst_ast_general(file, point, Rc::new(pat_constants));
+        let mut visitor = SerializedNames { names: vec![] };
+        visitor.visit_file(&inst_file);
+        visitor.names.sort();
+        visitor.names
+    }
+
+    fn get_scope_names(source: &str, line: usize) -> Vec<String> {
+        let file = syn::parse_file(source).unwrap();
+        let point = resolve_injection_point(&file, line, None).unwrap();
+        let pat_constants = Rc::new(get_pat_constants(std::slice::from_ref(&file)));
+        let mut names: Vec<String> = get_scope(&file, point, pat_constants).unwrap().into_iter().map(|variable| variable.name).collect();
+        names.sort();
+        names
+    }
+
+    /// Check every line of the corpus that is annotated with `// @bindings: ...`
+    fn check_corpus(source: &str) {
+        let mut checked = 0;
+        for (i, line) in source.lines().enumerate() {
+            let Some((_, expected)) = line.split_once("// @bindings:") else {
+                continue;
+            };
+            let mut expected: Vec<String> = expected.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+            expected.sort();
+            assert_eq!(get_serialized_names(source, i + 1), expected, "Bindings at line {}", i + 1);
+            assert_eq!(get_scope_names(source, i + 1), expected, "Scope at line {}", i + 1);
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn test_bindings_token_processor() {
+        check_corpus(include_str!("../../tests/corpus/token_processor.rs"));
+    }
+
+    #[test]
+    fn test_bindings_shadowing() {
+        check_corpus(include_str!("../../tests/corpus/shadowing.rs"));
+    }
+
+    #[test]
+    fn test_bindings_scopes() {
+        check_corpus(include_str!("../../tests/corpus/scopes.rs"));
+    }
+
+    #[test]
+    fn test_injection_points() {
+        let source = "\
+fn process(accounts: Vec<u64>) -> u64 {
+    let total = accounts
+        .iter()
+        .sum::<u64>();
+
+    let doubled: Vec<u64> = accounts.iter().map(|x| x * 2).collect();
+    match total {
+        0 => 1,
+        _ => {
+            total
+        }
+    }
+}
+";
+        let file = syn::parse_file(source).unwrap();
+        let resolve = |line, column| resolve_injection_point(&file, line, column);
+
+        // Statement start
+        assert_eq!(resolve(2, None), Some(InjectionPoint::Stmt { line: 2, column: 4 }));
+        // Middle of a multi-line statement
+        assert_eq!(resolve(3, None), Some(InjectionPoint::Stmt { line: 2, column: 4 }));
+        // Blank line before the next statement
+        assert_eq!(resolve(5, None), Some(InjectionPoint::Stmt { line: 6, column: 4 }));
+        // A column selects the closure body on the same line
+        assert_eq!(resolve(6, None), Some(InjectionPoint::Stmt { line: 6, column: 4 }));
+        assert_eq!(resolve(6, Some(53)), Some(InjectionPoint::Expr { line: 6, column: 52 }));
+        // Match arm without braces
+        assert_eq!(resolve(8, None), Some(InjectionPoint::Expr { line: 8, column: 13 }));
+        // Closing brace
+        assert_eq!(resolve(11, None), Some(InjectionPoint::BlockEnd { line: 11, column: 8 }));
+        // Outside of a function body
+        assert_eq!(resolve(15, None), None);
+
+        assert_eq!(get_serialized_names(source, 8), vec!["accounts", "doubled", "total"]);
+        assert_eq!(get_serialized_names(source, 11), vec!["(tail expression)", "accounts", "doubled", "total"]);
+    }
+
+    #[test]
+    fn test_tail_temporaries() {
+        // The `Ref` of `borrow()` must live until the dereference, as it does without the instrumentation
+        let source = "\
+fn first(cell: &RefCell<Vec<u8>>) -> u8 {
+    let doubled = 2 * *{
+        cell.borrow().first().unwrap()
+    };
+    doubled
+}
+";
+        let file = syn::parse_file(source).unwrap();
+        let point = resolve_injection_point(&file, 4, None).unwrap();
+        assert_eq!(point, InjectionPoint::BlockEnd { line: 4, column: 4 });
+        let inst_file = inst_ast_general(file, point, Rc::new(HashSet::new()));
+        let output = quote!(#inst_file).to_string();
+        assert!(output.contains("match cell . borrow () . first () . unwrap () { _solana_debugger_tail =>"));
+        assert!(!output.contains("let _solana_debugger_tail"));
+    }
+
+    #[test]
+    fn test_type_string() {
+        let cases = [
+            "&'a [AccountInfo<'a>]",
+            "HashMap<Pubkey, Vec<u8>>",
+            "(u8,)",
+            "[u8; 32]",
+            "&mut Option<&str>",
+            "Box<dyn Fn(u8) -> u64 + 'a>",
+            "impl Iterator<Item = u64>",
+            "*const u8",
+            "<T as Trait>::Output",
+            "fn(&[u8]) -> Result<(), ProgramError>",
+            "HashMap<VeryLongKeyTypeNameForTheWrapping, BTreeMap<AnotherVeryLongTypeName, Vec<(u8, YetAnotherLongName)>>>",
+        ];
+        for case in cases {
+            assert_eq!(get_type_string(&syn::parse_str(case).unwrap()), case);
+        }
+    }
+
+    #[test]
+    fn test_scope() {
+        let source = include_str!("../../tests/corpus/token_processor.rs");
+        let file = syn::parse_file(source).unwrap();
+        let pat_constants = Rc::new(get_pat_constants(std::slice::from_ref(&file)));
+
+        let point = resolve_injection_point(&file, 99, None).unwrap();
+        let scope = get_scope(&file, point, pat_constants.clone()).unwrap();
+        let mut names: Vec<String> = scope.iter().map(|variable| variable.name.clone()).collect();
+        names.sort();
+        assert_eq!(names, get_serialized_names(source, 99));
+
+        let typed: Vec<(&str, Option<&str>)> = scope.iter().take(3).map(|variable| (variable.name.as_str(), variable.ty.as_deref())).collect();
+        assert_eq!(typed, vec![("program_id", Some("&Pubkey")), ("accounts", Some("&[AccountInfo]")), ("amount", Some("u64"))]);
+        let total = scope.iter().find(|variable| variable.name == "total").unwrap();
+        assert_eq!((total.line, total.ty.as_deref()), (88, Some("u64")));
+        assert_eq!(scope.iter().find(|variable| variable.name == "source_amount").unwrap().ty, None);
+
+        // Not a point in this file
+        assert_eq!(get_scope(&file, InjectionPoint::Stmt { line: 1, column: 0 }, pat_constants), None);
+    }
+
+    #[test]
+    fn test_fn_return() {
+        let source = include_str!("../../tests/corpus/token_processor.rs");
+        let file = syn::parse_file(source).unwrap();
+
+        let points = find_fn_return_points(&file, "processor", "process_transfer");
+        assert_eq!(points, vec![Ok(InjectionPoint::FnReturn { line: 73, column: 7 })]);
+        assert_eq!(find_fn_return_points(&file, "processor", "Processor::process_transfer"), points);
+        assert_eq!(find_fn_return_points(&file, "processor", "crate::processor::Processor::process_transfer"), points);
+        assert!(find_fn_return_points(&file, "instruction", "processor::process_transfer").is_empty());
+        assert_eq!(find_fn_lines(&file, "processor", "process_transfer", 5), vec![Ok(78)]);
+        assert_eq!(resolve_fn_return_point(&file, 99), Ok(InjectionPoint::FnReturn { line: 73, column: 7 }));
+        assert!(resolve_fn_return_point(&file, 20).is_err());
+
+        let names = get_serialized_names_at(source, points[0].clone().unwrap());
+        assert_eq!(names, vec!["(return value)", "accounts", "amount", "program_id"]);
+    }
+
+    #[test]
+    fn test_fn_return_moved_params() {
+        let source = "
+fn process(data: Vec<u8>, seeds: Vec<u8>, label: String, amount: u64) -> Vec<u8> {
+    msg!(\"{:?} {}\", seeds, label);
+    if label.len() > 3 && amount > 0 {
+        return label.into_bytes();
+    }
+    let _ = seeds.iter().count();
+    data
+}";
+        let file = syn::parse_file(source).unwrap();
+        let point = resolve_fn_return_point(&file, 2).unwrap();
+        assert_eq!(get_serialized_names_at(source, point), vec!["(return value)", "amount", "seeds"]);
+
+        let inst_file = inst_ast_general(file, point, Rc::new(HashSet::new()));
+        let output = quote!(#inst_file).to_string();
+        assert!(output.contains("_solana_debugger_serialize_unavailable (\"data\" , \"moved by the function\")"));
+        assert!(output.contains("_solana_debugger_serialize_unavailable (\"label\" , \"moved by the function\")"));
+    }
+
+    #[test]
+    fn test_fn_return_references() {
+        let source = "
+struct Vault { amount: u8 }
+
+impl Vault {
+    fn get(&self, x: &u8) -> Result<&u8, ()> {
+        Ok(x)
+    }
+
+    fn get_named<'a>(&'a self, x: &'a u8) -> Result<&'a u8, ()> {
+        Ok(x)
+    }
+
+    fn get_static(&self) -> &'static str {
+        \"vault\"
+    }
+}";
+        let file = syn::parse_file(source).unwrap();
+        let err = resolve_fn_return_point(&file, 6).unwrap_err();
+        assert!(err.contains("elided lifetimes"), "{}", err);
+
+        let point = resolve_fn_return_point(&file, 10).unwrap();
+        assert_eq!(get_serialized_names_at(source, point), vec!["(return value)", "self", "x"]);
+        assert!(resolve_fn_return_point(&file, 14).is_ok());
+    }
+
+    #[test]
+    fn test_break_on_error() {
+        let source = include_str!("../../tests/corpus/token_processor.rs");
+        let file = syn::parse_file(source).unwrap();
+        let inst_file = inst_ast_errors(file, "src/processor.rs", Rc::new(HashSet::new()));
+
+        let inst_source = quote!(#inst_file).to_string();
+        let lines: Vec<usize> = inst_source.split("-.!;LINE_START;").skip(1)
+            .map(|rest| rest.split(';').next().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(lines, vec![28, 61, 64, 67, 75, 76, 102]);
+        assert!(inst_source.contains("-.!;LINE_START;28;src/processor.rs"));
+    }
+
+    #[test]
+    fn test_record_points() {
+        let source = "\
+const fn double(x: u64) -> u64 {
+    x * 2
+}
+fn process(accounts: Vec<u64>) -> u64 {
+    let total: u64 = accounts.iter().sum();
+    match total {
+        0 => 1,
+        _ => {
+            total
+        }
+    }
+}
+";
+        let file = syn::parse_file(source).unwrap();
+        let points = get_line_injection_points(&file);
+        // Not in the const fn, and only the first point of each line
+        let lines: Vec<usize> = points.iter().map(|point| point.line()).collect();
+        assert_eq!(lines, vec![5, 6, 7, 9, 10, 12]);
+
+        let inst_file = inst_ast_points(file, points, "src/lib.rs", Rc::new(HashSet::new()));
+        let inst_source = quote!(#inst_file).to_string();
+        let logged_lines: Vec<usize> = inst_source.split("-.!;LINE_START;").skip(1)
+            .map(|rest| rest.split(';').next().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(logged_lines, vec![5, 6, 7, 9, 10, 12]);
+        assert!(inst_source.contains("-.!;LINE_START;9;src/lib.rs"));
+    }
+}
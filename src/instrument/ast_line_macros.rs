@@ -0,0 +1,141 @@
+use proc_macro2::{Delimiter, Group, LineColumn, Literal, Spacing, TokenStream, TokenTree};
+use syn::fold::Fold;
+use syn::spanned::Spanned;
+use syn::{Expr, ExprLit, File, ItemMacro, Lit, LitInt, Macro};
+
+#[derive(Clone, Debug)]
+struct InstContext {
+}
+
+/// Replace `line!()` and `column!()` with the original location as a literal
+///
+/// The instrumented file is reformatted, so the compiler would report the location in the instrumented code
+pub fn inst_ast_line_macros(file: File) -> File {
+    let mut ctx = InstContext {};
+    ctx.fold_file(file)
+}
+
+impl Fold for InstContext {
+    fn fold_expr(&mut self, node: Expr) -> Expr {
+        if let Expr::Macro(expr_macro) = &node {
+            let mac = &expr_macro.mac;
+            let name = mac.path.segments.last().map(|segment| segment.ident.to_string());
+            if let Some(literal) = name.and_then(|name| get_location_literal(&name, mac)) {
+                return Expr::Lit(ExprLit {
+                    attrs: expr_macro.attrs.clone(),
+                    lit: Lit::Int(LitInt::from(literal)),
+                });
+            }
+        }
+        syn::fold::fold_expr(self, node)
+    }
+
+    // In a macro definition, `line!()` refers to where the macro is used
+    fn fold_item_macro(&mut self, node: ItemMacro) -> ItemMacro {
+        match node.mac.path.is_ident("macro_rules") {
+            true => node,
+            false => syn::fold::fold_item_macro(self, node),
+        }
+    }
+
+    // E.g. `msg!("{}", line!())`. The arguments of macros are not parsed, so the tokens are replaced
+    fn fold_macro(&mut self, mut node: Macro) -> Macro {
+        node.tokens = replace_location_macros(node.tokens);
+        node
+    }
+}
+
+fn get_location_literal(name: &str, mac: &Macro) -> Option<Literal> {
+    if !mac.tokens.is_empty() {
+        return None;
+    }
+    get_literal(name, mac.path.span().start())
+}
+
+/// Unlike `proc_macro2::LineColumn`, `column!()` is 1-based
+fn get_literal(name: &str, start: LineColumn) -> Option<Literal> {
+    match name {
+        "line" => Some(Literal::u32_suffixed(start.line as u32)),
+        "column" => Some(Literal::u32_suffixed(start.column as u32 + 1)),
+        _ => None,
+    }
+}
+
+fn replace_location_macros(tokens: TokenStream) -> TokenStream {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let mut result = Vec::with_capacity(tokens.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        if let Some((path_start, literal)) = get_location_literal_from_tokens(&tokens, i) {
+            // The path before the name was already copied, e.g. `std ::` of `std::line!()`
+            result.truncate(result.len() - (i - path_start));
+            result.push(TokenTree::Literal(literal));
+            i += 3;
+            continue;
+        }
+        result.push(match &tokens[i] {
+            TokenTree::Group(group) => {
+                let mut new_group = Group::new(group.delimiter(), replace_location_macros(group.stream()));
+                new_group.set_span(group.span());
+                TokenTree::Group(new_group)
+            }
+            token => token.clone(),
+        });
+        i += 1;
+    }
+    result.into_iter().collect()
+}
+
+/// Match `line ! ()` at `i`, with a path before it like in `std::line!()`. Like in expressions, only the last segment is checked
+///
+/// Returns where the path starts, which is also the location of the macro
+fn get_location_literal_from_tokens(tokens: &[TokenTree], i: usize) -> Option<(usize, Literal)> {
+    let TokenTree::Ident(ident) = &tokens[i] else {
+        return None;
+    };
+    match (tokens.get(i + 1), tokens.get(i + 2)) {
+        (Some(TokenTree::Punct(bang)), Some(TokenTree::Group(args)))
+            if bang.as_char() == '!' && bang.spacing() == Spacing::Alone
+                && args.delimiter() == Delimiter::Parenthesis && args.stream().is_empty() => {}
+        _ => return None,
+    }
+    let path_start = get_path_start(tokens, i);
+    let literal = get_literal(&ident.to_string(), tokens[path_start].span().start())?;
+    Some((path_start, literal))
+}
+
+/// Where the path that ends with the identifier at `i` starts, e.g. at `std` for `std::line`
+fn get_path_start(tokens: &[TokenTree], i: usize) -> usize {
+    let is_colon = |token: &TokenTree, spacing: Spacing| matches!(token, TokenTree::Punct(punct) if punct.as_char() == ':' && punct.spacing() == spacing);
+    let mut start = i;
+    while start >= 2 && is_colon(&tokens[start - 1], Spacing::Alone) && is_colon(&tokens[start - 2], Spacing::Joint) {
+        start -= 2;
+        // A leading `::`, e.g. `::std::line`
+        if start == 0 || !matches!(tokens[start - 1], TokenTree::Ident(_)) {
+            break;
+        }
+        start -= 1;
+    }
+    start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_macros() {
+        let source = r#"
+fn f() {
+    let a = line!();
+    let b = std::line!();
+    msg!("{} {} {}", line!(), std::line!(), ::core::column!());
+}
+"#;
+        let file = inst_ast_line_macros(syn::parse_file(source).unwrap());
+        let output = prettyplease::unparse(&file);
+        assert!(output.contains("let a = 3u32;"));
+        assert!(output.contains("let b = 4u32;"));
+        assert!(output.contains(r#"msg!("{} {} {}", 5u32, 5u32, 45u32);"#));
+    }
+}
@@ -0,0 +1,38 @@
+use proc_macro2::TokenStream;
+use syn::fold::Fold;
+use syn::*;
+use crate::instrument::ast_fn_body::*;
+
+#[derive(Clone, Debug)]
+struct InstContext {
+    /// Logged with the function names, e.g. `src/processor.rs`
+    file_path: String,
+}
+
+/// Put a compute unit probe where a function is entered and left, and in front of every line with a statement
+///
+/// The costs between the probes are attributed to the functions and lines, see `parse_profile`
+pub fn inst_ast_profile(file: File, file_path: &str) -> File {
+    let mut fold = FnBodyFold::new(InstContext {
+        file_path: file_path.to_string(),
+    });
+    fold.fold_file(file)
+}
+
+impl FnBodyInst for InstContext {
+    fn get_line_stmt(&mut self, line: usize, _code: TokenStream) -> Option<Stmt> {
+        let probe_str = format!("-.!;P;L;{}", line);
+        Some(parse_quote! {
+            crate::_solana_debugger_serialize::_solana_debugger_probe(#probe_str);
+        })
+    }
+
+    /// Put the frame guard in front of the body. It probes again when the function is left, see `_SolanaDebuggerProfileFrame`
+    fn inst_fn_block(&mut self, mut block: Block, _sig: &Signature, name: &str) -> Block {
+        let enter_str = format!("-.!;P;ENTER;{};{}", name, self.file_path);
+        block.stmts.insert(0, parse_quote! {
+            let _solana_debugger_profile_frame = crate::_solana_debugger_serialize::_SolanaDebuggerProfileFrame::enter(#enter_str);
+        });
+        block
+    }
+}
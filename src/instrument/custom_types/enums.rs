@@ -1,15 +1,18 @@
 use proc_macro2::TokenStream;
 use quote::quote;
 use syn::{parse_quote, ItemEnum, ItemImpl, Variant};
+use crate::instrument::custom_types::get_cfg_attrs;
 
 pub fn get_serialize_impl(node: &ItemEnum) -> ItemImpl {
     let name = &node.ident;
+    let cfg_attrs = get_cfg_attrs(&node.attrs);
 
     let variant_str_arms = node.variants.iter().map(get_variant_str_arm);
 
     let variant_content_arms = node.variants.iter().map(get_variant_content_arm);
 
     parse_quote! {
+        #(#cfg_attrs)*
         impl crate::_solana_debugger_serialize::_SolanaDebuggerSerialize for #name {
             fn _solana_debugger_serialize(&self, name: &str) {
                 solana_program::log::sol_log("START_NODE");
@@ -44,20 +47,24 @@ pub fn get_serialize_impl(node: &ItemEnum) -> ItemImpl {
 fn get_variant_str_arm(variant: &Variant) -> TokenStream {
     let variant_name = &variant.ident;
     let variant_name_str = &variant.ident.to_string();
+    let cfg_attrs = get_cfg_attrs(&variant.attrs);
 
     match &variant.fields {
         syn::Fields::Named(_) => {
             quote! {
+                #(#cfg_attrs)*
                 Self::#variant_name { .. } => #variant_name_str
             }
         }
         syn::Fields::Unnamed(_) => {
             quote! {
+                #(#cfg_attrs)*
                 Self::#variant_name(..) => #variant_name_str
             }
         }
         syn::Fields::Unit => {
             quote! {
+                #(#cfg_attrs)*
                 Self::#variant_name => #variant_name_str
             }
         }
@@ -69,36 +76,53 @@ fn get_variant_str_arm(variant: &Variant) -> TokenStream {
 /// ```
 /// match self {
 ///     Ok(v) => {
-///         crate::_solana_debugger_serialize::_SolanaDebuggerSerialize::_solana_debugger_serialize(&v, "0");
+///         crate::_solana_debugger_serialize::_solana_debugger_serialize_value!(&v, "0");
 ///     },
 ///     Err(v) => {
-///         crate::_solana_debugger_serialize::_SolanaDebuggerSerialize::_solana_debugger_serialize(&v, "0");
+///         crate::_solana_debugger_serialize::_solana_debugger_serialize_value!(&v, "0");
 ///     }
 /// }
 /// ```
 
 fn get_variant_content_arm(variant: &Variant) -> TokenStream {
     let variant_name = &variant.ident;
+    let cfg_attrs = get_cfg_attrs(&variant.attrs);
 
     match &variant.fields {
         syn::Fields::Named(fields) => {
 
-            let field_names = fields.named.iter().map(|field| &field.ident);
+            let field_pats = fields.named.iter().map(|field| {
+                let field_name = &field.ident;
+                let field_cfg_attrs = get_cfg_attrs(&field.attrs);
+                quote! {
+                    #(#field_cfg_attrs)* #field_name
+                }
+            });
 
             let field_stmts = fields.named.iter().map(|field| {
                 let field_name = field.ident.as_ref().unwrap();
                 let field_name_str = field_name.to_string();
+                let field_cfg_attrs = get_cfg_attrs(&field.attrs);
                 quote! {
-                    crate::_solana_debugger_serialize::_SolanaDebuggerSerialize::_solana_debugger_serialize(&#field_name, #field_name_str);
+                    #(#field_cfg_attrs)*
+                    crate::_solana_debugger_serialize::_solana_debugger_serialize_value!(&#field_name, #field_name_str);
                 }
             });
 
             quote! {
-                Self::#variant_name { #(#field_names),* } => {
+                #(#cfg_attrs)*
+                Self::#variant_name { #(#field_pats),* } => {
                     #(#field_stmts)*
                 }
             }
         }
+        // Tuple patterns can't have attributes, so we can't bind fields that are behind a cfg
+        syn::Fields::Unnamed(fields) if fields.unnamed.iter().any(|field| !get_cfg_attrs(&field.attrs).is_empty()) => {
+            quote! {
+                #(#cfg_attrs)*
+                Self::#variant_name(..) => {}
+            }
+        }
         syn::Fields::Unnamed(fields) => {
 
             let field_names = (0..fields.unnamed.len()).map(|i| {
@@ -108,11 +132,12 @@ fn get_variant_content_arm(variant: &Variant) -> TokenStream {
             let field_stmts = field_names.iter().enumerate().map(|(i, var)| {
                 let index_str = format!("{}", i);
                 quote! {
-                    crate::_solana_debugger_serialize::_SolanaDebuggerSerialize::_solana_debugger_serialize(&*#var, #index_str);
+                    crate::_solana_debugger_serialize::_solana_debugger_serialize_value!(&*#var, #index_str);
                 }
             });
 
             quote! {
+                #(#cfg_attrs)*
                 Self::#variant_name(#(#field_names),*) => {
                     #(#field_stmts)*
                 }
@@ -120,6 +145,7 @@ fn get_variant_content_arm(variant: &Variant) -> TokenStream {
         }
         syn::Fields::Unit => {
             quote! {
+                #(#cfg_attrs)*
                 Self::#variant_name => {}
             }
         }
@@ -1,2 +1,37 @@
 pub mod structs;
-pub mod enums;
\ No newline at end of file
+pub mod enums;
+
+use syn::{Attribute, Meta, Token};
+use syn::punctuated::Punctuated;
+
+/// Get the `#[cfg(...)]` attributes of an item, field or variant, so that they can be copied onto the generated code
+pub fn get_cfg_attrs(attrs: &[Attribute]) -> Vec<&Attribute> {
+    attrs.iter().filter(|attr| attr.path().is_ident("cfg")).collect()
+}
+
+/// Check for `#[cfg(test)]` and other cfgs that only hold in tests, like `#[cfg(all(test, feature = "x"))]`
+pub fn is_cfg_test(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path().is_ident("cfg") && attr.parse_args::<Meta>().is_ok_and(|predicate| is_test_only_predicate(&predicate))
+    })
+}
+
+/// `all(..)` is test-only if one of its predicates is, `any(..)` if all are. `not(..)` never is
+fn is_test_only_predicate(predicate: &Meta) -> bool {
+    match predicate {
+        Meta::Path(path) => path.is_ident("test"),
+        Meta::List(list) => {
+            let Ok(predicates) = list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated) else {
+                return false;
+            };
+            if list.path.is_ident("all") {
+                predicates.iter().any(is_test_only_predicate)
+            } else if list.path.is_ident("any") {
+                !predicates.is_empty() && predicates.iter().all(is_test_only_predicate)
+            } else {
+                false
+            }
+        }
+        Meta::NameValue(_) => false,
+    }
+}
@@ -1,18 +1,40 @@
 use quote::quote;
-use syn::{parse_quote, ItemImpl, ItemStruct};
+use syn::{parse_quote, Attribute, ItemImpl, ItemStruct};
+use crate::instrument::custom_types::get_cfg_attrs;
 
-pub fn get_serialize_impl(node: &ItemStruct) -> ItemImpl {
+/// `None` for tuple structs with fields behind a cfg: the indices of the fields after them depend on the cfg.
+/// These structs are serialized with their `Debug` output, if any
+pub fn get_serialize_impl(node: &ItemStruct) -> Option<ItemImpl> {
 
     let name = &node.ident;
     let fields = &node.fields;
+    let cfg_attrs = get_cfg_attrs(&node.attrs);
+
+    // Fields of packed structs can't be referenced (E0793), so we serialize a copy of each field
+    let copy_fields = has_copyable_fields(&node.attrs);
+
+    if let syn::Fields::Unnamed(fields_unnamed) = fields {
+        if fields_unnamed.unnamed.iter().any(|field| !get_cfg_attrs(&field.attrs).is_empty()) {
+            return None;
+        }
+    }
 
     let serialize_fields = match fields {
         syn::Fields::Named(fields_named) => {
             let field_statements = fields_named.named.iter().map(|field| {
                 let field_name = field.ident.as_ref().unwrap();
                 let field_name_str = field_name.to_string();
-                quote! {
-                    crate::_solana_debugger_serialize::_SolanaDebuggerSerialize::_solana_debugger_serialize(&self.#field_name, #field_name_str);
+                let field_cfg_attrs = get_cfg_attrs(&field.attrs);
+                if copy_fields {
+                    quote! {
+                        #(#field_cfg_attrs)*
+                        crate::_solana_debugger_serialize::_solana_debugger_serialize_value!(&{ self.#field_name }, #field_name_str);
+                    }
+                } else {
+                    quote! {
+                        #(#field_cfg_attrs)*
+                        crate::_solana_debugger_serialize::_solana_debugger_serialize_value!(&self.#field_name, #field_name_str);
+                    }
                 }
             });
             quote! {
@@ -20,11 +42,20 @@ pub fn get_serialize_impl(node: &ItemStruct) -> ItemImpl {
             }
         },
         syn::Fields::Unnamed(fields_unnamed) => {
-            let field_statements = fields_unnamed.unnamed.iter().enumerate().map(|(i, _)| {
+            let field_statements = fields_unnamed.unnamed.iter().enumerate().map(|(i, field)| {
                 let index = syn::Index::from(i);
                 let index_str = format!("{}", i);
-                quote! {
-                    crate::_solana_debugger_serialize::_SolanaDebuggerSerialize::_solana_debugger_serialize(&self.#index, #index_str);
+                let field_cfg_attrs = get_cfg_attrs(&field.attrs);
+                if copy_fields {
+                    quote! {
+                        #(#field_cfg_attrs)*
+                        crate::_solana_debugger_serialize::_solana_debugger_serialize_value!(&{ self.#index }, #index_str);
+                    }
+                } else {
+                    quote! {
+                        #(#field_cfg_attrs)*
+                        crate::_solana_debugger_serialize::_solana_debugger_serialize_value!(&self.#index, #index_str);
+                    }
                 }
             });
             quote! {
@@ -36,7 +67,8 @@ pub fn get_serialize_impl(node: &ItemStruct) -> ItemImpl {
         }
     };
 
-    parse_quote! {
+    Some(parse_quote! {
+        #(#cfg_attrs)*
         impl crate::_solana_debugger_serialize::_SolanaDebuggerSerialize for #name {
             fn _solana_debugger_serialize(&self, name: &str) {
                 solana_program::log::sol_log("START_NODE");
@@ -50,5 +82,37 @@ pub fn get_serialize_impl(node: &ItemStruct) -> ItemImpl {
                 solana_program::log::sol_log("END_NODE");
             }
         }
-    }
-}
\ No newline at end of file
+    })
+}
+
+/// Check if the struct is packed, so its fields must be copied
+///
+/// - `#[repr(packed)]`, `#[repr(C, packed)]`, `#[repr(packed(N))]`
+/// - Anchor: `#[zero_copy(unsafe)]` and `#[account(zero_copy(unsafe))]` add `repr(packed)`, which is not visible here.
+///   Plain `#[zero_copy]` and bytemuck `Pod` structs are `repr(C)`, their fields are serialized by reference.
+///   Copying them would put large array fields on the small SBF stack
+fn has_copyable_fields(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        let path = attr.path();
+        if path.is_ident("repr") {
+            let mut is_packed = false;
+            let _ = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("packed") {
+                    is_packed = true;
+                }
+                // Skip `(N)` of `packed(N)` and `align(N)`
+                if meta.input.peek(syn::token::Paren) {
+                    let _content;
+                    syn::parenthesized!(_content in meta.input);
+                }
+                Ok(())
+            });
+            is_packed
+        } else if path.is_ident("zero_copy") || path.is_ident("account") {
+            let tokens = &attr.meta;
+            quote!(#tokens).to_string().replace(' ', "").contains("zero_copy(unsafe)")
+        } else {
+            false
+        }
+    })
+}
@@ -1,30 +1,254 @@
-use syn::{parse_quote, File};
+use syn::{parse_quote, File, Item};
 
-pub fn get_fixed_serialization() -> File {
-    parse_quote! {
+/// `signature_type`: how the package names `Signature`, if it can. There's no serializer for it otherwise
+pub fn get_fixed_serialization(signature_type: Option<&syn::Path>) -> File {
+    let mut file: File = parse_quote! {
 
         use solana_program::log::{sol_log, sol_log_data};
         use std::any::type_name_of_val;
-        use std::cell::RefCell;
+        use std::fmt::Debug;
+        use std::borrow::Cow;
+        use std::cell::{Cell, RefCell};
+        use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
         use std::rc::Rc;
+        use std::sync::Arc;
         use solana_program::account_info::AccountInfo;
         use solana_program::pubkey::Pubkey;
 
         pub trait _SolanaDebuggerSerialize {
             fn _solana_debugger_serialize(&self, name: &str);
+
+            // How a reference to the value is logged: a node with the value as its child.
+            // str and slices log the reference like the value itself
+            fn _solana_debugger_serialize_ref(&self, name: &str, type_name: &str) {
+                sol_log("START_NODE");
+                sol_log("complex");
+                sol_log(name);
+                sol_log(type_name);
+                sol_log("no_data");
+                self._solana_debugger_serialize("value");
+                sol_log("END_NODE");
+            }
+        }
+
+        // Used by the generated code to log a value, see `_solana_debugger_serialize_value!`.
+        // The traits are implemented for different levels of references, so method resolution picks the first one that applies:
+        // the serializer, a container with Debug leaves, the Debug output, a container with leaves that only show their type, nothing
+        pub struct _SolanaDebuggerValue<'a, T: ?Sized>(pub &'a T);
+
+        pub trait _SolanaDebuggerViaSerialize {
+            fn _solana_debugger_serialize_value(&self, name: &str);
+        }
+
+        impl<'a, T: _SolanaDebuggerSerialize + ?Sized> _SolanaDebuggerViaSerialize for &&&&_SolanaDebuggerValue<'a, T> {
+            fn _solana_debugger_serialize_value(&self, name: &str) {
+                self.0._solana_debugger_serialize(name);
+            }
+        }
+
+        pub trait _SolanaDebuggerViaDebugLeaves {
+            fn _solana_debugger_serialize_value(&self, name: &str);
+        }
+
+        impl<'a, T: _SolanaDebuggerSerializeWith<_SolanaDebuggerDebugLeaf> + ?Sized> _SolanaDebuggerViaDebugLeaves for &&&_SolanaDebuggerValue<'a, T> {
+            fn _solana_debugger_serialize_value(&self, name: &str) {
+                <T as _SolanaDebuggerSerializeWith<_SolanaDebuggerDebugLeaf>>::_solana_debugger_serialize_with(self.0, name);
+            }
+        }
+
+        // Fallback for types without a dedicated serializer: use their Debug output
+        pub trait _SolanaDebuggerViaDebug {
+            fn _solana_debugger_serialize_value(&self, name: &str);
+        }
+
+        impl<'a, T: Debug + ?Sized> _SolanaDebuggerViaDebug for &&_SolanaDebuggerValue<'a, T> {
+            fn _solana_debugger_serialize_value(&self, name: &str) {
+                serialize_debug(self.0, name);
+            }
+        }
+
+        pub trait _SolanaDebuggerViaNoneLeaves {
+            fn _solana_debugger_serialize_value(&self, name: &str);
+        }
+
+        impl<'a, T: _SolanaDebuggerSerializeWith<_SolanaDebuggerNoneLeaf> + ?Sized> _SolanaDebuggerViaNoneLeaves for &_SolanaDebuggerValue<'a, T> {
+            fn _solana_debugger_serialize_value(&self, name: &str) {
+                <T as _SolanaDebuggerSerializeWith<_SolanaDebuggerNoneLeaf>>::_solana_debugger_serialize_with(self.0, name);
+            }
+        }
+
+        pub trait _SolanaDebuggerViaNone {
+            fn _solana_debugger_serialize_value(&self, name: &str);
+        }
+
+        impl<'a, T: ?Sized> _SolanaDebuggerViaNone for _SolanaDebuggerValue<'a, T> {
+            fn _solana_debugger_serialize_value(&self, name: &str) {
+                serialize_not_implemented(self.0, name);
+            }
+        }
+
+        // Log the value behind the reference `$value` as the node `$name`
+        macro_rules! _solana_debugger_serialize_value {
+            ($value:expr, $name:expr) => {{
+                #[allow(unused_imports)]
+                use crate::_solana_debugger_serialize::{
+                    _SolanaDebuggerViaSerialize as _, _SolanaDebuggerViaDebugLeaves as _, _SolanaDebuggerViaDebug as _,
+                    _SolanaDebuggerViaNoneLeaves as _, _SolanaDebuggerViaNone as _,
+                };
+                (&&&&&crate::_solana_debugger_serialize::_SolanaDebuggerValue($value))._solana_debugger_serialize_value($name)
+            }};
+        }
+
+        pub(crate) use _solana_debugger_serialize_value;
+
+        fn serialize_debug<T: Debug + ?Sized>(value: &T, name: &str) {
+            sol_log("START_NODE");
+            sol_log("primitive");
+            sol_log(name);
+            sol_log(type_name_of_val(value));
+            sol_log("debug_str");
+            sol_log(&format!("{:?}", value));
+            sol_log("END_NODE");
+        }
+
+        fn serialize_not_implemented<T: ?Sized>(value: &T, name: &str) {
+            sol_log("START_NODE");
+            sol_log("complex");
+            sol_log(name);
+            sol_log(type_name_of_val(value));
+            sol_log("not_implemented");
+            sol_log("END_NODE");
         }
 
-        impl<T: ?Sized> _SolanaDebuggerSerialize for T {
-            default fn _solana_debugger_serialize(&self, name: &str) {
+        // How the elements of containers are logged, see `_SolanaDebuggerSerializeWith`
+        pub trait _SolanaDebuggerLeaf<T: ?Sized> {
+            fn _solana_debugger_serialize_leaf(value: &T, name: &str);
+        }
+
+        pub struct _SolanaDebuggerSerializeLeaf;
+
+        impl<T: _SolanaDebuggerSerialize + ?Sized> _SolanaDebuggerLeaf<T> for _SolanaDebuggerSerializeLeaf {
+            fn _solana_debugger_serialize_leaf(value: &T, name: &str) {
+                value._solana_debugger_serialize(name);
+            }
+        }
+
+        pub struct _SolanaDebuggerDebugLeaf;
+
+        impl<T: Debug + ?Sized> _SolanaDebuggerLeaf<T> for _SolanaDebuggerDebugLeaf {
+            fn _solana_debugger_serialize_leaf(value: &T, name: &str) {
+                serialize_debug(value, name);
+            }
+        }
+
+        pub struct _SolanaDebuggerNoneLeaf;
+
+        impl<T: ?Sized> _SolanaDebuggerLeaf<T> for _SolanaDebuggerNoneLeaf {
+            fn _solana_debugger_serialize_leaf(value: &T, name: &str) {
+                serialize_not_implemented(value, name);
+            }
+        }
+
+        // Containers log their elements with the leaf `L`. With `_SolanaDebuggerSerializeLeaf` this is their serializer.
+        // The elements of a container can't pick a fallback on their own: that would need specialization on `Debug`
+        pub trait _SolanaDebuggerSerializeWith<L> {
+            fn _solana_debugger_serialize_with(&self, name: &str);
+
+            // Like `_SolanaDebuggerSerialize::_solana_debugger_serialize_ref`
+            fn _solana_debugger_serialize_ref_with(&self, name: &str, type_name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
-                sol_log(type_name_of_val(self));
-                sol_log("not_implemented");
+                sol_log(type_name);
+                sol_log("no_data");
+                <Self as _SolanaDebuggerSerializeWith<L>>::_solana_debugger_serialize_with(self, "value");
                 sol_log("END_NODE");
             }
         }
 
+        // Containers have a serializer if all of their elements have one
+        macro_rules! impl_serialize_with_leaves {
+            ([$($generics:tt)*] $type:ty) => {
+                impl<$($generics)*> _SolanaDebuggerSerialize for $type
+                where
+                    $type: _SolanaDebuggerSerializeWith<_SolanaDebuggerSerializeLeaf>,
+                {
+                    fn _solana_debugger_serialize(&self, name: &str) {
+                        _SolanaDebuggerSerializeWith::<_SolanaDebuggerSerializeLeaf>::_solana_debugger_serialize_with(self, name);
+                    }
+                }
+            }
+        }
+
+        // Logged instead of a value that can't be accessed there, e.g. a parameter that the function moved
+        pub fn _solana_debugger_serialize_unavailable(name: &str, reason: &str) {
+            sol_log("START_NODE");
+            sol_log("primitive");
+            sol_log(name);
+            sol_log("");
+            sol_log("unavailable");
+            sol_log(reason);
+            sol_log("END_NODE");
+        }
+
+        // Used by the break-on-error instrumentation to check the operand of `?`
+        pub trait _SolanaDebuggerIsErr {
+            fn _solana_debugger_is_err(&self) -> bool;
+        }
+
+        impl<T: ?Sized> _SolanaDebuggerIsErr for T {
+            default fn _solana_debugger_is_err(&self) -> bool {
+                false
+            }
+        }
+
+        impl<T, E> _SolanaDebuggerIsErr for Result<T, E> {
+            fn _solana_debugger_is_err(&self) -> bool {
+                self.is_err()
+            }
+        }
+
+        // Used by the call stack instrumentation. Logs when the function is left, also on early returns and `?`
+        pub struct _SolanaDebuggerFrame;
+
+        impl _SolanaDebuggerFrame {
+            pub fn enter(marker: &str) -> Self {
+                sol_log(marker);
+                _SolanaDebuggerFrame
+            }
+        }
+
+        impl Drop for _SolanaDebuggerFrame {
+            fn drop(&mut self) {
+                sol_log("-.!;FN_EXIT");
+            }
+        }
+
+        // Used by the profiler. Logs the marker, then the remaining compute units
+        #[inline(never)]
+        pub fn _solana_debugger_probe(marker: &str) {
+            sol_log(marker);
+            solana_program::log::sol_log_compute_units();
+        }
+
+        // Used by the profiler. Probes when the function is entered and left
+        pub struct _SolanaDebuggerProfileFrame;
+
+        impl _SolanaDebuggerProfileFrame {
+            pub fn enter(marker: &str) -> Self {
+                // Nothing happens between these probes, so they measure the cost of a probe
+                _solana_debugger_probe("-.!;P;CAL");
+                _solana_debugger_probe(marker);
+                _SolanaDebuggerProfileFrame
+            }
+        }
+
+        impl Drop for _SolanaDebuggerProfileFrame {
+            fn drop(&mut self) {
+                _solana_debugger_probe("-.!;P;EXIT");
+            }
+        }
+
         macro_rules! impl_serialize {
             ($type:ty, $is_complex:expr, $ser_type:expr, $data_ser:expr) => {
                 impl _SolanaDebuggerSerialize for $type {
@@ -83,6 +307,42 @@ pub fn get_fixed_serialization() -> File {
         impl_serialize_uint!(u128);
         impl_serialize_uint!(usize);
 
+        macro_rules! impl_serialize_float {
+            ($type:ty) => {
+                impl_serialize!(
+                    $type,
+                    false,
+                    "float",
+                    |s: &$type| {
+                        let bytes = s.to_le_bytes();
+                        sol_log_data(&[bytes.as_slice()]);
+                    }
+                );
+            }
+        }
+
+        impl_serialize_float!(f32);
+        impl_serialize_float!(f64);
+
+        impl_serialize!(
+            char,
+            false,
+            "char",
+            |s: &char| {
+                let bytes = (*s as u32).to_le_bytes();
+                sol_log_data(&[bytes.as_slice()]);
+            }
+        );
+
+        impl_serialize!(
+            (),
+            false,
+            "str_ident",
+            |_s: &()| {
+                sol_log("()");
+            }
+        );
+
         impl _SolanaDebuggerSerialize for bool {
             fn _solana_debugger_serialize(&self, name: &str) {
                 sol_log("START_NODE");
@@ -95,12 +355,16 @@ pub fn get_fixed_serialization() -> File {
             }
         }
 
-        impl _SolanaDebuggerSerialize for &str {
+        impl _SolanaDebuggerSerialize for str {
             fn _solana_debugger_serialize(&self, name: &str) {
+                self._solana_debugger_serialize_ref(name, type_name_of_val(self));
+            }
+
+            fn _solana_debugger_serialize_ref(&self, name: &str, type_name: &str) {
                 sol_log("START_NODE");
                 sol_log("primitive");
                 sol_log(name);
-                sol_log(type_name_of_val(self));
+                sol_log(type_name);
                 sol_log("str");
                 sol_log(self);
                 sol_log("END_NODE");
@@ -119,8 +383,8 @@ pub fn get_fixed_serialization() -> File {
             }
         }
 
-        impl<T> _SolanaDebuggerSerialize for Option<T> {
-            fn _solana_debugger_serialize(&self, name: &str) {
+        impl<T, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for Option<T> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
@@ -132,14 +396,16 @@ pub fn get_fixed_serialization() -> File {
                 };
                 sol_log(variant_str);
                 if let Some(v) = self {
-                    v._solana_debugger_serialize("0");
+                    L::_solana_debugger_serialize_leaf(v, "0");
                 }
                 sol_log("END_NODE");
             }
         }
 
-        impl<T, E> _SolanaDebuggerSerialize for Result<T, E> {
-            fn _solana_debugger_serialize(&self, name: &str) {
+        impl_serialize_with_leaves!([T] Option<T>);
+
+        impl<T, E, L: _SolanaDebuggerLeaf<T> + _SolanaDebuggerLeaf<E>> _SolanaDebuggerSerializeWith<L> for Result<T, E> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
@@ -152,59 +418,138 @@ pub fn get_fixed_serialization() -> File {
                 sol_log(variant_str);
                 match self {
                     Ok(v) => {
-                        v._solana_debugger_serialize("0");
+                        <L as _SolanaDebuggerLeaf<T>>::_solana_debugger_serialize_leaf(v, "0");
                     },
                     Err(v) => {
-                        v._solana_debugger_serialize("0");
+                        <L as _SolanaDebuggerLeaf<E>>::_solana_debugger_serialize_leaf(v, "0");
                     }
                 }
                 sol_log("END_NODE");
             }
         }
 
-        impl<T1, T2> _SolanaDebuggerSerialize for (T1, T2) {
-            fn _solana_debugger_serialize(&self, name: &str) {
+        impl_serialize_with_leaves!([T, E] Result<T, E>);
+
+        macro_rules! impl_serialize_tuple {
+            ($($type:ident $index:tt),+) => {
+                impl<$($type),+, L: $(_SolanaDebuggerLeaf<$type> +)+> _SolanaDebuggerSerializeWith<L> for ($($type,)+) {
+                    fn _solana_debugger_serialize_with(&self, name: &str) {
+                        sol_log("START_NODE");
+                        sol_log("complex");
+                        sol_log(name);
+                        sol_log(type_name_of_val(self));
+                        sol_log("no_data");
+                        $(<L as _SolanaDebuggerLeaf<$type>>::_solana_debugger_serialize_leaf(&self.$index, stringify!($index));)+
+                        sol_log("END_NODE");
+                    }
+                }
+
+                impl_serialize_with_leaves!([$($type),+] ($($type,)+));
+            }
+        }
+
+        impl_serialize_tuple!(T0 0);
+        impl_serialize_tuple!(T0 0, T1 1);
+        impl_serialize_tuple!(T0 0, T1 1, T2 2);
+        impl_serialize_tuple!(T0 0, T1 1, T2 2, T3 3);
+        impl_serialize_tuple!(T0 0, T1 1, T2 2, T3 3, T4 4);
+        impl_serialize_tuple!(T0 0, T1 1, T2 2, T3 3, T4 4, T5 5);
+        impl_serialize_tuple!(T0 0, T1 1, T2 2, T3 3, T4 4, T5 5, T6 6);
+        impl_serialize_tuple!(T0 0, T1 1, T2 2, T3 3, T4 4, T5 5, T6 6, T7 7);
+        impl_serialize_tuple!(T0 0, T1 1, T2 2, T3 3, T4 4, T5 5, T6 6, T7 7, T8 8);
+        impl_serialize_tuple!(T0 0, T1 1, T2 2, T3 3, T4 4, T5 5, T6 6, T7 7, T8 8, T9 9);
+        impl_serialize_tuple!(T0 0, T1 1, T2 2, T3 3, T4 4, T5 5, T6 6, T7 7, T8 8, T9 9, T10 10);
+        impl_serialize_tuple!(T0 0, T1 1, T2 2, T3 3, T4 4, T5 5, T6 6, T7 7, T8 8, T9 9, T10 10, T11 11);
+
+        impl<T: ?Sized, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for Box<T> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
                 sol_log("no_data");
-                self.0._solana_debugger_serialize("0");
-                self.1._solana_debugger_serialize("1");
+                L::_solana_debugger_serialize_leaf(&**self, "value");
                 sol_log("END_NODE");
             }
         }
 
-        impl<T> _SolanaDebuggerSerialize for Box<T> {
-            fn _solana_debugger_serialize(&self, name: &str) {
+        impl_serialize_with_leaves!([T: ?Sized] Box<T>);
+
+        impl<T: ?Sized, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for Rc<T> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
-                sol_log("no_data");
-                (**self)._solana_debugger_serialize("value");
+                sol_log("rc_meta");
+                let strong_count = (Rc::strong_count(self) as u128).to_le_bytes();
+                sol_log_data(&[strong_count.as_slice()]);
+                let weak_count = (Rc::weak_count(self) as u128).to_le_bytes();
+                sol_log_data(&[weak_count.as_slice()]);
+                L::_solana_debugger_serialize_leaf(&**self, "value");
                 sol_log("END_NODE");
             }
         }
 
-        impl<T> _SolanaDebuggerSerialize for Rc<T> {
-            fn _solana_debugger_serialize(&self, name: &str) {
+        impl_serialize_with_leaves!([T: ?Sized] Rc<T>);
+
+        impl<T: ?Sized, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for Arc<T> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
                 sol_log("rc_meta");
-                let strong_count = (Rc::strong_count(self) as u128).to_le_bytes();
+                let strong_count = (Arc::strong_count(self) as u128).to_le_bytes();
                 sol_log_data(&[strong_count.as_slice()]);
-                let weak_count = (Rc::weak_count(self) as u128).to_le_bytes();
+                let weak_count = (Arc::weak_count(self) as u128).to_le_bytes();
                 sol_log_data(&[weak_count.as_slice()]);
-                (**self)._solana_debugger_serialize("value");
+                L::_solana_debugger_serialize_leaf(&**self, "value");
                 sol_log("END_NODE");
             }
         }
 
-        impl<T> _SolanaDebuggerSerialize for RefCell<T> {
-            fn _solana_debugger_serialize(&self, name: &str) {
+        impl_serialize_with_leaves!([T: ?Sized] Arc<T>);
+
+        impl<T: Copy, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for Cell<T> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                sol_log("START_NODE");
+                sol_log("complex");
+                sol_log(name);
+                sol_log(type_name_of_val(self));
+                sol_log("no_data");
+                L::_solana_debugger_serialize_leaf(&self.get(), "value");
+                sol_log("END_NODE");
+            }
+        }
+
+        impl_serialize_with_leaves!([T: Copy] Cell<T>);
+
+        impl<'a, B: ToOwned + ?Sized + 'a, L: _SolanaDebuggerLeaf<&'a B> + _SolanaDebuggerLeaf<B::Owned>> _SolanaDebuggerSerializeWith<L> for Cow<'a, B> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                sol_log("START_NODE");
+                sol_log("complex");
+                sol_log(name);
+                sol_log(type_name_of_val(self));
+                sol_log("str_ident");
+                match self {
+                    Cow::Borrowed(v) => {
+                        sol_log("Borrowed");
+                        <L as _SolanaDebuggerLeaf<&'a B>>::_solana_debugger_serialize_leaf(v, "0");
+                    },
+                    Cow::Owned(v) => {
+                        sol_log("Owned");
+                        <L as _SolanaDebuggerLeaf<B::Owned>>::_solana_debugger_serialize_leaf(v, "0");
+                    }
+                }
+                sol_log("END_NODE");
+            }
+        }
+
+        impl_serialize_with_leaves!(['a, B: ToOwned + ?Sized + 'a] Cow<'a, B>);
+
+        impl<T: ?Sized, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for RefCell<T> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
@@ -212,7 +557,7 @@ pub fn get_fixed_serialization() -> File {
 
                 if let Ok(v) = self.try_borrow() {
                     sol_log("no_data");
-                    (*v)._solana_debugger_serialize("value");
+                    L::_solana_debugger_serialize_leaf(&*v, "value");
                 } else {
                     sol_log("error_str");
                     sol_log("Failed to borrow");
@@ -222,178 +567,379 @@ pub fn get_fixed_serialization() -> File {
             }
         }
 
-        impl<T> _SolanaDebuggerSerialize for Vec<T> {
+        impl_serialize_with_leaves!([T: ?Sized] RefCell<T>);
+
+        fn serialize_seq<'a, T: 'a, L: _SolanaDebuggerLeaf<T>>(name: &str, type_name: &str, len: usize, iter: impl Iterator<Item = &'a T>) {
+            sol_log("START_NODE");
+            sol_log("complex");
+            sol_log(name);
+            sol_log(type_name);
+
+            sol_log("array_len");
+
+            let len = (len as u128).to_le_bytes();
+            sol_log_data(&[len.as_slice()]);
+
+            for el in iter {
+                L::_solana_debugger_serialize_leaf(el, "-inc-index");
+            }
+
+            sol_log("END_NODE");
+        }
+
+        // Entries are logged as pairs of child nodes: first the key, then the value
+        fn serialize_map<'a, K: 'a, V: 'a, L: _SolanaDebuggerLeaf<K> + _SolanaDebuggerLeaf<V>>(name: &str, type_name: &str, len: usize, iter: impl Iterator<Item = (&'a K, &'a V)>) {
+            sol_log("START_NODE");
+            sol_log("complex");
+            sol_log(name);
+            sol_log(type_name);
+
+            sol_log("map_len");
+
+            let len = (len as u128).to_le_bytes();
+            sol_log_data(&[len.as_slice()]);
+
+            for (key, value) in iter {
+                <L as _SolanaDebuggerLeaf<K>>::_solana_debugger_serialize_leaf(key, "key");
+                <L as _SolanaDebuggerLeaf<V>>::_solana_debugger_serialize_leaf(value, "value");
+            }
+
+            sol_log("END_NODE");
+        }
+
+        impl<T, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for Vec<T> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                serialize_seq::<T, L>(name, type_name_of_val(self), self.len(), self.iter());
+            }
+        }
+
+        impl_serialize_with_leaves!([T] Vec<T>);
+
+        impl<T, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for VecDeque<T> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                serialize_seq::<T, L>(name, type_name_of_val(self), self.len(), self.iter());
+            }
+        }
+
+        impl_serialize_with_leaves!([T] VecDeque<T>);
+
+        impl<T, S, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for HashSet<T, S> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                serialize_seq::<T, L>(name, type_name_of_val(self), self.len(), self.iter());
+            }
+        }
+
+        impl_serialize_with_leaves!([T, S] HashSet<T, S>);
+
+        impl<T, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for BTreeSet<T> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                serialize_seq::<T, L>(name, type_name_of_val(self), self.len(), self.iter());
+            }
+        }
+
+        impl_serialize_with_leaves!([T] BTreeSet<T>);
+
+        impl<K, V, S, L: _SolanaDebuggerLeaf<K> + _SolanaDebuggerLeaf<V>> _SolanaDebuggerSerializeWith<L> for HashMap<K, V, S> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                serialize_map::<K, V, L>(name, type_name_of_val(self), self.len(), self.iter());
+            }
+        }
+
+        impl_serialize_with_leaves!([K, V, S] HashMap<K, V, S>);
+
+        impl<K, V, L: _SolanaDebuggerLeaf<K> + _SolanaDebuggerLeaf<V>> _SolanaDebuggerSerializeWith<L> for BTreeMap<K, V> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                serialize_map::<K, V, L>(name, type_name_of_val(self), self.len(), self.iter());
+            }
+        }
+
+        impl_serialize_with_leaves!([K, V] BTreeMap<K, V>);
+
+        impl<T, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for [T] {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                <Self as _SolanaDebuggerSerializeWith<L>>::_solana_debugger_serialize_ref_with(self, name, type_name_of_val(self));
+            }
+
+            fn _solana_debugger_serialize_ref_with(&self, name: &str, type_name: &str) {
+                serialize_seq::<T, L>(name, type_name, self.len(), self.iter());
+            }
+        }
+
+        impl<T> _SolanaDebuggerSerialize for [T]
+        where
+            [T]: _SolanaDebuggerSerializeWith<_SolanaDebuggerSerializeLeaf>,
+        {
             fn _solana_debugger_serialize(&self, name: &str) {
+                _SolanaDebuggerSerializeWith::<_SolanaDebuggerSerializeLeaf>::_solana_debugger_serialize_with(self, name);
+            }
+
+            fn _solana_debugger_serialize_ref(&self, name: &str, type_name: &str) {
+                _SolanaDebuggerSerializeWith::<_SolanaDebuggerSerializeLeaf>::_solana_debugger_serialize_ref_with(self, name, type_name);
+            }
+        }
+
+        // T may be unsized, e.g. &dyn Trait
+        impl<T: _SolanaDebuggerSerialize + ?Sized> _SolanaDebuggerSerialize for &T {
+            fn _solana_debugger_serialize(&self, name: &str) {
+                (**self)._solana_debugger_serialize_ref(name, type_name_of_val(self));
+            }
+        }
+
+        impl<T: _SolanaDebuggerSerialize + ?Sized> _SolanaDebuggerSerialize for &mut T {
+            fn _solana_debugger_serialize(&self, name: &str) {
+                (**self)._solana_debugger_serialize_ref(name, type_name_of_val(self));
+            }
+        }
+
+        impl<T: _SolanaDebuggerSerializeWith<L> + ?Sized, L> _SolanaDebuggerSerializeWith<L> for &T {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                <T as _SolanaDebuggerSerializeWith<L>>::_solana_debugger_serialize_ref_with(*self, name, type_name_of_val(self));
+            }
+        }
+
+        impl<T: _SolanaDebuggerSerializeWith<L> + ?Sized, L> _SolanaDebuggerSerializeWith<L> for &mut T {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                <T as _SolanaDebuggerSerializeWith<L>>::_solana_debugger_serialize_ref_with(*self, name, type_name_of_val(self));
+            }
+        }
+
+        impl<'a, T: ?Sized, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for core::cell::Ref<'a, T> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
+                sol_log("no_data");
 
-                sol_log("array_len");
+                L::_solana_debugger_serialize_leaf(&**self, "value");
 
-                let len = (self.len() as u128).to_le_bytes();
-                sol_log_data(&[len.as_slice()]);
+                sol_log("END_NODE");
+            }
+        }
 
-                for el in self {
-                    el._solana_debugger_serialize("-inc-index");
-                }
+        impl_serialize_with_leaves!(['a, T: ?Sized] core::cell::Ref<'a, T>);
+
+        impl<'a, T: ?Sized, L: _SolanaDebuggerLeaf<T>> _SolanaDebuggerSerializeWith<L> for core::cell::RefMut<'a, T> {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                sol_log("START_NODE");
+                sol_log("complex");
+                sol_log(name);
+                sol_log(type_name_of_val(self));
+                sol_log("no_data");
+
+                L::_solana_debugger_serialize_leaf(&**self, "value");
 
                 sol_log("END_NODE");
             }
         }
 
-        impl<T> _SolanaDebuggerSerialize for &[T] {
+        impl_serialize_with_leaves!(['a, T: ?Sized] core::cell::RefMut<'a, T>);
+
+        impl<T, L: _SolanaDebuggerLeaf<T>, const N: usize> _SolanaDebuggerSerializeWith<L> for [T; N] {
+            fn _solana_debugger_serialize_with(&self, name: &str) {
+                serialize_seq::<T, L>(name, type_name_of_val(self), N, self.iter());
+            }
+        }
+
+        impl_serialize_with_leaves!([T, const N: usize] [T; N]);
+
+        impl<'a> _SolanaDebuggerSerialize for AccountInfo<'a> {
             fn _solana_debugger_serialize(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
+                sol_log("no_data");
 
-                sol_log("array_len");
-
-                let len = (self.len() as u128).to_le_bytes();
-                sol_log_data(&[len.as_slice()]);
-
-                for el in (*self).iter() {
-                    el._solana_debugger_serialize("-inc-index");
-                }
+                self.key._solana_debugger_serialize("key");
+                self.lamports._solana_debugger_serialize("lamports");
+                self.data._solana_debugger_serialize("data");
+                self.owner._solana_debugger_serialize("owner");
+                self.rent_epoch._solana_debugger_serialize("rent_epoch");
+                self.is_signer._solana_debugger_serialize("is_signer");
+                self.is_writable._solana_debugger_serialize("is_writable");
+                self.executable._solana_debugger_serialize("executable");
 
                 sol_log("END_NODE");
             }
         }
 
-        impl<T> _SolanaDebuggerSerialize for &mut [T] {
+        impl _SolanaDebuggerSerialize for Pubkey {
             fn _solana_debugger_serialize(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
 
-                sol_log("array_len");
-
-                let len = (self.len() as u128).to_le_bytes();
-                sol_log_data(&[len.as_slice()]);
+                sol_log("pubkey");
 
-                for el in (*self).iter() {
-                    el._solana_debugger_serialize("-inc-index");
-                }
+                sol_log_data(&[self.as_ref()]);
 
                 sol_log("END_NODE");
             }
         }
-        impl<T: Sized> _SolanaDebuggerSerialize for &T {
+
+        impl _SolanaDebuggerSerialize for solana_program::sysvar::rent::Rent {
             fn _solana_debugger_serialize(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
+
                 sol_log("no_data");
 
-                (**self)._solana_debugger_serialize("value");
+                self.lamports_per_byte_year._solana_debugger_serialize("lamports_per_byte_year");
+                self.exemption_threshold._solana_debugger_serialize("exemption_threshold");
+                self.burn_percent._solana_debugger_serialize("burn_percent");
 
                 sol_log("END_NODE");
             }
         }
 
-        impl<T> _SolanaDebuggerSerialize for &mut T {
+        impl _SolanaDebuggerSerialize for solana_program::instruction::Instruction {
             fn _solana_debugger_serialize(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
+
                 sol_log("no_data");
 
-                (**self)._solana_debugger_serialize("value");
+                self.program_id._solana_debugger_serialize("program_id");
+                self.accounts._solana_debugger_serialize("accounts");
+                self.data._solana_debugger_serialize("data");
 
                 sol_log("END_NODE");
             }
         }
 
-        impl<'a, T> _SolanaDebuggerSerialize for core::cell::Ref<'a, T> {
+        impl _SolanaDebuggerSerialize for solana_program::instruction::AccountMeta {
             fn _solana_debugger_serialize(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
+
                 sol_log("no_data");
 
-                crate::_solana_debugger_serialize::_SolanaDebuggerSerialize::_solana_debugger_serialize(&**self, "value");
+                self.pubkey._solana_debugger_serialize("pubkey");
+                self.is_signer._solana_debugger_serialize("is_signer");
+                self.is_writable._solana_debugger_serialize("is_writable");
 
                 sol_log("END_NODE");
             }
         }
 
-        impl<'a, T> _SolanaDebuggerSerialize for core::cell::RefMut<'a, T> {
+        impl _SolanaDebuggerSerialize for solana_program::hash::Hash {
             fn _solana_debugger_serialize(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
-                sol_log("no_data");
 
-                crate::_solana_debugger_serialize::_SolanaDebuggerSerialize::_solana_debugger_serialize(&**self, "value");
+                sol_log("hash");
+
+                sol_log_data(&[self.as_ref()]);
 
                 sol_log("END_NODE");
             }
         }
 
-        impl<T, const N: usize> _SolanaDebuggerSerialize for [T; N] {
+        impl _SolanaDebuggerSerialize for solana_program::program_error::ProgramError {
             fn _solana_debugger_serialize(&self, name: &str) {
+                use solana_program::program_error::ProgramError;
+
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
 
-                sol_log("array_len");
+                sol_log("str_ident");
 
-                let len = (N as u128).to_le_bytes();
-                sol_log_data(&[len.as_slice()]);
+                let variant_str = match self {
+                    ProgramError::Custom(_) => "Custom",
+                    ProgramError::InvalidArgument => "InvalidArgument",
+                    ProgramError::InvalidInstructionData => "InvalidInstructionData",
+                    ProgramError::InvalidAccountData => "InvalidAccountData",
+                    ProgramError::AccountDataTooSmall => "AccountDataTooSmall",
+                    ProgramError::InsufficientFunds => "InsufficientFunds",
+                    ProgramError::IncorrectProgramId => "IncorrectProgramId",
+                    ProgramError::MissingRequiredSignature => "MissingRequiredSignature",
+                    ProgramError::AccountAlreadyInitialized => "AccountAlreadyInitialized",
+                    ProgramError::UninitializedAccount => "UninitializedAccount",
+                    ProgramError::NotEnoughAccountKeys => "NotEnoughAccountKeys",
+                    ProgramError::AccountBorrowFailed => "AccountBorrowFailed",
+                    ProgramError::MaxSeedLengthExceeded => "MaxSeedLengthExceeded",
+                    ProgramError::InvalidSeeds => "InvalidSeeds",
+                    ProgramError::BorshIoError(_) => "BorshIoError",
+                    ProgramError::AccountNotRentExempt => "AccountNotRentExempt",
+                    ProgramError::UnsupportedSysvar => "UnsupportedSysvar",
+                    ProgramError::IllegalOwner => "IllegalOwner",
+                    ProgramError::MaxAccountsDataAllocationsExceeded => "MaxAccountsDataAllocationsExceeded",
+                    ProgramError::InvalidRealloc => "InvalidRealloc",
+                    ProgramError::MaxInstructionTraceLengthExceeded => "MaxInstructionTraceLengthExceeded",
+                    ProgramError::BuiltinProgramsMustConsumeComputeUnits => "BuiltinProgramsMustConsumeComputeUnits",
+                    ProgramError::InvalidAccountOwner => "InvalidAccountOwner",
+                    ProgramError::ArithmeticOverflow => "ArithmeticOverflow",
+                    ProgramError::Immutable => "Immutable",
+                    ProgramError::IncorrectAuthority => "IncorrectAuthority",
+                };
+                sol_log(variant_str);
 
-                for el in self {
-                    el._solana_debugger_serialize("-inc-index");
+                match self {
+                    ProgramError::Custom(code) => {
+                        code._solana_debugger_serialize("0");
+                    }
+                    ProgramError::BorshIoError(msg) => {
+                        msg._solana_debugger_serialize("0");
+                    }
+                    _ => {}
                 }
+                self.to_string()._solana_debugger_serialize("message");
 
                 sol_log("END_NODE");
             }
         }
 
-        impl<'a> _SolanaDebuggerSerialize for AccountInfo<'a> {
+        impl _SolanaDebuggerSerialize for solana_program::clock::Clock {
             fn _solana_debugger_serialize(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
+
                 sol_log("no_data");
 
-                self.key._solana_debugger_serialize("key");
-                self.lamports._solana_debugger_serialize("lamports");
-                self.data._solana_debugger_serialize("data");
-                self.owner._solana_debugger_serialize("owner");
-                self.rent_epoch._solana_debugger_serialize("rent_epoch");
-                self.is_signer._solana_debugger_serialize("is_signer");
-                self.is_writable._solana_debugger_serialize("is_writable");
-                self.executable._solana_debugger_serialize("executable");
+                self.slot._solana_debugger_serialize("slot");
+                self.epoch_start_timestamp._solana_debugger_serialize("epoch_start_timestamp");
+                self.epoch._solana_debugger_serialize("epoch");
+                self.leader_schedule_epoch._solana_debugger_serialize("leader_schedule_epoch");
+                self.unix_timestamp._solana_debugger_serialize("unix_timestamp");
 
                 sol_log("END_NODE");
             }
         }
 
-        impl _SolanaDebuggerSerialize for Pubkey {
+        impl _SolanaDebuggerSerialize for solana_program::epoch_schedule::EpochSchedule {
             fn _solana_debugger_serialize(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
                 sol_log(name);
                 sol_log(type_name_of_val(self));
 
-                sol_log("pubkey");
+                sol_log("no_data");
 
-                sol_log_data(&[self.as_ref()]);
+                self.slots_per_epoch._solana_debugger_serialize("slots_per_epoch");
+                self.leader_schedule_slot_offset._solana_debugger_serialize("leader_schedule_slot_offset");
+                self.warmup._solana_debugger_serialize("warmup");
+                self.first_normal_epoch._solana_debugger_serialize("first_normal_epoch");
+                self.first_normal_slot._solana_debugger_serialize("first_normal_slot");
 
                 sol_log("END_NODE");
             }
         }
 
-        impl _SolanaDebuggerSerialize for solana_program::sysvar::rent::Rent {
+        impl _SolanaDebuggerSerialize for solana_program::epoch_rewards::EpochRewards {
             fn _solana_debugger_serialize(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
@@ -402,15 +948,19 @@ pub fn get_fixed_serialization() -> File {
 
                 sol_log("no_data");
 
-                self.lamports_per_byte_year._solana_debugger_serialize("lamports_per_byte_year");
-                self.exemption_threshold._solana_debugger_serialize("exemption_threshold");
-                self.burn_percent._solana_debugger_serialize("burn_percent");
+                self.distribution_starting_block_height._solana_debugger_serialize("distribution_starting_block_height");
+                self.num_partitions._solana_debugger_serialize("num_partitions");
+                self.parent_blockhash._solana_debugger_serialize("parent_blockhash");
+                self.total_points._solana_debugger_serialize("total_points");
+                self.total_rewards._solana_debugger_serialize("total_rewards");
+                self.distributed_rewards._solana_debugger_serialize("distributed_rewards");
+                self.active._solana_debugger_serialize("active");
 
                 sol_log("END_NODE");
             }
         }
 
-        impl _SolanaDebuggerSerialize for solana_program::instruction::Instruction {
+        impl _SolanaDebuggerSerialize for solana_program::last_restart_slot::LastRestartSlot {
             fn _solana_debugger_serialize(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
@@ -419,15 +969,13 @@ pub fn get_fixed_serialization() -> File {
 
                 sol_log("no_data");
 
-                self.program_id._solana_debugger_serialize("program_id");
-                self.accounts._solana_debugger_serialize("accounts");
-                self.data._solana_debugger_serialize("data");
+                self.last_restart_slot._solana_debugger_serialize("last_restart_slot");
 
                 sol_log("END_NODE");
             }
         }
 
-        impl _SolanaDebuggerSerialize for solana_program::instruction::AccountMeta {
+        impl _SolanaDebuggerSerialize for solana_program::stake_history::StakeHistoryEntry {
             fn _solana_debugger_serialize(&self, name: &str) {
                 sol_log("START_NODE");
                 sol_log("complex");
@@ -436,13 +984,47 @@ pub fn get_fixed_serialization() -> File {
 
                 sol_log("no_data");
 
-                self.pubkey._solana_debugger_serialize("pubkey");
-                self.is_signer._solana_debugger_serialize("is_signer");
-                self.is_writable._solana_debugger_serialize("is_writable");
+                self.effective._solana_debugger_serialize("effective");
+                self.activating._solana_debugger_serialize("activating");
+                self.deactivating._solana_debugger_serialize("deactivating");
 
                 sol_log("END_NODE");
             }
         }
 
+        impl _SolanaDebuggerSerialize for solana_program::slot_hashes::SlotHashes {
+            fn _solana_debugger_serialize(&self, name: &str) {
+                serialize_seq::<_, _SolanaDebuggerSerializeLeaf>(name, type_name_of_val(self), self.len(), self.iter());
+            }
+        }
+
+        impl _SolanaDebuggerSerialize for solana_program::stake_history::StakeHistory {
+            fn _solana_debugger_serialize(&self, name: &str) {
+                serialize_seq::<_, _SolanaDebuggerSerializeLeaf>(name, type_name_of_val(self), self.len(), self.iter());
+            }
+        }
+
+    };
+
+    if let Some(signature_type) = signature_type {
+        let signature_impl: Item = parse_quote! {
+            impl _SolanaDebuggerSerialize for #signature_type {
+                fn _solana_debugger_serialize(&self, name: &str) {
+                    sol_log("START_NODE");
+                    sol_log("complex");
+                    sol_log(name);
+                    sol_log(type_name_of_val(self));
+
+                    sol_log("signature");
+
+                    sol_log_data(&[self.as_ref()]);
+
+                    sol_log("END_NODE");
+                }
+            }
+        };
+        file.items.push(signature_impl);
     }
+
+    file
 }
@@ -0,0 +1,431 @@
+use std::collections::BTreeSet;
+use proc_macro2::LineColumn;
+use syn::spanned::Spanned;
+use syn::visit::Visit;
+use syn::{Arm, Block, Expr, ExprClosure, ExprConst, File, ImplItemConst, ImplItemFn, ItemConst, ItemFn, ItemImpl, ItemMod, ItemStatic, ItemTrait, Lifetime, PathArguments, ReturnType, Signature, TraitItemFn, Type, TypeBareFn, TypeParamBound, TypeReference};
+
+/// A position in the source where instrumentation can be inserted
+///
+/// Lines are 1-based, columns are 0-based (like `proc_macro2::LineColumn`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InjectionPoint {
+    /// Before the statement that starts here
+    Stmt { line: usize, column: usize },
+    /// Around the expression that starts here: a match arm or closure body without braces
+    Expr { line: usize, column: usize },
+    /// After the last statement of the block whose closing brace is here. A tail expression is matched to a temporary
+    BlockEnd { line: usize, column: usize },
+    /// When the function whose name starts here returns
+    FnReturn { line: usize, column: usize },
+}
+
+impl InjectionPoint {
+    pub fn line(&self) -> usize {
+        match self {
+            InjectionPoint::Stmt { line, .. } => *line,
+            InjectionPoint::Expr { line, .. } => *line,
+            InjectionPoint::BlockEnd { line, .. } => *line,
+            InjectionPoint::FnReturn { line, .. } => *line,
+        }
+    }
+
+    pub fn is_stmt_at(&self, pos: LineColumn) -> bool {
+        matches!(*self, InjectionPoint::Stmt { line, column } if line == pos.line && column == pos.column)
+    }
+
+    pub fn is_expr_at(&self, pos: LineColumn) -> bool {
+        matches!(*self, InjectionPoint::Expr { line, column } if line == pos.line && column == pos.column)
+    }
+
+    pub fn is_block_end_at(&self, pos: LineColumn) -> bool {
+        matches!(*self, InjectionPoint::BlockEnd { line, column } if line == pos.line && column == pos.column)
+    }
+
+    pub fn is_fn_return_at(&self, pos: LineColumn) -> bool {
+        matches!(*self, InjectionPoint::FnReturn { line, column } if line == pos.line && column == pos.column)
+    }
+}
+
+struct BlockInfo {
+    open: LineColumn,
+    close: LineColumn,
+    stmts: Vec<(LineColumn, LineColumn)>,
+}
+
+/// Collects all places where instrumentation can be inserted
+#[derive(Default)]
+struct InjectionPointCollector {
+    blocks: Vec<BlockInfo>,
+    /// Start and end of match arm and closure bodies without braces
+    exprs: Vec<(LineColumn, LineColumn)>,
+    /// Statements and expressions in the order they appear in the AST, i.e. outer before inner
+    starts: Vec<InjectionPoint>,
+}
+
+impl<'ast> Visit<'ast> for InjectionPointCollector {
+    fn visit_block(&mut self, node: &'ast Block) {
+        let stmts = node.stmts.iter().map(|stmt| (stmt.span().start(), stmt.span().end())).collect();
+        self.blocks.push(BlockInfo {
+            open: node.brace_token.span.open().start(),
+            close: node.brace_token.span.close().start(),
+            stmts,
+        });
+        for stmt in node.stmts.iter() {
+            let start = stmt.span().start();
+            self.starts.push(InjectionPoint::Stmt { line: start.line, column: start.column });
+            self.visit_stmt(stmt);
+        }
+    }
+
+    fn visit_arm(&mut self, node: &'ast Arm) {
+        self.add_expr(&node.body);
+        syn::visit::visit_arm(self, node);
+    }
+
+    fn visit_expr_closure(&mut self, node: &'ast ExprClosure) {
+        self.add_expr(&node.body);
+        syn::visit::visit_expr_closure(self, node);
+    }
+
+    // Const contexts can't log
+    fn visit_expr_const(&mut self, _node: &'ast ExprConst) {}
+
+    fn visit_item_const(&mut self, _node: &'ast ItemConst) {}
+
+    fn visit_item_static(&mut self, _node: &'ast ItemStatic) {}
+
+    fn visit_impl_item_const(&mut self, _node: &'ast ImplItemConst) {}
+
+    fn visit_item_fn(&mut self, node: &'ast ItemFn) {
+        if node.sig.constness.is_none() {
+            syn::visit::visit_item_fn(self, node);
+        }
+    }
+
+    fn visit_impl_item_fn(&mut self, node: &'ast ImplItemFn) {
+        if node.sig.constness.is_none() {
+            syn::visit::visit_impl_item_fn(self, node);
+        }
+    }
+
+    fn visit_trait_item_fn(&mut self, node: &'ast TraitItemFn) {
+        if node.sig.constness.is_none() {
+            syn::visit::visit_trait_item_fn(self, node);
+        }
+    }
+}
+
+impl InjectionPointCollector {
+    fn add_expr(&mut self, expr: &Expr) {
+        if let Expr::Block(_) = expr {
+            return;
+        }
+        let start = expr.span().start();
+        self.exprs.push((start, expr.span().end()));
+        self.starts.push(InjectionPoint::Expr { line: start.line, column: start.column });
+    }
+}
+
+/// Map a source location to the nearest place where instrumentation can be inserted
+///
+/// - A statement (or brace-less arm/closure body) that starts at `line` is used directly.
+///   If several start there, `column` (1-based) selects one. Without `column`, the first one is used
+/// - Inside a multi-line statement, the start of that statement is used
+/// - Otherwise, the next statement of the innermost enclosing block is used, or the end of that block
+pub fn resolve_injection_point(file: &File, line: usize, column: Option<usize>) -> Option<InjectionPoint> {
+    let mut collector = InjectionPointCollector::default();
+    collector.visit_file(file);
+
+    // Statements and expressions that start at the line
+    let at_line: Vec<(usize, InjectionPoint)> = collector.starts.iter().filter_map(|point| match *point {
+        InjectionPoint::Stmt { line: l, column: c } | InjectionPoint::Expr { line: l, column: c } if l == line => Some((c, *point)),
+        _ => None,
+    }).collect();
+
+    match column {
+        Some(column) => {
+            // The last one (i.e. innermost) that starts at or before the column
+            let found = at_line.iter()
+                .filter(|(c, _)| *c < column)
+                .max_by_key(|(c, _)| *c);
+            if let Some((_, point)) = found {
+                return Some(*point);
+            }
+        }
+        None => {
+            if let Some((_, point)) = at_line.iter().min_by_key(|(c, _)| *c) {
+                return Some(*point);
+            }
+        }
+    }
+
+    // Innermost block that contains the line
+    let block = collector.blocks.iter()
+        .filter(|block| block.open.line <= line && line <= block.close.line)
+        .max_by_key(|block| (block.open.line, block.open.column))?;
+
+    // A multi-line statement or expression that contains the line
+    let containing_stmt = block.stmts.iter()
+        .chain(collector.exprs.iter())
+        .filter(|(start, end)| start.line < line && line <= end.line)
+        .filter(|(start, _)| (start.line, start.column) > (block.open.line, block.open.column))
+        .max_by_key(|(start, _)| (start.line, start.column));
+    if let Some((start, _)) = containing_stmt {
+        let is_expr = collector.exprs.iter().any(|(expr_start, _)| expr_start == start);
+        return Some(match is_expr {
+            true => InjectionPoint::Expr { line: start.line, column: start.column },
+            false => InjectionPoint::Stmt { line: start.line, column: start.column },
+        });
+    }
+
+    // The next statement
+    if let Some((start, _)) = block.stmts.iter().find(|(start, _)| start.line > line) {
+        return Some(InjectionPoint::Stmt { line: start.line, column: start.column });
+    }
+
+    Some(InjectionPoint::BlockEnd { line: block.close.line, column: block.close.column })
+}
+
+/// The points that lines resolve to without a column, for the lines that don't resolve to another line
+///
+/// These are the lines that `list` marks as usable
+pub fn get_line_injection_points(file: &File) -> Vec<InjectionPoint> {
+    let mut collector = InjectionPointCollector::default();
+    collector.visit_file(file);
+
+    let candidate_lines: BTreeSet<usize> = collector.starts.iter().map(|point| point.line())
+        .chain(collector.blocks.iter().map(|block| block.close.line))
+        .collect();
+    candidate_lines.into_iter()
+        .filter_map(|line| resolve_injection_point(file, line, None).filter(|point| point.line() == line))
+        .collect()
+}
+
+/// A function with a body
+struct FnInfo {
+    /// E.g. `process` or `Processor::process` for methods
+    qualified_name: String,
+    /// The module path and the qualified name, e.g. `processor::Processor::process`
+    path: String,
+    start: LineColumn,
+    end: LineColumn,
+    /// The opening brace of the body
+    body_start: LineColumn,
+    /// The name of the function
+    ident_start: LineColumn,
+    can_inst_return: bool,
+}
+
+/// Collects all functions that have a body
+#[derive(Default)]
+struct FnCollector {
+    fns: Vec<FnInfo>,
+    /// Name of the impl or trait we're in
+    parent_name: Option<String>,
+    /// Path of the module we're in, starting with the module of the file
+    module_path: Vec<String>,
+}
+
+impl FnCollector {
+    fn add_fn(&mut self, sig: &Signature, block: &Block) {
+        let name = sig.ident.to_string();
+        let qualified_name = match &self.parent_name {
+            Some(parent) => format!("{}::{}", parent, name),
+            None => name,
+        };
+        let path = self.module_path.iter().chain([&qualified_name]).cloned().collect::<Vec<_>>().join("::");
+        self.fns.push(FnInfo {
+            qualified_name,
+            path,
+            start: sig.fn_token.span.start(),
+            end: block.brace_token.span.close().end(),
+            body_start: block.brace_token.span.open().start(),
+            ident_start: sig.ident.span().start(),
+            can_inst_return: can_inst_fn_return(sig),
+        });
+    }
+}
+
+impl<'ast> Visit<'ast> for FnCollector {
+    fn visit_item_fn(&mut self, node: &'ast ItemFn) {
+        self.add_fn(&node.sig, &node.block);
+        // Nested functions are not qualified by the impl
+        let parent_name = self.parent_name.take();
+        syn::visit::visit_item_fn(self, node);
+        self.parent_name = parent_name;
+    }
+
+    fn visit_item_impl(&mut self, node: &'ast ItemImpl) {
+        let parent_name = self.parent_name.take();
+        if let Type::Path(type_path) = &*node.self_ty {
+            self.parent_name = type_path.path.segments.last().map(|segment| segment.ident.to_string());
+        }
+        syn::visit::visit_item_impl(self, node);
+        self.parent_name = parent_name;
+    }
+
+    fn visit_item_mod(&mut self, node: &'ast ItemMod) {
+        self.module_path.push(node.ident.to_string());
+        syn::visit::visit_item_mod(self, node);
+        self.module_path.pop();
+    }
+
+    fn visit_item_trait(&mut self, node: &'ast ItemTrait) {
+        let parent_name = self.parent_name.replace(node.ident.to_string());
+        syn::visit::visit_item_trait(self, node);
+        self.parent_name = parent_name;
+    }
+
+    fn visit_impl_item_fn(&mut self, node: &'ast ImplItemFn) {
+        self.add_fn(&node.sig, &node.block);
+        let parent_name = self.parent_name.take();
+        syn::visit::visit_impl_item_fn(self, node);
+        self.parent_name = parent_name;
+    }
+
+    fn visit_trait_item_fn(&mut self, node: &'ast TraitItemFn) {
+        if let Some(block) = &node.default {
+            self.add_fn(&node.sig, block);
+        }
+        let parent_name = self.parent_name.take();
+        syn::visit::visit_trait_item_fn(self, node);
+        self.parent_name = parent_name;
+    }
+}
+
+/// The body of the function is wrapped in a closure, which isn't possible for some functions
+///
+/// Elided lifetimes in the return type would refer to the closure instead of the parameters, so they must be named.
+/// Lifetimes hidden in paths (e.g. `Ref<u8>`) can't be detected here
+fn can_inst_fn_return(sig: &Signature) -> bool {
+    let (returns_impl_trait, has_elided_lifetimes) = match &sig.output {
+        ReturnType::Default => (false, false),
+        ReturnType::Type(_, ty) => {
+            let mut collector = ElidedLifetimeCollector::default();
+            collector.visit_type(ty);
+            (matches!(**ty, Type::ImplTrait(_)), collector.found)
+        }
+    };
+    sig.constness.is_none() && sig.asyncness.is_none() && !returns_impl_trait && !has_elided_lifetimes
+}
+
+/// Finds references without a lifetime and `'_` in a type
+#[derive(Default)]
+struct ElidedLifetimeCollector {
+    found: bool,
+}
+
+impl<'ast> Visit<'ast> for ElidedLifetimeCollector {
+    fn visit_type_reference(&mut self, node: &'ast TypeReference) {
+        self.found |= node.lifetime.is_none();
+        syn::visit::visit_type_reference(self, node);
+    }
+
+    fn visit_lifetime(&mut self, node: &'ast Lifetime) {
+        self.found |= node.ident == "_";
+    }
+
+    // Their lifetimes don't depend on the function
+    fn visit_type_bare_fn(&mut self, _node: &'ast TypeBareFn) {}
+
+    fn visit_type_param_bound(&mut self, node: &'ast TypeParamBound) {
+        if let TypeParamBound::Trait(bound) = node {
+            if bound.path.segments.last().is_some_and(|segment| matches!(segment.arguments, PathArguments::Parenthesized(_))) {
+                // `Fn(&u8) -> &u8` has its own lifetimes
+                return;
+            }
+        }
+        syn::visit::visit_type_param_bound(self, node);
+    }
+}
+
+/// `module_path` is the path of the module of the file, e.g. `processor` for `src/processor.rs`. See `get_module_path`
+fn get_fns(file: &File, module_path: &str) -> Vec<FnInfo> {
+    let mut collector = FnCollector {
+        module_path: module_path.split("::").filter(|segment| !segment.is_empty()).map(String::from).collect(),
+        ..Default::default()
+    };
+    collector.visit_file(file);
+    collector.fns
+}
+
+/// Find the functions with the given name, e.g. `process`, `Processor::process` or `processor::Processor::process`
+///
+/// The name matches the end of the path of the function. A leading `crate::` is ignored
+fn find_fns(file: &File, module_path: &str, name: &str) -> Vec<FnInfo> {
+    let name = name.strip_prefix("crate::").unwrap_or(name);
+    get_fns(file, module_path).into_iter()
+        .filter(|fn_info| fn_info.path == name || fn_info.path.ends_with(&format!("::{}", name)))
+        .collect()
+}
+
+/// The lines of a function, see `get_fn_ranges`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FnRange {
+    /// E.g. `process` or `Processor::process` for methods
+    pub name: String,
+    /// The line of the `fn` keyword
+    pub start_line: usize,
+    /// The line of the closing brace
+    pub end_line: usize,
+}
+
+/// Get the functions that have a body, in the order they appear in the file
+pub fn get_fn_ranges(file: &File) -> Vec<FnRange> {
+    get_fns(file, "").into_iter()
+        .map(|fn_info| FnRange {
+            name: fn_info.qualified_name,
+            start_line: fn_info.start.line,
+            end_line: fn_info.end.line,
+        })
+        .collect()
+}
+
+/// Get the lines of the functions with the given name, see `find_fns`
+pub fn find_fn_ranges(file: &File, module_path: &str, name: &str) -> Vec<FnRange> {
+    find_fns(file, module_path, name).into_iter()
+        .map(|fn_info| FnRange {
+            name: fn_info.qualified_name,
+            start_line: fn_info.start.line,
+            end_line: fn_info.end.line,
+        })
+        .collect()
+}
+
+/// Get the return of the innermost function that contains the line
+pub fn resolve_fn_return_point(file: &File, line: usize) -> Result<InjectionPoint, String> {
+    let fn_info = get_fns(file, "").into_iter()
+        .filter(|fn_info| fn_info.start.line <= line && line <= fn_info.end.line)
+        .max_by_key(|fn_info| (fn_info.start.line, fn_info.start.column))
+        .ok_or(format!("Line {} is not inside a function", line))?;
+    get_fn_return_point(&fn_info)
+}
+
+/// Get the returns of the functions with the given name, see `find_fns`
+pub fn find_fn_return_points(file: &File, module_path: &str, name: &str) -> Vec<Result<InjectionPoint, String>> {
+    find_fns(file, module_path, name).iter()
+        .map(get_fn_return_point)
+        .collect()
+}
+
+/// Get the line `offset` lines after the `fn` keyword of the functions with the given name, see `find_fns`
+///
+/// Lines of the signature are moved to the opening brace of the body. The line is not resolved to an injection point yet, see `resolve_injection_point`
+pub fn find_fn_lines(file: &File, module_path: &str, name: &str, offset: usize) -> Vec<Result<usize, String>> {
+    find_fns(file, module_path, name).iter()
+        .map(|fn_info| {
+            let line = (fn_info.start.line + offset).max(fn_info.body_start.line);
+            if line > fn_info.end.line {
+                Err(format!("{}:+{} is after the end of the function (line {})", fn_info.qualified_name, offset, fn_info.end.line))?
+            }
+            Ok(line)
+        })
+        .collect()
+}
+
+fn get_fn_return_point(fn_info: &FnInfo) -> Result<InjectionPoint, String> {
+    if !fn_info.can_inst_return {
+        Err(format!("The return of {} can't be inspected (const fn, async fn, `impl Trait` return type or elided lifetimes in the return type, which would have to be named)", fn_info.qualified_name))?
+    }
+    Ok(InjectionPoint::FnReturn { line: fn_info.ident_start.line, column: fn_info.ident_start.column })
+}
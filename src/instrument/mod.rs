@@ -3,7 +3,14 @@ pub mod source;
 pub mod fixed_serialization;
 pub mod ast;
 pub mod ast_general;
+pub mod injection_point;
+pub mod source_map;
 pub mod ast_custom_types;
+pub mod ast_line_macros;
+pub mod ast_fn_body;
+pub mod ast_call_stack;
+pub mod ast_coverage;
+pub mod ast_profile;
 pub mod custom_types;
 
 pub use project::*;
@@ -11,7 +18,13 @@ pub use source::*;
 pub use fixed_serialization::*;
 pub use ast::*;
 pub use ast_general::*;
+pub use injection_point::*;
+pub use source_map::*;
 pub use ast_custom_types::*;
+pub use ast_line_macros::*;
+pub use ast_call_stack::*;
+pub use ast_coverage::*;
+pub use ast_profile::*;
 
 pub fn is_hidden_path(path: &std::ffi::OsStr) -> bool {
     path.to_str().map_or(false, |s| s.starts_with('.'))
@@ -1,6 +1,7 @@
 use std::collections::VecDeque;
 use std::fs;
 use std::path::{Path, PathBuf};
+use cargo_metadata::{DependencyKind, MetadataCommand};
 use crate::instrument::*;
 
 #[derive(Debug)]
@@ -13,6 +14,9 @@ pub struct InstProjectArgs {
 
     /// Which kind of instrumentation to perform
     pub inst_spec: InstProjectSpec,
+
+    /// Where the call stack is logged
+    pub call_stack: CallStackInst,
 }
 
 #[derive(Debug)]
@@ -36,7 +40,46 @@ pub enum InstInputProjectType {
 pub enum InstProjectSpec {
     SingleLine {
         file: PathBuf,
-        line: usize,
+        /// See `resolve_injection_point`
+        point: InjectionPoint,
+    },
+    /// Log the variables wherever an error is produced, see `inst_ast_errors`
+    BreakOnError,
+    /// Count how often each line is executed, see `inst_ast_coverage`
+    Coverage,
+    /// Measure the compute units of each function and line, see `inst_ast_profile`
+    Profile,
+    /// Log the variables at every line of these files, see `get_line_injection_points`
+    Record {
+        files: Vec<PathBuf>,
+    },
+    /// Log the variables at each of the points, e.g. every line of a function
+    Points {
+        file: PathBuf,
+        points: Vec<InjectionPoint>,
+    },
+}
+
+/// Where function entries and exits are logged for the call stack, see `inst_ast_call_stack`
+///
+/// Every function and call gets a log, so this costs compute units and makes more code that has to compile
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CallStackInst {
+    Off,
+    /// Only in the files with the location, e.g. to tell the recursive calls of a function apart
+    Location,
+    /// In every instrumented file, optionally with the arguments of each function
+    All { args: bool },
+}
+
+impl InstProjectSpec {
+    /// Whether the location is in the package at `package_path`. A workspace package other than the program is only instrumented then
+    fn is_location_in_package(&self, package_path: &Path) -> bool {
+        match self {
+            InstProjectSpec::SingleLine { file, .. } | InstProjectSpec::Points { file, .. } => file.starts_with(package_path.join("src")) && package_path.join("Cargo.toml").is_file(),
+            InstProjectSpec::Record { files } => files.iter().any(|file| file.starts_with(package_path.join("src"))) && package_path.join("Cargo.toml").is_file(),
+            InstProjectSpec::BreakOnError | InstProjectSpec::Coverage | InstProjectSpec::Profile => false,
+        }
     }
 }
 
@@ -60,7 +103,7 @@ pub fn inst_project(args: InstProjectArgs) -> Result<InstProjectInfo, Box<dyn st
 
     match args.input_project.project_type {
         InstInputProjectType::Package { program_path } => {
-            inst_project_package(&program_path, &output_dir, &args.inst_spec)?;
+            inst_project_package(&program_path, &output_dir, Path::new(""), &args.inst_spec, args.call_stack)?;
             Ok(
                 InstProjectInfo {
                     program_path: output_dir.clone(),
@@ -75,7 +118,7 @@ pub fn inst_project(args: InstProjectArgs) -> Result<InstProjectInfo, Box<dyn st
                 Err("inst_project: Invalid workspace root")?;
             }
 
-            inst_project_workspace(&root_path, &output_dir, &program_path, &args.input_project.target_dir, &args.inst_spec)?;
+            inst_project_workspace(&root_path, &output_dir, &program_path, &args.input_project.target_dir, &args.inst_spec, args.call_stack)?;
 
             let relative_program_path = program_path.strip_prefix(&root_path).unwrap();
             let output_program_path = output_dir.join(relative_program_path);
@@ -91,7 +134,8 @@ pub fn inst_project(args: InstProjectArgs) -> Result<InstProjectInfo, Box<dyn st
     }
 }
 
-fn inst_project_package(input_path: &Path, output_path: &Path, inst_spec: &InstProjectSpec) -> Result<(), Box<dyn std::error::Error>> {
+/// `package_path`: the package as the logged files start with it, see `inst_source`
+fn inst_project_package(input_path: &Path, output_path: &Path, package_path: &Path, inst_spec: &InstProjectSpec, call_stack: CallStackInst) -> Result<(), Box<dyn std::error::Error>> {
     let cargo_config_path = input_path.join("Cargo.toml");
     if !cargo_config_path.exists() {
         Err("Cargo.toml not found")?
@@ -115,17 +159,49 @@ fn inst_project_package(input_path: &Path, output_path: &Path, inst_spec: &InstP
     let source_path_out = output_path.join("src");
     fs::create_dir(&source_path_out)?;
 
-    inst_source(&source_path, &source_path_out, inst_spec)?;
+    let signature_type = get_signature_type(&input_path.join("Cargo.toml"))?;
+
+    inst_source(&source_path, &source_path_out, package_path, inst_spec, call_stack, signature_type.as_ref())?;
 
     Ok(())
 }
 
+/// How the package can name `Signature`, e.g. `solana_sdk::signature::Signature`.
+/// `solana_program` doesn't have it, so it needs a dependency on a crate that does
+fn get_signature_type(manifest_path: &Path) -> Result<Option<syn::Path>, Box<dyn std::error::Error>> {
+    let manifest_path = manifest_path.canonicalize()?;
+    let metadata = MetadataCommand::new()
+        .manifest_path(&manifest_path)
+        .no_deps()
+        .exec()?;
+    let package = metadata.packages.iter()
+        .find(|package| package.manifest_path.as_std_path() == manifest_path)
+        .ok_or("Could not find package in cargo metadata output")?;
+
+    for dependency in &package.dependencies {
+        // Optional and platform specific dependencies may not be there when the program is compiled
+        if dependency.kind != DependencyKind::Normal || dependency.optional || dependency.target.is_some() {
+            continue;
+        }
+        let crate_name = dependency.rename.as_ref().unwrap_or(&dependency.name).replace('-', "_");
+        let path = match dependency.name.as_str() {
+            "solana-signature" => format!("{crate_name}::Signature"),
+            "solana-sdk" => format!("{crate_name}::signature::Signature"),
+            _ => continue,
+        };
+        return Ok(Some(syn::parse_str(&path)?));
+    }
+
+    Ok(None)
+}
+
 fn inst_project_workspace(
     workspace_path: &Path,
     output_path: &Path,
     debugee_path: &Path,
     input_target_dir: &Path,
-    inst_spec: &InstProjectSpec
+    inst_spec: &InstProjectSpec,
+    call_stack: CallStackInst,
 ) -> Result<(), Box<dyn std::error::Error>> {
 
     let mut queue = VecDeque::<(PathBuf, PathBuf)>::new();
@@ -150,10 +226,13 @@ fn inst_project_workspace(
                 let new_output_dir = output_dir.join(file_name);
                 fs::create_dir(&new_output_dir)?;
 
-                if path != debugee_path {
-                    queue.push_back((path, new_output_dir));
+                if path == debugee_path || inst_spec.is_location_in_package(&path) {
+                    // Like the paths that are shown, e.g. `crates/utils` for a package at the workspace root
+                    let package_path = path.strip_prefix(debugee_path)
+                        .or_else(|_| path.strip_prefix(workspace_path))?;
+                    inst_project_package(&path, &new_output_dir, package_path, inst_spec, call_stack)?;
                 } else {
-                    inst_project_package(&path, &new_output_dir, inst_spec)?;
+                    queue.push_back((path, new_output_dir));
                 }
             } else if path.is_file() {
                 let new_output_file = output_dir.join(file_name);
@@ -3,13 +3,20 @@ use std::fs;
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use crate::instrument::*;
 
-#[allow(irrefutable_let_patterns)]
-pub fn inst_source(input_path: &Path, output_path: &Path, inst_spec: &InstProjectSpec) -> Result<(), Box<dyn std::error::Error>> {
-    if let InstProjectSpec::SingleLine { .. } = inst_spec {
-        write_fixed_serialization_file(&output_path.join("_solana_debugger_serialize.rs"))?;
-    }
+/// `package_path`: where the package is, relative to the program or, for other packages, to the workspace root. Empty for the program.
+/// The logged files start with it, so the files of different packages don't collide
+///
+/// `signature_type`: how the package names `Signature`, see `get_fixed_serialization`
+pub fn inst_source(input_path: &Path, output_path: &Path, package_path: &Path, inst_spec: &InstProjectSpec, call_stack: CallStackInst, signature_type: Option<&syn::Path>) -> Result<(), Box<dyn std::error::Error>> {
+    write_fixed_serialization_file(&output_path.join("_solana_debugger_serialize.rs"), signature_type)?;
+
+    let parsed_files = parse_source_files(input_path)?;
+    let source_files: Vec<syn::File> = parsed_files.iter().map(|(_, file)| file.clone()).collect();
+    let pat_constants = Rc::new(get_pat_constants(&source_files));
+    let crate_fns = Rc::new(get_crate_fns(&source_files));
 
     let mut queue = VecDeque::<(PathBuf, PathBuf)>::new();
     queue.push_back((input_path.into(), output_path.into()));
@@ -31,31 +38,85 @@ pub fn inst_source(input_path: &Path, output_path: &Path, inst_spec: &InstProjec
             } else if path.is_file() {
                 let new_output_file = output_dir.join(file_name);
 
-                if let InstProjectSpec::SingleLine { file, line } = &inst_spec {
+                // TODO: path should be dynamically obtained
+                let is_main_module = new_output_file.ends_with("src/lib.rs");
+
+                // E.g. "src/processor.rs", or "crates/utils/src/lib.rs" for another package
+                let file_path_str = package_path.join("src").join(path.strip_prefix(input_path)?).to_string_lossy().to_string();
+
+                let mode = match inst_spec {
+                    InstProjectSpec::SingleLine { file, point } if path == *file => InstMode::Line(*point),
+                    InstProjectSpec::Points { file, points } if path == *file => InstMode::Points(points.clone()),
+                    InstProjectSpec::Record { files } if files.contains(&path) => parsed_files.iter()
+                        .find(|(parsed_path, _)| *parsed_path == path)
+                        .map(|(_, file)| InstMode::Points(get_line_injection_points(file)))
+                        .unwrap_or(InstMode::None),
+                    InstProjectSpec::BreakOnError => InstMode::Errors,
+                    InstProjectSpec::Coverage => InstMode::Coverage,
+                    InstProjectSpec::Profile => InstMode::Profile,
+                    _ => InstMode::None,
+                };
+
+                let ast_spec = InstAstSpec {
+                    mod_fixed_serialization: is_main_module,
+                    feature_min_specialization: is_main_module,
+                    debugee_file_path: file_path_str,
+                    mode,
+                    call_stack,
+                    crate_fns: crate_fns.clone(),
+                    pat_constants: pat_constants.clone(),
+                    custom_type_serialization: true
+                };
+
+                inst_source_file(&path, &new_output_file, &ast_spec)?;
+            }
+        }
+    }
 
-                    // TODO: path should be dynamically obtained
-                    let is_main_module = new_output_file.ends_with("src/lib.rs");
+    Ok(())
+}
 
-                    // TODO: should be something like "src/lib.rs"
-                    let file_path_str = "".to_string();
+/// Parse all Rust files in the source directory
+pub fn parse_source_files(input_path: &Path) -> Result<Vec<(PathBuf, syn::File)>, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
 
-                    let line_inst = if path == file.to_owned() { Some(*line) } else { None };
+    let mut queue = VecDeque::<PathBuf>::new();
+    queue.push_back(input_path.into());
 
-                    let ast_spec = InstAstSpec {
-                        mod_fixed_serialization: is_main_module,
-                        feature_min_specialization: is_main_module,
-                        debugee_file_path: file_path_str,
-                        line_inst,
-                        custom_type_serialization: true
-                    };
+    while let Some(input_dir) = queue.pop_front() {
+        for entry in fs::read_dir(&input_dir)? {
+            let entry = entry?;
+            let path = entry.path();
 
-                    inst_source_file(&path, &new_output_file, &ast_spec)?;
-                }
+            if is_hidden_path(path.file_name().unwrap()) {
+                continue;
+            }
+
+            if path.is_dir() {
+                queue.push_back(path);
+            } else if path.is_file() && path.extension().unwrap_or_default() == "rs" {
+                let contents = fs::read_to_string(&path)?;
+                files.push((path, syn::parse_file(&contents)?));
             }
         }
     }
 
-    Ok(())
+    Ok(files)
+}
+
+/// Get the module path of a source file, e.g. `processor` for `src/processor.rs` or `a::b` for `src/a/b/mod.rs`
+///
+/// The crate root (`lib.rs`, `main.rs`) is the empty path. `#[path]` attributes are not considered
+pub fn get_module_path(source_path: &Path, file_path: &Path) -> String {
+    let relative_path = file_path.strip_prefix(source_path).unwrap_or(file_path).with_extension("");
+    let mut segments: Vec<String> = relative_path.iter().map(|segment| segment.to_string_lossy().to_string()).collect();
+    if segments.len() == 1 && (segments[0] == "lib" || segments[0] == "main") {
+        segments.clear();
+    }
+    if segments.last().is_some_and(|segment| segment == "mod") {
+        segments.pop();
+    }
+    segments.join("::")
 }
 
 fn inst_source_file(input_file_path: &Path, output_file_path: &Path, spec: &InstAstSpec) -> Result<(), Box<dyn std::error::Error>> {
@@ -68,13 +129,14 @@ fn inst_source_file(input_file_path: &Path, output_file_path: &Path, spec: &Inst
     //eprintln!("Write {}", output_file_path.display());
     let mut output_file = File::create(output_file_path)?;
     output_file.write_all(output_file_contents.as_bytes())?;
+    write_source_map(&output_ast, &input_file_contents, &output_file_contents, output_file_path, false)?;
 
     Ok(())
 }
 
-fn write_fixed_serialization_file(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+fn write_fixed_serialization_file(path: &Path, signature_type: Option<&syn::Path>) -> Result<(), Box<dyn std::error::Error>> {
     let mut output_file = File::create(&path)?;
-    let trait_code = crate::instrument::get_fixed_serialization();
+    let trait_code = crate::instrument::get_fixed_serialization(signature_type);
     let contents = prettyplease::unparse(&trait_code);
     output_file.write_all(contents.as_bytes())?;
     Ok(())
@@ -0,0 +1,215 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+use quote::ToTokens;
+use serde::{Deserialize, Serialize};
+
+/// Maps the lines of an instrumented file to the lines of the original file
+///
+/// Stored next to the instrumented file, see `get_source_map_path`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SourceMap {
+    /// Index: line in the instrumented file - 1. `None` for lines that only contain inserted code
+    lines: Vec<Option<usize>>,
+}
+
+/// A token and the line where it starts. The line is `None` for inserted tokens
+struct LineToken {
+    text: String,
+    line: Option<usize>,
+}
+
+/// How many tokens are skipped at most to find the next match. The token streams are almost identical
+const MAX_LOOKAHEAD: usize = 4;
+
+impl SourceMap {
+    /// Get the map from the lines of `output` (the result of `prettyplease::unparse(ast)`) to the lines of `source`
+    ///
+    /// `ast` was parsed from `source` and then modified
+    pub fn new(ast: &syn::File, source: &str, output: &str) -> Result<SourceMap, Box<dyn std::error::Error>> {
+        let mut ast_tokens = Vec::new();
+        get_line_tokens(ast.to_token_stream(), &mut ast_tokens, Some(source));
+
+        let output_ast = syn::parse_file(output)?;
+        let mut output_tokens = Vec::new();
+        get_line_tokens(output_ast.to_token_stream(), &mut output_tokens, None);
+
+        let mut lines = vec![None; output.lines().count()];
+
+        // prettyplease only adds or removes a few tokens (e.g. trailing commas), so a greedy alignment is enough
+        let (mut i, mut j) = (0, 0);
+        while i < ast_tokens.len() && j < output_tokens.len() {
+            if ast_tokens[i].text == output_tokens[j].text {
+                if let (Some(line), Some(output_line)) = (ast_tokens[i].line, output_tokens[j].line) {
+                    let entry = &mut lines[output_line - 1];
+                    if entry.is_none() {
+                        *entry = Some(line);
+                    }
+                }
+                i += 1;
+                j += 1;
+                continue;
+            }
+            let skip_ast = (1..=MAX_LOOKAHEAD).find(|k| ast_tokens.get(i + k).is_some_and(|t| t.text == output_tokens[j].text));
+            let skip_output = (1..=MAX_LOOKAHEAD).find(|k| output_tokens.get(j + k).is_some_and(|t| t.text == ast_tokens[i].text));
+            match (skip_ast, skip_output) {
+                (Some(k), Some(l)) if k <= l => i += k,
+                (_, Some(l)) => j += l,
+                (Some(k), None) => i += k,
+                (None, None) => {
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+
+        Ok(SourceMap { lines })
+    }
+
+    pub fn get_original_line(&self, line: usize) -> Option<usize> {
+        self.lines.get(line.checked_sub(1)?).copied().flatten()
+    }
+
+    /// Like `get_original_line`, but lines with inserted code map to the closest original line before them
+    pub fn get_nearest_original_line(&self, line: usize) -> Option<usize> {
+        (1..=line).rev().find_map(|l| self.get_original_line(l))
+    }
+
+    /// `self` maps to the lines of an intermediate file, `previous` maps that file to the original
+    pub fn then(&self, previous: &SourceMap) -> SourceMap {
+        SourceMap {
+            lines: self.lines.iter().map(|line| line.and_then(|l| previous.get_original_line(l))).collect()
+        }
+    }
+
+    pub fn write_to_file(&self, path: &Path) -> std::io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    pub fn load_from_file(path: &Path) -> std::io::Result<SourceMap> {
+        let file = File::open(path)?;
+        let source_map = serde_json::from_reader(BufReader::new(file))?;
+        Ok(source_map)
+    }
+}
+
+/// E.g. `src/.lib.rs.map.json` for `src/lib.rs`. A hidden file, so it's not instrumented again
+pub fn get_source_map_path(file_path: &Path) -> PathBuf {
+    let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+    file_path.with_file_name(format!(".{}.map.json", file_name))
+}
+
+/// Map a line of an instrumented file to the original file. `None` if there is no source map or the line was inserted
+pub fn map_to_original_line(inst_file_path: &Path, line: usize) -> Option<usize> {
+    let source_map_path = get_source_map_path(inst_file_path);
+    if !source_map_path.is_file() {
+        return None;
+    }
+    SourceMap::load_from_file(&source_map_path).ok()?.get_nearest_original_line(line)
+}
+
+/// Flatten a token stream
+///
+/// If `source` is set, only tokens from the source get a line. Others were inserted, e.g. with `parse_quote!`
+fn get_line_tokens(tokens: TokenStream, result: &mut Vec<LineToken>, source: Option<&str>) {
+    for token in tokens {
+        match token {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                if !open.is_empty() {
+                    result.push(LineToken { text: open.to_string(), line: get_token_line(open, group.span_open(), source) });
+                }
+                get_line_tokens(group.stream(), result, source);
+                if !close.is_empty() {
+                    result.push(LineToken { text: close.to_string(), line: get_token_line(close, group.span_close(), source) });
+                }
+            }
+            token => {
+                let text = token.to_string();
+                let line = get_token_line(&text, token.span(), source);
+                result.push(LineToken { text, line });
+            }
+        }
+    }
+}
+
+fn get_token_line(text: &str, span: proc_macro2::Span, source: Option<&str>) -> Option<usize> {
+    // Inserted tokens also have a span, e.g. `Span::call_site()`, but it doesn't point to their text in the source
+    if let Some(source) = source {
+        if source.get(span.byte_range()) != Some(text) {
+            return None;
+        }
+    }
+    Some(span.start().line)
+}
+
+/// Write the source map of an instrumented file. `output` was written from `ast`, which was parsed from `source`
+///
+/// If the file already had a source map (i.e. `source` is an instrumented file), the maps are combined
+pub fn write_source_map(ast: &syn::File, source: &str, output: &str, output_file_path: &Path, is_reinstrumented: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut source_map = SourceMap::new(ast, source, output)?;
+    let source_map_path = get_source_map_path(output_file_path);
+    if is_reinstrumented && source_map_path.is_file() {
+        source_map = source_map.then(&SourceMap::load_from_file(&source_map_path)?);
+    }
+    source_map.write_to_file(&source_map_path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::{parse_quote, Item, Stmt};
+
+    /// The map from the instrumented file to `source`, after `modify` changed the statements of its first function
+    fn get_source_map(source: &str, modify: impl FnOnce(&mut Vec<Stmt>)) -> (SourceMap, String) {
+        let mut ast = syn::parse_file(source).unwrap();
+        if let Some(Item::Fn(item_fn)) = ast.items.first_mut() {
+            modify(&mut item_fn.block.stmts);
+        }
+        let output = prettyplease::unparse(&ast);
+        (SourceMap::new(&ast, source, &output).unwrap(), output)
+    }
+
+    fn get_lines(source_map: &SourceMap, output: &str) -> Vec<Option<usize>> {
+        (1..=output.lines().count()).map(|line| source_map.get_original_line(line)).collect()
+    }
+
+    #[test]
+    fn test_inserted_lines() {
+        let source = "fn f() {\n    let a = 1;\n    let b = 2;\n}\n";
+        let (source_map, output) = get_source_map(source, |stmts| {
+            stmts.insert(1, parse_quote! { msg!("a = {}", a); });
+        });
+
+        assert_eq!(get_lines(&source_map, &output), vec![Some(1), Some(2), None, Some(3), Some(4)]);
+        assert_eq!(source_map.get_nearest_original_line(3), Some(2));
+    }
+
+    #[test]
+    fn test_removed_lines() {
+        let source = "fn f() {\n    let a = 1;\n    let b = 2;\n    let c = 3;\n}\n";
+        let (source_map, output) = get_source_map(source, |stmts| {
+            stmts.remove(1);
+        });
+
+        assert_eq!(get_lines(&source_map, &output), vec![Some(1), Some(2), Some(4), Some(5)]);
+    }
+
+    #[test]
+    fn test_reformatted_stmt() {
+        let source = "fn f() {\n    let a = g(1,\n        2\n    );\n    let b = 3;\n}\n";
+        let (source_map, output) = get_source_map(source, |_| {});
+
+        assert_eq!(output.lines().nth(1), Some("    let a = g(1, 2);"));
+        assert_eq!(get_lines(&source_map, &output), vec![Some(1), Some(2), Some(5), Some(6)]);
+    }
+}
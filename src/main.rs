@@ -4,9 +4,12 @@ mod instrument;
 mod compile;
 mod output;
 
+use std::path::{Path, PathBuf};
 use clap::*;
-use crate::commands::var::VariableFilter;
+use crate::commands::var::{VarLocation, VariableFilter};
 use crate::commands::var::VariableFilter::*;
+use crate::commands::snapshot::SnapshotAction;
+use crate::instrument::CallStackInst;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -32,12 +35,155 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             Command::new("var")
                 .about("Inspect the value of variables")
                 .arg(Arg::new("location")
-                    .help("Location to inspect. Format: FILE:LINE, e.g. lib.rs:33 (without `src/`)")
+                    .help("Location to inspect. Format: FILE:LINE or FILE:LINE:COLUMN, e.g. lib.rs:33 (`src/` is optional), \
+                        a function like processor::process or Processor::process:+5 (5 lines after `fn`), \
+                        or fn:NAME to inspect the return of a function")
+                    .required_unless_present("break_on_error"))
+                .arg(Arg::new("on_return")
+                    .long("on-return")
+                    .help("Inspect the return value of the function that contains the location")
+                    .action(ArgAction::SetTrue))
+                .arg(Arg::new("break_on_error")
+                    .long("break-on-error")
+                    .help("Instead of a location, inspect the variables where the first error is produced with `?` or `return Err(..)`")
+                    .conflicts_with("on_return")
+                    .action(ArgAction::SetTrue))
+                .arg(Arg::new("stack")
+                    .long("stack")
+                    .help("Show the functions of the program that led to the location")
+                    .action(ArgAction::SetTrue))
+                .arg(Arg::new("stack_args")
+                    .long("stack-args")
+                    .help("Like --stack, with the arguments of each function")
+                    .action(ArgAction::SetTrue))
+                .arg(Arg::new("accounts")
+                    .long("accounts")
+                    .help("Show the accounts after the transaction, with the state of token accounts decoded")
+                    .action(ArgAction::SetTrue))
+                .arg(Arg::new("snapshot")
+                    .long("snapshot")
+                    .value_name("PATH")
+                    .help("Write the variables to a JSON file, to compare later runs with --check"))
+                .arg(Arg::new("check")
+                    .long("check")
+                    .value_name("PATH")
+                    .help("Compare the variables with a file written by --snapshot. Fails if they differ")
+                    .conflicts_with("snapshot"))
+                .arg(Arg::new("ignore")
+                    .long("ignore")
+                    .value_name("FIELD")
+                    .help("Field to ignore with --check, e.g. key or accounts.*.key. Can be repeated")
+                    .requires("check")
+                    .action(ArgAction::Append))
+                .arg(Arg::new("diff_json")
+                    .long("diff-json")
+                    .value_name("PATH")
+                    .help("Write the differences found by --check to a JSON file, or - for stdout instead of the text output")
+                    .requires("check"))
+                .arg(Arg::new("variable_names")
+                    .help("Name of variables to inspect. Leave empty to show all")
+                    .required(false)
+                    .action(ArgAction::Append))
+        )
+        .subcommand(
+            Command::new("scope")
+                .about("Show the variables in scope at a location, without running the program")
+                .arg(Arg::new("location")
+                    .help("Location to inspect, in the same format as for `var`")
+                    .required(true))
+                .arg(Arg::new("on_return")
+                    .long("on-return")
+                    .help("Show the variables at the return of the function that contains the location")
+                    .action(ArgAction::SetTrue))
+        )
+        .subcommand(
+            Command::new("list")
+                .about("Show the source of a file and the lines that can be inspected with `var`")
+                .arg(Arg::new("location")
+                    .help("FILE or FILE:LINE. With a line, only the function that contains it is shown")
+                    .required(true))
+        )
+        .subcommand(
+            Command::new("coverage")
+                .about("Show how often each line is executed")
+                .long_about("Show how often each line is executed\n\n\
+                    Each executed line logs a hit, which costs about 100 compute units. \
+                    A program close to the compute budget can exceed it when it is instrumented")
+                .arg(Arg::new("lcov")
+                    .long("lcov")
+                    .help("Path of the lcov file to write")
+                    .default_value("lcov.info"))
+                .arg(Arg::new("input_paths")
+                    .help("Folders containing inputs to the program. Leave empty to use the configured input")
+                    .required(false)
+                    .action(ArgAction::Append))
+        )
+        .subcommand(
+            Command::new("profile")
+                .about("Show the compute units spent in each function, and in each line of a function")
+                .arg(Arg::new("function")
+                    .long("fn")
+                    .help("Function to show the cost of each line of. Defaults to the one that spends the most compute units itself"))
+                .arg(Arg::new("folded")
+                    .long("folded")
+                    .help("Path of the folded call stacks to write, e.g. for flamegraph tools")
+                    .default_value("profile.folded"))
+        )
+        .subcommand(
+            Command::new("record")
+                .about("Run the program once and store the variables at every line, to inspect them with `query`")
+                .arg(Arg::new("files")
+                    .help("Files to record, e.g. processor.rs. Leave empty to record all files of the program")
+                    .required(false)
+                    .action(ArgAction::Append))
+        )
+        .subcommand(
+            Command::new("query")
+                .about("Inspect the value of variables in the last recording, without running the program")
+                .arg(Arg::new("location")
+                    .help("Location to inspect: FILE:LINE, FILE:LINE:COLUMN or a function like for `var`")
                     .required(true))
                 .arg(Arg::new("variable_names")
                     .help("Name of variables to inspect. Leave empty to show all")
                     .required(false)
                     .action(ArgAction::Append))
+        )
+        .subcommand(
+            Command::new("step")
+                .about("Show each line of a function as it is executed, with the variables that changed")
+                .arg(Arg::new("function")
+                    .help("Function to step through, e.g. process_transfer or Processor::process_transfer")
+                    .required(true))
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Show the value of a variable at each line of a function, e.g. `history amount in process_transfer`")
+                .arg(Arg::new("variable_name")
+                    .help("Variable to follow")
+                    .required(true))
+                .arg(Arg::new("in")
+                    .value_name("in")
+                    .value_parser(["in"])
+                    .hide_possible_values(true)
+                    .required(true))
+                .arg(Arg::new("function")
+                    .help("Function of the variable, e.g. process_transfer or Processor::process_transfer")
+                    .required(true))
+        )
+        .subcommand(
+            Command::new("diff-run")
+                .about("Run the program with two inputs and show where the executions diverge first")
+                .arg(Arg::new("input_path_a")
+                    .help("Input directory of the first execution")
+                    .required(true))
+                .arg(Arg::new("input_path_b")
+                    .help("Input directory of the second execution")
+                    .required(true))
+                .arg(Arg::new("ignore")
+                    .long("ignore")
+                    .value_name("FIELD")
+                    .help("Field whose values may differ, e.g. one that comes from the input. Same format as for `var --ignore`. Can be repeated")
+                    .action(ArgAction::Append))
         );
 
     let processed_args = get_processed_args();
@@ -48,6 +194,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Some(("init", sub_m)) => subcommand_init(sub_m),
         Some(("status", sub_m)) => subcommand_status(sub_m),
         Some(("var", sub_m)) => subcommand_var(sub_m).await,
+        Some(("scope", sub_m)) => subcommand_scope(sub_m),
+        Some(("list", sub_m)) => subcommand_list(sub_m),
+        Some(("coverage", sub_m)) => subcommand_coverage(sub_m).await,
+        Some(("profile", sub_m)) => subcommand_profile(sub_m).await,
+        Some(("record", sub_m)) => subcommand_record(sub_m).await,
+        Some(("query", sub_m)) => subcommand_query(sub_m),
+        Some(("step", sub_m)) => subcommand_step(sub_m).await,
+        Some(("history", sub_m)) => subcommand_history(sub_m).await,
+        Some(("diff-run", sub_m)) => subcommand_diff_run(sub_m).await,
         _ => {
             eprintln!("Invalid subcommand. Help:");
             eprintln!();
@@ -61,7 +216,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 fn get_processed_args() -> Vec<String> {
     let mut args: Vec<String> = std::env::args().collect();
 
-    if args.len() > 1 && try_get_file_line_format(&args[1]).is_ok() {
+    // A plain function name without `::` could be a subcommand, so it needs `var`
+    let is_location = |arg: &str| try_get_file_line_format(arg).is_ok()
+        || arg.starts_with("fn:")
+        || try_get_fn_offset_format(arg).is_some_and(|(name, offset)| name.contains("::") || offset > 0);
+    if args.len() > 1 && is_location(&args[1]) {
         args.insert(1, "var".to_string());
     }
 
@@ -83,26 +242,174 @@ fn subcommand_status(_matches: &ArgMatches) -> Result<(), Box<dyn std::error::Er
 }
 
 async fn subcommand_var(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
-    let location_str = matches.get_one::<String>("location").unwrap();
+    let mut variable_names: Vec<String> = matches.get_many::<String>("variable_names")
+        .map(|v| v.cloned().collect())
+        .unwrap_or_default();
 
-    let (file_path, line_number) = try_get_file_line_format(&location_str)?;
+    let on_return = matches.get_flag("on_return");
 
-    // We assume that the source files are stored in `src`
-    // We prepend `src/` for convenience
-    let file_path = "src/".to_string() + file_path.as_str();
+    let var_location = match (matches.get_flag("break_on_error"), matches.get_one::<String>("location")) {
+        (true, location) => {
+            // There is no location, so the first positional argument is a variable name
+            if let Some(var) = location {
+                variable_names.insert(0, var.clone());
+            }
+            VarLocation::FirstError
+        }
+        (false, None) => Err("Missing location")?,
+        (false, Some(location_str)) => get_var_location(location_str, on_return)?,
+    };
 
-    let variable_filter = match matches.get_many::<String>("variable_names") {
-        None => VariableFilter::All,
-        Some(v) => Select(v.map(|s| s.clone()).collect())
+    let variable_filter = match variable_names.is_empty() {
+        true => VariableFilter::All,
+        false => Select(variable_names)
     };
     //dbg!(&variable_filter);
 
-    commands::var::process_var(&file_path, line_number, variable_filter).await?;
+    let snapshot_action = match (matches.get_one::<String>("snapshot"), matches.get_one::<String>("check")) {
+        (Some(path), _) => SnapshotAction::Write(PathBuf::from(path)),
+        (None, Some(path)) => SnapshotAction::Check {
+            path: PathBuf::from(path),
+            ignored_fields: matches.get_many::<String>("ignore").map(|v| v.cloned().collect()).unwrap_or_default(),
+            diff_json: matches.get_one::<String>("diff_json").map(PathBuf::from),
+        },
+        (None, None) => SnapshotAction::None,
+    };
+
+    let call_stack = match (matches.get_flag("stack"), matches.get_flag("stack_args")) {
+        (_, true) => CallStackInst::All { args: true },
+        (true, false) => CallStackInst::All { args: false },
+        (false, false) => CallStackInst::Off,
+    };
+    commands::var::process_var(var_location, variable_filter, call_stack, matches.get_flag("accounts"), snapshot_action).await?;
 
     Ok(())
 }
 
-fn try_get_file_line_format(input: &str) -> Result<(String, usize), String> {
+fn subcommand_scope(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let location_str = matches.get_one::<String>("location").unwrap();
+    let var_location = get_var_location(location_str, matches.get_flag("on_return"))?;
+
+    commands::scope::process_scope(var_location)?;
+
+    Ok(())
+}
+
+fn subcommand_list(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let location_str = matches.get_one::<String>("location").unwrap();
+    let (file, line) = match try_get_file_line_format(location_str) {
+        Ok((file, line_number, _)) => (file, Some(line_number)),
+        Err(_) => (location_str.clone(), None),
+    };
+
+    commands::list::process_list(&file, line)?;
+
+    Ok(())
+}
+
+async fn subcommand_coverage(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input_paths: Vec<PathBuf> = matches.get_many::<String>("input_paths")
+        .map(|v| v.map(PathBuf::from).collect())
+        .unwrap_or_default();
+    let lcov_path = matches.get_one::<String>("lcov").unwrap();
+
+    commands::coverage::process_coverage(input_paths, Path::new(lcov_path)).await?;
+
+    Ok(())
+}
+
+async fn subcommand_profile(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let function = matches.get_one::<String>("function").cloned();
+    let folded_path = matches.get_one::<String>("folded").unwrap();
+
+    commands::profile::process_profile(function, Path::new(folded_path)).await?;
+
+    Ok(())
+}
+
+async fn subcommand_record(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let files: Vec<String> = matches.get_many::<String>("files")
+        .map(|v| v.cloned().collect())
+        .unwrap_or_default();
+
+    commands::record::process_record(files).await?;
+
+    Ok(())
+}
+
+fn subcommand_query(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let location_str = matches.get_one::<String>("location").unwrap();
+    let var_location = get_var_location(location_str, false)?;
+
+    let variable_names: Vec<String> = matches.get_many::<String>("variable_names")
+        .map(|v| v.cloned().collect())
+        .unwrap_or_default();
+    let variable_filter = match variable_names.is_empty() {
+        true => VariableFilter::All,
+        false => Select(variable_names)
+    };
+
+    commands::query::process_query(var_location, variable_filter)?;
+
+    Ok(())
+}
+
+async fn subcommand_step(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let function = matches.get_one::<String>("function").unwrap();
+
+    commands::step::process_step(function).await?;
+
+    Ok(())
+}
+
+async fn subcommand_history(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let variable_name = matches.get_one::<String>("variable_name").unwrap();
+    let function = matches.get_one::<String>("function").unwrap();
+
+    commands::history::process_history(variable_name, function).await?;
+
+    Ok(())
+}
+
+async fn subcommand_diff_run(matches: &ArgMatches) -> Result<(), Box<dyn std::error::Error>> {
+    let input_path_a = matches.get_one::<String>("input_path_a").unwrap();
+    let input_path_b = matches.get_one::<String>("input_path_b").unwrap();
+    let ignored_fields: Vec<String> = matches.get_many::<String>("ignore").map(|v| v.cloned().collect()).unwrap_or_default();
+
+    commands::diff_run::process_diff_run(PathBuf::from(input_path_a), PathBuf::from(input_path_b), &ignored_fields).await?;
+
+    Ok(())
+}
+
+/// Parse a location of `var` or `scope`
+fn get_var_location(location_str: &str, on_return: bool) -> Result<VarLocation, Box<dyn std::error::Error>> {
+    if let Some(name) = location_str.strip_prefix("fn:") {
+        return Ok(VarLocation::FnReturn { name: name.to_string() });
+    }
+    // Function first: `NAME:+5` would also be parsed as `FILE:LINE`
+    let var_location = match (try_get_fn_offset_format(location_str), try_get_file_line_format(location_str)) {
+        (Some((name, offset)), _) => match on_return {
+            true => VarLocation::FnReturn { name },
+            false => VarLocation::FnLine { name, offset },
+        },
+        (None, Ok((file, line_number, column))) => match on_return {
+            true => VarLocation::FnReturnAtLine { file, line: line_number },
+            false => VarLocation::Line { file, line: line_number, column },
+        },
+        (None, Err(err)) => Err(err)?,
+    };
+    Ok(var_location)
+}
+
+/// Parse `FILE:LINE` or `FILE:LINE:COLUMN`
+fn try_get_file_line_format(input: &str) -> Result<(String, usize, Option<usize>), String> {
+
+    let split: Vec<String> = input.rsplitn(3, ':').map(|v| v.to_string()).collect();
+    if split.len() == 3 {
+        if let (Ok(column), Ok(line_number)) = (split[0].parse::<usize>(), split[1].parse::<usize>()) {
+            return Ok((split[2].clone(), line_number, Some(column)));
+        }
+    }
 
     let split: Vec<String> = input.rsplitn(2, ':').map(|v| v.to_string()).collect();
 
@@ -115,5 +422,21 @@ fn try_get_file_line_format(input: &str) -> Result<(String, usize), String> {
 
     let file_path = split[1].clone();
 
-    Ok((file_path, line_number))
+    Ok((file_path, line_number, None))
+}
+
+/// Parse `NAME` or `NAME:+OFFSET`, where `NAME` is a path like `processor::process`
+fn try_get_fn_offset_format(input: &str) -> Option<(String, usize)> {
+    let (name, offset) = match input.rsplit_once(":+") {
+        Some((name, offset)) => (name, offset.parse::<usize>().ok()?),
+        None => (input, 0),
+    };
+    let is_path = name.split("::").all(|segment|
+        segment.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && segment.chars().all(|c| c.is_alphanumeric() || c == '_')
+    );
+    match is_path {
+        true => Some((name.to_string(), offset)),
+        false => None,
+    }
 }
\ No newline at end of file
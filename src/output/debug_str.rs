@@ -0,0 +1,186 @@
+/// Values up to this length are printed on a single line
+const DEBUG_STR_INLINE_WIDTH: usize = 60;
+
+/// Re-indent the output of `format!("{:?}", x)` so that it looks similar to `format!("{:#?}", x)`
+///
+/// Groups (`{ .. }`, `[ .. ]`, `( .. )`) that are short enough are kept on a single line
+pub fn pretty_print_debug_str(input: &str) -> String {
+    if input.len() <= DEBUG_STR_INLINE_WIDTH {
+        return input.to_string();
+    }
+
+    let chars: Vec<char> = input.chars().collect();
+    let closing = get_closing_brackets(&chars);
+
+    let mut out = String::new();
+    let mut indent: usize = 0;
+    let mut i: usize = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' | '\'' => {
+                let end = skip_literal(&chars, i);
+                out.extend(&chars[i..end]);
+                i = end;
+                continue;
+            }
+            '{' | '[' | '(' => {
+                match closing[i] {
+                    Some(close) if close + 1 - i <= DEBUG_STR_INLINE_WIDTH => {
+                        out.extend(&chars[i..=close]);
+                        i = close + 1;
+                        continue;
+                    }
+                    Some(_) => {
+                        out.push(c);
+                        indent += 1;
+                        push_newline(&mut out, indent);
+                        i = skip_spaces(&chars, i + 1);
+                        continue;
+                    }
+                    None => out.push(c),
+                }
+            }
+            '}' | ']' | ')' if indent > 0 => {
+                out.truncate(out.trim_end().len());
+                out.push(',');
+                indent -= 1;
+                push_newline(&mut out, indent);
+                out.push(c);
+            }
+            ',' if indent > 0 => {
+                out.push(',');
+                push_newline(&mut out, indent);
+                i = skip_spaces(&chars, i + 1);
+                continue;
+            }
+            _ => out.push(c),
+        }
+        i += 1;
+    }
+
+    out
+}
+
+fn push_newline(out: &mut String, indent: usize) {
+    out.push('\n');
+    out.push_str(&"    ".repeat(indent));
+}
+
+fn skip_spaces(chars: &[char], mut i: usize) -> usize {
+    while i < chars.len() && chars[i] == ' ' {
+        i += 1;
+    }
+    i
+}
+
+/// Get the index after the string or char literal starting at `start`
+///
+/// A single quote that doesn't start a char literal (e.g. in `don't`) is treated as a normal char
+fn skip_literal(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    if quote == '\'' {
+        let is_char_literal = chars.get(start + 1) == Some(&'\\') || chars.get(start + 2) == Some(&'\'');
+        if !is_char_literal {
+            return start + 1;
+        }
+    }
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            c if c == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+/// For each opening bracket, get the index of its closing bracket (if there is one)
+fn get_closing_brackets(chars: &[char]) -> Vec<Option<usize>> {
+    let mut result = vec![None; chars.len()];
+    let mut stack: Vec<usize> = Vec::new();
+    let mut i: usize = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '"' | '\'' => {
+                i = skip_literal(chars, i);
+                continue;
+            }
+            '{' | '[' | '(' => stack.push(i),
+            '}' | ']' | ')' => {
+                if let Some(open) = stack.pop() {
+                    result[open] = Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short() {
+        assert_eq!(pretty_print_debug_str("Point { x: 1, y: 2 }"), "Point { x: 1, y: 2 }");
+    }
+
+    #[test]
+    fn test_nested_structs() {
+        let input = "Config { owner: Pubkey([1, 2, 3]), limits: Limits { max_amount: 1000000, min_amount: 10 }, name: \"main\" }";
+        let expected = "\
+Config {
+    owner: Pubkey([1, 2, 3]),
+    limits: Limits { max_amount: 1000000, min_amount: 10 },
+    name: \"main\",
+}";
+        assert_eq!(pretty_print_debug_str(input), expected);
+    }
+
+    #[test]
+    fn test_enums() {
+        let input = "Some(Transfer { from: Account { key: 1, lamports: 500 }, to: Account { key: 2, lamports: 700 } })";
+        let expected = "\
+Some(
+    Transfer {
+        from: Account { key: 1, lamports: 500 },
+        to: Account { key: 2, lamports: 700 },
+    },
+)";
+        assert_eq!(pretty_print_debug_str(input), expected);
+    }
+
+    #[test]
+    fn test_literals() {
+        // Brackets and commas in string and char literals don't start groups, and an unclosed one doesn't change the indentation
+        let input = "Note { text: \"a {b}, c [d] (e\", sep: ',', open: '{', owner: \"don't\" }";
+        let expected = "\
+Note {
+    text: \"a {b}, c [d] (e\",
+    sep: ',',
+    open: '{',
+    owner: \"don't\",
+}";
+        assert_eq!(pretty_print_debug_str(input), expected);
+    }
+
+    #[test]
+    fn test_maps() {
+        let input = "{1: \"one\", 2: \"two\", 3: \"three\", 4: \"four\", 5: \"five\", 6: \"six\"}";
+        let expected = "\
+{
+    1: \"one\",
+    2: \"two\",
+    3: \"three\",
+    4: \"four\",
+    5: \"five\",
+    6: \"six\",
+}";
+        assert_eq!(pretty_print_debug_str(input), expected);
+    }
+}
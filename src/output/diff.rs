@@ -0,0 +1,281 @@
+use serde::Serialize;
+use crate::output::parse::{DebugNode, LineVars};
+
+/// A difference between two states of the same variables, see `diff_debug_nodes`
+#[derive(Debug, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum NodeChange {
+    /// E.g. a variable that came into scope or an element that was pushed
+    Added { path: String, node: DebugNode },
+    /// An element or field that is gone. Variables that go out of scope are not reported
+    Removed { path: String, node: DebugNode },
+    Changed { path: String, old_value: String, new_value: String },
+}
+
+/// Where a hit is in the same place again after the locations differ, see `align_hits`
+const REJOIN_LEN: usize = 3;
+
+/// How two executions of the same program differ, see `find_divergence`. Both `None` if they are the same
+#[derive(Debug, Default)]
+pub struct Divergence {
+    pub control_flow: Option<ControlFlowDivergence>,
+    /// The first aligned hits with different values. They can be before or after the control flow divergence
+    pub values: Option<ValueDivergence>,
+}
+
+/// The first hits at different locations, e.g. after a branch
+#[derive(Debug, PartialEq)]
+pub struct ControlFlowDivergence {
+    /// The first hit of each execution after the common ones. The length of the execution if it ends there
+    pub index_a: usize,
+    pub index_b: usize,
+    /// The hits where the executions are at the same location again. `None` if they don't meet again
+    pub rejoin: Option<(usize, usize)>,
+}
+
+#[derive(Debug)]
+pub struct ValueDivergence {
+    pub index_a: usize,
+    pub index_b: usize,
+    pub changes: Vec<NodeChange>,
+}
+
+/// Compare the hits of two executions, aligned by their locations
+///
+/// Changes of `ignored_fields` don't count, e.g. variables that come from the input. See `is_ignored_path`
+pub fn find_divergence(a: &[LineVars], b: &[LineVars], ignored_fields: &[String]) -> Divergence {
+    let mut divergence = Divergence::default();
+    let (mut index_a, mut index_b) = (0, 0);
+    for (aligned_a, aligned_b) in align_hits(a, b) {
+        if divergence.control_flow.is_none() && (aligned_a, aligned_b) != (index_a, index_b) {
+            divergence.control_flow = Some(ControlFlowDivergence { index_a, index_b, rejoin: Some((aligned_a, aligned_b)) });
+        }
+        (index_a, index_b) = (aligned_a + 1, aligned_b + 1);
+
+        if divergence.values.is_none() {
+            let mut changes = diff_debug_nodes(&a[aligned_a].nodes, &b[aligned_b].nodes);
+            changes.retain(|change| !is_ignored_path(change.path(), ignored_fields));
+            if !changes.is_empty() {
+                divergence.values = Some(ValueDivergence { index_a: aligned_a, index_b: aligned_b, changes });
+            }
+        }
+    }
+    // The hits after the last aligned ones
+    if divergence.control_flow.is_none() && (index_a < a.len() || index_b < b.len()) {
+        divergence.control_flow = Some(ControlFlowDivergence { index_a, index_b, rejoin: None });
+    }
+    divergence
+}
+
+/// Pairs of hits at the same location, in order
+///
+/// Where the locations differ, the executions continue from the nearest pair of hits that starts `REJOIN_LEN` hits at the same locations,
+/// e.g. after a loop that runs more often in one of them
+fn align_hits(a: &[LineVars], b: &[LineVars]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    let (mut index_a, mut index_b) = (0, 0);
+    while index_a < a.len() && index_b < b.len() {
+        if !is_same_location(&a[index_a], &b[index_b]) {
+            match find_rejoin(a, b, index_a, index_b) {
+                Some(rejoin) => (index_a, index_b) = rejoin,
+                None => break,
+            }
+        }
+        pairs.push((index_a, index_b));
+        index_a += 1;
+        index_b += 1;
+    }
+    pairs
+}
+
+/// The pair with the fewest hits skipped in both executions together
+fn find_rejoin(a: &[LineVars], b: &[LineVars], index_a: usize, index_b: usize) -> Option<(usize, usize)> {
+    let (remaining_a, remaining_b) = (a.len() - index_a, b.len() - index_b);
+    for skipped in 1..remaining_a + remaining_b - 1 {
+        let min_skipped_a = skipped.saturating_sub(remaining_b - 1);
+        let max_skipped_a = skipped.min(remaining_a - 1);
+        for skipped_a in min_skipped_a..=max_skipped_a {
+            let (rejoin_a, rejoin_b) = (index_a + skipped_a, index_b + skipped - skipped_a);
+            let len = REJOIN_LEN.min(a.len() - rejoin_a).min(b.len() - rejoin_b);
+            if (0..len).all(|offset| is_same_location(&a[rejoin_a + offset], &b[rejoin_b + offset])) {
+                return Some((rejoin_a, rejoin_b));
+            }
+        }
+    }
+    None
+}
+
+/// The same line, reached through the same calls
+fn is_same_location(a: &LineVars, b: &LineVars) -> bool {
+    a.line_num == b.line_num
+        && a.file == b.file
+        && a.cpi_depth == b.cpi_depth
+        && a.call_stack.len() == b.call_stack.len()
+        && a.call_stack.iter().zip(&b.call_stack).all(|(frame_a, frame_b)| frame_a.function == frame_b.function && frame_a.line == frame_b.line)
+}
+
+/// Compare the variables by name, and their children recursively
+///
+/// Paths are like `source.amount` or `accounts.0.lamports`
+pub fn diff_debug_nodes(old: &[DebugNode], new: &[DebugNode]) -> Vec<NodeChange> {
+    let mut changes = Vec::new();
+    for new_node in new {
+        match old.iter().find(|old_node| old_node.name == new_node.name) {
+            Some(old_node) => diff_debug_node(old_node, new_node, &new_node.name, &mut changes),
+            None => changes.push(NodeChange::Added { path: new_node.name.clone(), node: new_node.clone() }),
+        }
+    }
+    changes
+}
+
+fn diff_debug_node(old: &DebugNode, new: &DebugNode, path: &str, changes: &mut Vec<NodeChange>) {
+    if old.value != new.value || old.full_type != new.full_type {
+        changes.push(NodeChange::Changed {
+            path: path.to_string(),
+            old_value: get_value_string(old),
+            new_value: get_value_string(new),
+        });
+    }
+    for new_child in &new.children {
+        let child_path = format!("{}.{}", path, new_child.name);
+        match old.children.iter().find(|old_child| old_child.name == new_child.name) {
+            Some(old_child) => diff_debug_node(old_child, new_child, &child_path, changes),
+            None => changes.push(NodeChange::Added { path: child_path, node: new_child.clone() }),
+        }
+    }
+    for old_child in &old.children {
+        if !new.children.iter().any(|new_child| new_child.name == old_child.name) {
+            changes.push(NodeChange::Removed { path: format!("{}.{}", path, old_child.name), node: old_child.clone() });
+        }
+    }
+}
+
+/// Whether the path of a change is one of the fields, or a child of one
+///
+/// A field is a name (e.g. `key`) or a path (e.g. `accounts.*.key`) that can be at any depth. `*` matches any name
+pub fn is_ignored_path(path: &str, ignored_fields: &[String]) -> bool {
+    let names: Vec<&str> = path.split('.').collect();
+    ignored_fields.iter().any(|field| {
+        let field_names: Vec<&str> = field.split('.').collect();
+        names.windows(field_names.len()).any(|window| {
+            window.iter().zip(&field_names).all(|(name, field_name)| *field_name == "*" || name == field_name)
+        })
+    })
+}
+
+impl NodeChange {
+    pub fn path(&self) -> &str {
+        match self {
+            NodeChange::Added { path, .. } | NodeChange::Removed { path, .. } | NodeChange::Changed { path, .. } => path,
+        }
+    }
+}
+
+/// The value, or the type if there is none (e.g. for a struct)
+fn get_value_string(node: &DebugNode) -> String {
+    match node.value.is_empty() {
+        true => format!("({})", node.full_type),
+        false => node.value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::parse::DebugNodeType;
+
+    /// A hit of `src/lib.rs` with one variable `x`
+    fn hit(line_num: usize, x: u64) -> LineVars {
+        LineVars {
+            line_num,
+            file: Some("src/lib.rs".to_string()),
+            nodes: vec![DebugNode {
+                node_type: DebugNodeType::Primitive,
+                name: "x".to_string(),
+                full_type: "u64".to_string(),
+                value: x.to_string(),
+                children: vec![],
+            }],
+            call_stack: vec![],
+            cpi_depth: 1,
+        }
+    }
+
+    fn hits(lines: &[usize]) -> Vec<LineVars> {
+        lines.iter().map(|line| hit(*line, 0)).collect()
+    }
+
+    fn get_values(divergence: &Divergence) -> Option<(usize, usize)> {
+        divergence.values.as_ref().map(|values| (values.index_a, values.index_b))
+    }
+
+    #[test]
+    fn test_same() {
+        let divergence = find_divergence(&hits(&[1, 2, 3]), &hits(&[1, 2, 3]), &[]);
+        assert!(divergence.control_flow.is_none());
+        assert!(divergence.values.is_none());
+    }
+
+    #[test]
+    fn test_loop_rejoins() {
+        // The loop of lines 2 and 3 runs once more in B
+        let a = hits(&[1, 2, 3, 2, 3, 4, 5, 6]);
+        let mut b = hits(&[1, 2, 3, 2, 3, 2, 3, 4, 5, 6]);
+        b[8].nodes[0].value = "1".to_string();
+        let divergence = find_divergence(&a, &b, &[]);
+        assert_eq!(divergence.control_flow.as_ref(), Some(&ControlFlowDivergence { index_a: 5, index_b: 5, rejoin: Some((5, 7)) }));
+        // The hits after the loop are compared, not the ones at the same index
+        assert_eq!(get_values(&divergence), Some((6, 8)));
+    }
+
+    #[test]
+    fn test_values_before_control_flow() {
+        let a = vec![hit(1, 0), hit(2, 5), hit(3, 0)];
+        let b = vec![hit(1, 0), hit(2, 6), hit(4, 0)];
+        let divergence = find_divergence(&a, &b, &[]);
+        assert_eq!(get_values(&divergence), Some((1, 1)));
+        assert_eq!(divergence.control_flow.as_ref(), Some(&ControlFlowDivergence { index_a: 2, index_b: 2, rejoin: None }));
+    }
+
+    #[test]
+    fn test_ignored_fields() {
+        let a = vec![hit(1, 5), hit(2, 0)];
+        let b = vec![hit(1, 6), hit(2, 0)];
+        assert!(find_divergence(&a, &b, &[]).values.is_some());
+        assert!(find_divergence(&a, &b, &["x".to_string()]).values.is_none());
+    }
+
+    #[test]
+    fn test_ended() {
+        let divergence = find_divergence(&hits(&[1, 2, 3]), &hits(&[1, 2, 3, 4]), &[]);
+        assert_eq!(divergence.control_flow.as_ref(), Some(&ControlFlowDivergence { index_a: 3, index_b: 3, rejoin: None }));
+    }
+
+    #[test]
+    fn test_ignored_path() {
+        let ignored_fields = ["key".to_string(), "accounts.*.lamports".to_string()];
+        assert!(is_ignored_path("key", &ignored_fields));
+        // At any depth, and with its children
+        assert!(is_ignored_path("source.key", &ignored_fields));
+        assert!(is_ignored_path("key.0", &ignored_fields));
+        assert!(is_ignored_path("accounts.0.lamports", &ignored_fields));
+        assert!(is_ignored_path("ctx.accounts.1.lamports", &ignored_fields));
+        // Only whole names match
+        assert!(!is_ignored_path("keys", &ignored_fields));
+        assert!(!is_ignored_path("accounts.0.owner", &ignored_fields));
+        assert!(!is_ignored_path("accounts.lamports", &ignored_fields));
+        assert!(!is_ignored_path("amount", &[]));
+    }
+
+    #[test]
+    fn test_node_change_json() {
+        let change = NodeChange::Changed { path: "x".to_string(), old_value: "1".to_string(), new_value: "2".to_string() };
+        assert_eq!(serde_json::to_string(&change).unwrap(), r#"{"change":"changed","path":"x","old_value":"1","new_value":"2"}"#);
+    }
+
+    #[test]
+    fn test_no_rejoin() {
+        let divergence = find_divergence(&hits(&[1, 2, 9, 9, 9]), &hits(&[1, 2, 8, 8, 8]), &[]);
+        assert_eq!(divergence.control_flow.as_ref(), Some(&ControlFlowDivergence { index_a: 2, index_b: 2, rejoin: None }));
+    }
+}
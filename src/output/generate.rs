@@ -1,6 +1,8 @@
 use std::path::Path;
 use std::sync::{Arc, RwLock};
 use solana_program_test::{find_file, ProgramTest};
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
 use crate::utils::program_input::ProgramInput;
 
 struct OutputLogger {
@@ -21,6 +23,15 @@ impl log::Log for OutputLogger {
     fn flush(&self) {}
 }
 
+#[derive(Debug)]
+pub struct ProgramOutput {
+    pub logs: Vec<String>,
+    /// Set if the transaction failed
+    pub tx_error: Option<String>,
+    /// The accounts of the transaction after it ran, without programs. Only fetched if requested
+    pub accounts: Option<Vec<(Pubkey, Account)>>,
+}
+
 pub fn set_output_logger() -> Result<Arc<RwLock<Vec<String>>>, Box<dyn std::error::Error>> {
     let output_logger = OutputLogger { output: Arc::new(RwLock::new(Vec::new())) };
     let output_clone = Arc::clone(&output_logger.output);
@@ -31,12 +42,14 @@ pub fn set_output_logger() -> Result<Arc<RwLock<Vec<String>>>, Box<dyn std::erro
     Ok(output_clone)
 }
 
+/// `fetch_accounts`: also fetch the accounts of the transaction after it ran, see `ProgramOutput::accounts`
 pub async fn generate_program_output(
     program_dir: &Path,
     program_name: &str,
     input: ProgramInput,
-    output_log: Arc<RwLock<Vec<String>>>
-) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    output_log: Arc<RwLock<Vec<String>>>,
+    fetch_accounts: bool,
+) -> Result<ProgramOutput, Box<dyn std::error::Error>> {
 
     std::env::set_var("BPF_OUT_DIR", program_dir.to_str().unwrap());
     let program_so_filename = format!("{program_name}.so");
@@ -56,9 +69,23 @@ pub async fn generate_program_output(
     let (banks_client, _payer, recent_blockhash) = program_test.start().await;
     let mut transaction = input.transaction;
     transaction.sign(&input.keypairs, recent_blockhash);
-    let _tx_result = banks_client.process_transaction(transaction).await?;
-    //dbg!(&tx_result);
+    let account_keys = transaction.message.account_keys.clone();
+    // A failed transaction still has logs, e.g. the variables before the error
+    let tx_error = banks_client.process_transaction(transaction).await.err().map(|e| e.to_string());
+    //dbg!(&tx_error);
+
+    let mut accounts = None;
+    if fetch_accounts {
+        let mut fetched = Vec::new();
+        for pubkey in account_keys {
+            match banks_client.get_account(pubkey).await? {
+                Some(account) if !account.executable => fetched.push((pubkey, account)),
+                _ => {}
+            }
+        }
+        accounts = Some(fetched);
+    }
 
-    let output = output_log.read().unwrap().clone();
-    Ok(output)
+    let logs = output_log.read().unwrap().clone();
+    Ok(ProgramOutput { logs, tx_error, accounts })
 }
\ No newline at end of file
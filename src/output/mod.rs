@@ -1,7 +1,15 @@
 pub mod generate;
 pub mod parse;
 pub mod print_node;
+pub mod debug_str;
+pub mod spl_token;
+pub mod panic;
+pub mod profile;
+pub mod diff;
 
 pub use generate::*;
 pub use parse::*;
 pub use print_node::*;
+pub use panic::*;
+pub use profile::*;
+pub use diff::*;
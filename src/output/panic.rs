@@ -0,0 +1,108 @@
+use std::path::Path;
+use crate::instrument::map_to_original_line;
+
+/// A panic of the program, e.g. an overflow or `unwrap` on `None`
+#[derive(Debug)]
+pub struct ProgramPanic {
+    /// Relative to the root of the compiled project, e.g. `src/lib.rs`
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+    /// Whether `line` is a line of the original file (see `map_panic_to_original`)
+    pub is_original_line: bool,
+}
+
+/// Find the panic message in the program logs
+///
+/// The message is either `panicked at FILE:LINE:COLUMN:\nMESSAGE` or (older) `panicked at 'MESSAGE', FILE:LINE:COLUMN`
+pub fn find_program_panic(logs: &[String]) -> Option<ProgramPanic> {
+    logs.iter().find_map(|log| {
+        let rest = log.strip_prefix("Program log: ")?.strip_prefix("panicked at ")?;
+        let (location, message) = match rest.strip_prefix('\'') {
+            Some(rest) => {
+                let (message, location) = rest.rsplit_once("', ")?;
+                (location, message)
+            }
+            None => rest.split_once(":\n").unwrap_or((rest.trim_end_matches(':'), "")),
+        };
+        let (file, line) = parse_panic_location(location)?;
+        Some(ProgramPanic {
+            file,
+            line,
+            message: message.to_string(),
+            is_original_line: false,
+        })
+    })
+}
+
+/// `FILE:LINE:COLUMN` to file and line
+fn parse_panic_location(location: &str) -> Option<(String, usize)> {
+    let split: Vec<&str> = location.rsplitn(3, ':').collect();
+    if split.len() != 3 {
+        return None;
+    }
+    let line = split[1].parse().ok()?;
+    Some((split[2].to_string(), line))
+}
+
+/// The panic location refers to the instrumented files. Map it back using their source maps
+///
+/// Panics in dependencies (e.g. in `~/.cargo/registry`) are left unchanged
+pub fn map_panic_to_original(panic: &mut ProgramPanic, inst_workspace_root: &Path) {
+    if let Some(line) = map_to_original_line(&inst_workspace_root.join(&panic.file), panic.line) {
+        panic.line = line;
+        panic.is_original_line = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instrument::write_source_map;
+
+    #[test]
+    fn test_find_program_panic() {
+        let logs = vec![
+            "Program log: x = 1".to_string(),
+            "Program log: panicked at src/lib.rs:12:5:\nattempt to add with overflow".to_string(),
+        ];
+        let panic = find_program_panic(&logs).unwrap();
+        assert_eq!((panic.file.as_str(), panic.line, panic.message.as_str()), ("src/lib.rs", 12, "attempt to add with overflow"));
+
+        let logs = vec!["Program log: panicked at 'called `Option::unwrap()` on a `None` value', src/lib.rs:7:18".to_string()];
+        let panic = find_program_panic(&logs).unwrap();
+        assert_eq!((panic.file.as_str(), panic.line, panic.message.as_str()), ("src/lib.rs", 7, "called `Option::unwrap()` on a `None` value"));
+    }
+
+    #[test]
+    fn test_map_panic_to_original() {
+        let source = "fn f(a: u8) -> u8 {\n    let b = a + 1;\n    b\n}\n";
+        let mut ast = syn::parse_file(source).unwrap();
+        if let Some(syn::Item::Fn(item_fn)) = ast.items.first_mut() {
+            item_fn.block.stmts.insert(0, syn::parse_quote! { msg!("a = {}", a); });
+        }
+        let output = prettyplease::unparse(&ast);
+
+        let inst_workspace_root = std::env::temp_dir().join(format!("solana_debugger_test_panic_{}", std::process::id()));
+        let inst_file_path = inst_workspace_root.join("src/lib.rs");
+        std::fs::create_dir_all(inst_file_path.parent().unwrap()).unwrap();
+        std::fs::write(&inst_file_path, &output).unwrap();
+        write_source_map(&ast, source, &output, &inst_file_path, false).unwrap();
+
+        // The addition is on line 3 of the instrumented file, after the inserted `msg!`
+        assert_eq!(output.lines().nth(2), Some("    let b = a + 1;"));
+        let logs = vec!["Program log: panicked at src/lib.rs:3:13:\nattempt to add with overflow".to_string()];
+        let mut panic = find_program_panic(&logs).unwrap();
+        map_panic_to_original(&mut panic, &inst_workspace_root);
+
+        // A panic in a dependency has no source map and keeps its location
+        let logs = vec!["Program log: panicked at /root/.cargo/registry/src/dep/src/lib.rs:3:13:\noverflow".to_string()];
+        let mut dependency_panic = find_program_panic(&logs).unwrap();
+        map_panic_to_original(&mut dependency_panic, &inst_workspace_root);
+
+        std::fs::remove_dir_all(&inst_workspace_root).unwrap();
+
+        assert_eq!((panic.line, panic.is_original_line), (2, true));
+        assert_eq!((dependency_panic.line, dependency_panic.is_original_line), (3, false));
+    }
+}
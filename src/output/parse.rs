@@ -1,16 +1,21 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::fmt::Debug;
 use base64::Engine;
 use base64::engine::general_purpose;
+use serde::{Deserialize, Serialize};
+use solana_sdk::hash::Hash;
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use crate::output::debug_str::pretty_print_debug_str;
+use crate::output::spl_token::decode_token_accounts;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DebugNodeType {
     Primitive,
     Complex,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DebugNode {
     pub node_type: DebugNodeType,
     pub name: String,
@@ -19,11 +24,30 @@ pub struct DebugNode {
     pub children: Vec<DebugNode>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct LineVars {
     pub line_num: usize,
+    /// Only logged when many files are instrumented, e.g. `src/processor.rs`
+    pub file: Option<String>,
     // Use a Vec to retain the order
     pub nodes: Vec<DebugNode>,
+    /// The functions of the program that led here. The first one is the outermost
+    pub call_stack: Vec<StackFrame>,
+    /// 1 for the instruction of the transaction, 2 for a program it invokes (CPI) etc.
+    pub cpi_depth: usize,
+}
+
+/// A function call of the program, see `inst_ast_call_stack`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StackFrame {
+    /// E.g. `Processor::process`
+    pub function: String,
+    /// E.g. `src/processor.rs`
+    pub file: String,
+    /// The line that is executed in this function, i.e. the call of the next frame. `None` if unknown
+    pub line: Option<usize>,
+    /// The arguments when the function was entered. Empty unless they are logged
+    pub args: Vec<DebugNode>,
 }
 
 #[derive(Debug, Clone)]
@@ -40,32 +64,73 @@ static BASE64_ENGINE: general_purpose::GeneralPurpose = general_purpose::STANDAR
 pub fn parse_program_output(output: Vec<String>) -> Result<Vec<LineVars>, Box<dyn std::error::Error>> {
     let cleaned = clean_program_output(output);
     let mut result: Vec<LineVars> = Vec::new();
+    // The call stacks of the nested program invocations. The last one is the current
+    let mut invocations: Vec<Vec<StackFrame>> = Vec::new();
     let mut it = cleaned.into_iter();
     while let Some(line) = it.next() {
+        if line == "-.!;INVOKE" {
+            invocations.push(Vec::new());
+            continue;
+        }
+        if line == "-.!;INVOKE_END" {
+            invocations.pop();
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("-.!;FN_ENTER;") {
+            // -.!;FN_ENTER;FUNCTION;FILE, then the arguments until -.!;FN_ENTER_END
+            let (function, file) = rest.split_once(';').ok_or(OutputParseError(format!("Invalid line: {}", line)))?;
+            let Some(args_block) = consume_block(&mut it, "-.!;FN_ENTER_END") else {
+                break;
+            };
+            if invocations.is_empty() {
+                invocations.push(Vec::new());
+            }
+            invocations.last_mut().unwrap().push(StackFrame {
+                function: function.to_string(),
+                file: file.to_string(),
+                line: None,
+                args: parse_line_vars_nodes(args_block)?,
+            });
+            continue;
+        }
+        if line == "-.!;FN_EXIT" {
+            invocations.last_mut().and_then(|frames| frames.pop());
+            continue;
+        }
+        if let Some(call_line) = line.strip_prefix("-.!;AT;") {
+            if let Some(frame) = invocations.last_mut().and_then(|frames| frames.last_mut()) {
+                frame.line = Some(call_line.parse()?);
+            }
+            continue;
+        }
         if !line.starts_with("-.!;LINE_START") {
             continue;
         }
-        let split: Vec<&str> = line.split(';').collect();
-        if split.len() != 3 {
+        // -.!;LINE_START;LINE or -.!;LINE_START;LINE;FILE
+        let split: Vec<&str> = line.splitn(4, ';').collect();
+        if split.len() < 3 {
             Err(OutputParseError(format!("Invalid line: {}", line)))?
         }
         let line_num: usize = split[2].parse()?;
-        let mut line_block: VecDeque<String> = VecDeque::new();
-        loop {
-            match it.next() {
-                Some(line_2) => {
-                    if line_2 == "-.!;LINE_END" {
-                        break;
-                    }
-                    line_block.push_back(line_2);
-                }
-                _ => Err(OutputParseError("LINE_END not found".into()))?
-            }
+        let file = split.get(3).map(|file| file.to_string());
+        let Some(line_block) = consume_block(&mut it, "-.!;LINE_END") else {
+            // The program stopped while logging, e.g. it panicked or ran out of compute units
+            break;
+        };
+        let mut line_nodes = parse_line_vars_nodes(line_block)?;
+        for node in line_nodes.iter_mut() {
+            decode_token_accounts(node);
+        }
+        let mut call_stack = invocations.last().cloned().unwrap_or_default();
+        if let Some(frame) = call_stack.last_mut() {
+            frame.line = Some(line_num);
         }
-        let line_nodes = parse_line_vars_nodes(line_block)?;
         result.push(LineVars {
             line_num,
+            file,
             nodes: line_nodes,
+            call_stack,
+            cpi_depth: invocations.len().max(1),
         })
     }
     /*
@@ -77,9 +142,65 @@ pub fn parse_program_output(output: Vec<String>) -> Result<Vec<LineVars>, Box<dy
     Ok(result)
 }
 
+/// Hit counts of a coverage run, see `inst_ast_coverage`
+#[derive(Debug, Default)]
+pub struct CoverageHits {
+    /// File (e.g. `src/processor.rs`) to line to number of hits
+    pub files: BTreeMap<String, BTreeMap<usize, usize>>,
+    /// The runtime stopped logging, so later hits are missing
+    pub truncated: bool,
+}
+
+/// Add the hits in the output of one run
+pub fn parse_coverage_hits(output: Vec<String>, hits: &mut CoverageHits) -> Result<(), Box<dyn std::error::Error>> {
+    for line in output {
+        if line == "Log truncated" {
+            hits.truncated = true;
+        }
+        let Some(rest) = line.strip_prefix("Program log: -.!;HIT;") else {
+            continue;
+        };
+        // -.!;HIT;LINE;FILE
+        let (line_num, file) = rest.split_once(';').ok_or(OutputParseError(format!("Invalid line: {}", line)))?;
+        *hits.files.entry(file.to_string()).or_default().entry(line_num.parse()?).or_default() += 1;
+    }
+    Ok(())
+}
+
+/// Get the lines until `end`. `None` if the output stops before
+///
+/// Call stack markers in between are skipped. They come from functions that are called while logging, e.g. a custom `Debug` implementation
+fn consume_block(it: &mut impl Iterator<Item = String>, end: &str) -> Option<VecDeque<String>> {
+    let mut block: VecDeque<String> = VecDeque::new();
+    let mut nested_enters = 0;
+    for line in it {
+        if line == end && nested_enters == 0 {
+            return Some(block);
+        }
+        match line.as_str() {
+            _ if line.starts_with("-.!;FN_ENTER;") => nested_enters += 1,
+            "-.!;FN_ENTER_END" => nested_enters -= 1,
+            "-.!;FN_EXIT" => {}
+            _ if line.starts_with("-.!;AT;") => {}
+            _ if nested_enters > 0 => {}
+            _ => block.push_back(line),
+        }
+    }
+    None
+}
+
 fn clean_program_output(output: Vec<String>) -> Vec<String> {
     let mut result = vec![];
     for line in output {
+        // E.g. `Program 11111111111111111111111111111111 invoke [1]`, then `Program 11111111111111111111111111111111 success`
+        if let Some(rest) = line.strip_prefix("Program ").filter(|_| !line.starts_with("Program log:") && !line.starts_with("Program data:")) {
+            if rest.contains(" invoke [") {
+                result.push("-.!;INVOKE".to_string());
+            } else if rest.ends_with(" success") || rest.contains(" failed: ") {
+                result.push("-.!;INVOKE_END".to_string());
+            }
+            continue;
+        }
         if !(line.starts_with("Program log:") || line.starts_with("Program data:")) {
             continue;
         }
@@ -134,6 +255,29 @@ fn consume_debug_node(lines: &mut VecDeque<String>) -> Result<DebugNode, OutputP
             let integer = u128::from_le_bytes(byte_arr);
             integer.to_string()
         }
+        "float" => {
+            let data_line = lines.pop_front().ok_or(OutputParseError("Not enough lines".into()))?;
+            let decoded = BASE64_ENGINE.decode(data_line).map_err(|_| OutputParseError("Decode error".into()))?;
+            // f32 is logged with 4 bytes, f64 with 8 bytes
+            match decoded.len() {
+                4 => {
+                    let byte_arr: [u8; 4] = decoded.try_into().map_err(|_| OutputParseError("Decode error".into()))?;
+                    format!("{:?}", f32::from_le_bytes(byte_arr))
+                }
+                8 => {
+                    let byte_arr: [u8; 8] = decoded.try_into().map_err(|_| OutputParseError("Decode error".into()))?;
+                    format!("{:?}", f64::from_le_bytes(byte_arr))
+                }
+                _ => Err(OutputParseError("Decode error: Invalid length".into()))?
+            }
+        }
+        "char" => {
+            let data_line = lines.pop_front().ok_or(OutputParseError("Not enough lines".into()))?;
+            let decoded = BASE64_ENGINE.decode(data_line).map_err(|_| OutputParseError("Decode error".into()))?;
+            let byte_arr: [u8; 4] = decoded.try_into().map_err(|_| OutputParseError("Decode error".into()))?;
+            let char_val = char::from_u32(u32::from_le_bytes(byte_arr)).ok_or(OutputParseError("Decode error: Invalid char".into()))?;
+            format!("{:?}", char_val)
+        }
         "bool" => {
             let data_line = lines.pop_front().ok_or(OutputParseError("Not enough lines".into()))?;
             let decoded = BASE64_ENGINE.decode(data_line).map_err(|_| OutputParseError("Decode error".into()))?;
@@ -152,6 +296,14 @@ fn consume_debug_node(lines: &mut VecDeque<String>) -> Result<DebugNode, OutputP
             let data_line = lines.pop_front().ok_or(OutputParseError("Not enough lines".into()))?;
             data_line
         }
+        "debug_str" => {
+            let data_line = lines.pop_front().ok_or(OutputParseError("Not enough lines".into()))?;
+            pretty_print_debug_str(&data_line)
+        }
+        "unavailable" => {
+            let data_line = lines.pop_front().ok_or(OutputParseError("Not enough lines".into()))?;
+            format!("[not available: {}]", data_line)
+        }
         "error_str" => {
             let data_line = lines.pop_front().ok_or(OutputParseError("Not enough lines".into()))?;
             format!(r#"Error: {}"#, data_line)
@@ -175,7 +327,7 @@ fn consume_debug_node(lines: &mut VecDeque<String>) -> Result<DebugNode, OutputP
 
             format!("strong_count={}, weak_count={}", strong_count, weak_count)
         }
-        "array_len" => {
+        "array_len" | "map_len" => {
             let data_line = lines.pop_front().ok_or(OutputParseError("Not enough lines".into()))?;
             let decoded = BASE64_ENGINE.decode(data_line).map_err(|_| OutputParseError("Decode error".into()))?;
             let byte_arr: [u8; 16] = decoded.try_into().map_err(|_| OutputParseError("Decode error".into()))?;
@@ -192,6 +344,22 @@ fn consume_debug_node(lines: &mut VecDeque<String>) -> Result<DebugNode, OutputP
 
             pubkey.to_string()
         }
+        "hash" => {
+            let data_line = lines.pop_front().ok_or(OutputParseError("Not enough lines".into()))?;
+            let decoded = BASE64_ENGINE.decode(data_line).map_err(|_| OutputParseError("Decode error".into()))?;
+            let byte_arr: [u8; 32] = decoded.try_into().map_err(|_| OutputParseError("Decode error".into()))?;
+            let hash = Hash::new_from_array(byte_arr);
+
+            hash.to_string()
+        }
+        "signature" => {
+            let data_line = lines.pop_front().ok_or(OutputParseError("Not enough lines".into()))?;
+            let decoded = BASE64_ENGINE.decode(data_line).map_err(|_| OutputParseError("Decode error".into()))?;
+            let byte_arr: [u8; 64] = decoded.try_into().map_err(|_| OutputParseError("Decode error".into()))?;
+            let signature = Signature::from(byte_arr);
+
+            signature.to_string()
+        }
         x => {
             Err(OutputParseError(format!("Unimplemented: {}", x)))?
         }
@@ -208,6 +376,10 @@ fn consume_debug_node(lines: &mut VecDeque<String>) -> Result<DebugNode, OutputP
         children.push(child);
     }
 
+    if ser_type == "map_len" {
+        children = group_map_entries(children)?;
+    }
+
     match lines.pop_front() {
         None => Err(OutputParseError("Not enough lines".into()))?,
         Some(v) => {
@@ -226,3 +398,201 @@ fn consume_debug_node(lines: &mut VecDeque<String>) -> Result<DebugNode, OutputP
     };
     Ok(node)
 }
+
+/// Map entries are logged as a flat list of key and value nodes
+/// Turn each pair into a single node: If the key is a simple value, it becomes the name of the value node.
+/// Otherwise, we create an entry node that holds both
+fn group_map_entries(children: Vec<DebugNode>) -> Result<Vec<DebugNode>, OutputParseError> {
+    if !children.len().is_multiple_of(2) {
+        Err(OutputParseError("Map entry without value".into()))?
+    }
+    let mut result = Vec::new();
+    let mut it = children.into_iter();
+    let mut index = 0;
+    while let (Some(key), Some(mut value)) = (it.next(), it.next()) {
+        let is_simple_key = matches!(key.node_type, DebugNodeType::Primitive) && key.children.is_empty() && !key.value.is_empty();
+        if is_simple_key {
+            value.name = key.value;
+            result.push(value);
+        } else {
+            result.push(DebugNode {
+                node_type: DebugNodeType::Complex,
+                name: index.to_string(),
+                full_type: "".to_string(),
+                value: "".to_string(),
+                children: vec![key, value],
+            });
+        }
+        index += 1;
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVOKE_1: &str = "Program 11111111111111111111111111111111 invoke [1]";
+    const INVOKE_2: &str = "Program 22222222222222222222222222222222 invoke [2]";
+    const SUCCESS_1: &str = "Program 11111111111111111111111111111111 success";
+    const SUCCESS_2: &str = "Program 22222222222222222222222222222222 success";
+
+    fn log(line: &str) -> String {
+        format!("Program log: {}", line)
+    }
+
+    /// The logs of a variable that is serialized as its `Debug` string
+    fn var_logs(name: &str, value: &str) -> Vec<String> {
+        ["START_NODE", "primitive", name, "u64", "str_ident", value, "END_NODE"].iter().map(|line| log(line)).collect()
+    }
+
+    fn get_output(parts: Vec<Vec<String>>) -> Vec<String> {
+        parts.into_iter().flatten().collect()
+    }
+
+    fn consume(lines: &[&str]) -> DebugNode {
+        let mut lines: VecDeque<String> = lines.iter().map(|line| line.to_string()).collect();
+        consume_debug_node(&mut lines).unwrap()
+    }
+
+    fn get_frames(item: &LineVars) -> Vec<(&str, Option<usize>)> {
+        item.call_stack.iter().map(|frame| (frame.function.as_str(), frame.line)).collect()
+    }
+
+    #[test]
+    fn test_call_stack() {
+        let output = get_output(vec![
+            vec![INVOKE_1.to_string()],
+            vec![log("-.!;FN_ENTER;process;src/lib.rs")],
+            var_logs("amount", "5"),
+            vec![log("-.!;FN_ENTER_END"), log("-.!;AT;12")],
+            vec![log("-.!;FN_ENTER;Processor::transfer;src/processor.rs"), log("-.!;FN_ENTER_END")],
+            vec![log("-.!;LINE_START;30;src/processor.rs")],
+            var_logs("balance", "7"),
+            vec![log("-.!;LINE_END"), log("-.!;FN_EXIT"), log("-.!;LINE_START;13;src/lib.rs"), log("-.!;LINE_END"), log("-.!;FN_EXIT")],
+            vec![SUCCESS_1.to_string()],
+        ]);
+        let line_vars = parse_program_output(output).unwrap();
+
+        assert_eq!(line_vars.len(), 2);
+        assert_eq!(get_frames(&line_vars[0]), vec![("process", Some(12)), ("Processor::transfer", Some(30))]);
+        assert_eq!(line_vars[0].call_stack[0].args[0].value, "5");
+        assert_eq!(line_vars[0].call_stack[1].file, "src/processor.rs");
+        assert_eq!(line_vars[0].nodes[0].value, "7");
+        assert_eq!(get_frames(&line_vars[1]), vec![("process", Some(13))]);
+        assert_eq!(line_vars[1].cpi_depth, 1);
+    }
+
+    #[test]
+    fn test_invoke() {
+        let output = get_output(vec![
+            vec![INVOKE_1.to_string(), log("-.!;FN_ENTER;process;src/lib.rs"), log("-.!;FN_ENTER_END"), log("-.!;AT;20")],
+            vec![INVOKE_2.to_string(), log("-.!;FN_ENTER;callee;src/lib.rs"), log("-.!;FN_ENTER_END")],
+            vec![log("-.!;LINE_START;5;src/lib.rs"), log("-.!;LINE_END")],
+            // The invoked program stops without leaving its frame
+            vec![SUCCESS_2.to_string()],
+            vec![log("-.!;LINE_START;21;src/lib.rs"), log("-.!;LINE_END"), log("-.!;FN_EXIT"), SUCCESS_1.to_string()],
+        ]);
+        let line_vars = parse_program_output(output).unwrap();
+
+        assert_eq!(line_vars.len(), 2);
+        assert_eq!(get_frames(&line_vars[0]), vec![("callee", Some(5))]);
+        assert_eq!(line_vars[0].cpi_depth, 2);
+        assert_eq!(get_frames(&line_vars[1]), vec![("process", Some(21))]);
+        assert_eq!(line_vars[1].cpi_depth, 1);
+    }
+
+    #[test]
+    fn test_calls_while_logging() {
+        // A custom `Debug` implementation calls a function of the crate while the variable is logged
+        let output = get_output(vec![
+            vec![log("-.!;LINE_START;8"), log("START_NODE"), log("primitive"), log("x"), log("Wrapper"), log("debug_str")],
+            vec![log("-.!;AT;40"), log("-.!;FN_ENTER;format_inner;src/lib.rs"), log("-.!;FN_ENTER_END"), log("-.!;FN_EXIT")],
+            vec![log("Wrapper(1)"), log("END_NODE"), log("-.!;LINE_END")],
+        ]);
+        let line_vars = parse_program_output(output).unwrap();
+
+        assert_eq!(line_vars.len(), 1);
+        assert_eq!(line_vars[0].file, None);
+        assert_eq!(line_vars[0].nodes[0].value, "Wrapper(1)");
+        assert!(line_vars[0].call_stack.is_empty());
+    }
+
+    #[test]
+    fn test_truncated_output() {
+        let output = get_output(vec![
+            vec![log("-.!;LINE_START;3"), log("-.!;LINE_END")],
+            vec![log("-.!;FN_ENTER;process;src/lib.rs")],
+            var_logs("amount", "5"),
+            vec!["Log truncated".to_string()],
+        ]);
+        let line_vars = parse_program_output(output).unwrap();
+
+        assert_eq!(line_vars.len(), 1);
+        assert_eq!(line_vars[0].line_num, 3);
+    }
+    #[test]
+    fn test_signature() {
+        let signature = Signature::from([7u8; 64]);
+        let data = BASE64_ENGINE.encode(signature.as_ref());
+        let node = consume(&["START_NODE", "complex", "sig", "solana_signature::Signature", "signature", &data, "END_NODE"]);
+
+        assert_eq!(node.value, signature.to_string());
+    }
+    fn encode_u128(value: u128) -> String {
+        BASE64_ENGINE.encode(value.to_le_bytes())
+    }
+
+    /// The lines of a leaf node, e.g. `u8` logged as `uint`
+    fn leaf_lines<'a>(name: &'a str, full_type: &'a str, ser_type: &'a str, data: &'a str) -> Vec<&'a str> {
+        vec!["START_NODE", "primitive", name, full_type, ser_type, data, "END_NODE"]
+    }
+
+    #[test]
+    fn test_float() {
+        let f32_data = BASE64_ENGINE.encode(1.5f32.to_le_bytes());
+        assert_eq!(consume(&leaf_lines("x", "f32", "float", &f32_data)).value, "1.5");
+
+        let f64_data = BASE64_ENGINE.encode((-0.25f64).to_le_bytes());
+        assert_eq!(consume(&leaf_lines("x", "f64", "float", &f64_data)).value, "-0.25");
+    }
+
+    #[test]
+    fn test_char() {
+        let data = BASE64_ENGINE.encode(('é' as u32).to_le_bytes());
+        assert_eq!(consume(&leaf_lines("c", "char", "char", &data)).value, "'é'");
+    }
+
+    #[test]
+    fn test_map() {
+        let (len, one, two, ten) = (encode_u128(2), encode_u128(1), encode_u128(2), encode_u128(10));
+        let mut lines = vec!["START_NODE", "complex", "map", "BTreeMap<u8, u64>", "map_len", &len];
+        // A simple key becomes the name of its value
+        lines.extend(leaf_lines("key", "u8", "uint", &one));
+        lines.extend(leaf_lines("value", "u64", "uint", &ten));
+        // Other keys get an entry node with both
+        lines.extend(["START_NODE", "complex", "key", "(u8,)", "no_data"]);
+        lines.extend(leaf_lines("0", "u8", "uint", &two));
+        lines.push("END_NODE");
+        lines.extend(leaf_lines("value", "u64", "uint", &ten));
+        lines.push("END_NODE");
+
+        let node = consume(&lines);
+        assert_eq!(node.value, "len=2");
+        assert_eq!(node.children.len(), 2);
+        assert_eq!((node.children[0].name.as_str(), node.children[0].value.as_str()), ("1", "10"));
+        let entry = &node.children[1];
+        assert_eq!(entry.name, "1");
+        let entry_children: Vec<&str> = entry.children.iter().map(|child| child.name.as_str()).collect();
+        assert_eq!(entry_children, vec!["key", "value"]);
+        assert_eq!(entry.children[0].children[0].value, "2");
+    }
+
+    #[test]
+    fn test_empty_map() {
+        let len = encode_u128(0);
+        let node = consume(&["START_NODE", "complex", "map", "HashMap<u8, u64>", "map_len", &len, "END_NODE"]);
+        assert_eq!(node.value, "len=0");
+        assert!(node.children.is_empty());
+    }
+}
@@ -42,8 +42,12 @@ pub fn print_debug_node_colored(node: &DebugNode, indent: usize) {
         _ => format!("({})", node.full_type)
     }.italic().cyan();
 
+    // Multi-line values (e.g. pretty-printed Debug output) go below the node
+    let is_multi_line = node.value.contains('\n');
+
     let value_str = match node.value.len() {
         0 => "".to_string(),
+        _ if is_multi_line => "".to_string(),
         _ => node.value.clone()
     }.bright_purple();
 
@@ -51,6 +55,13 @@ pub fn print_debug_node_colored(node: &DebugNode, indent: usize) {
 
     println!("{}{} {}: {}{}{}", indent_str, node_type, name_str, value_str, gap_str, type_str);
 
+    if is_multi_line {
+        let value_indent_str = "  ".repeat(indent + 2);
+        for value_line in node.value.lines() {
+            println!("{}{}", value_indent_str, value_line.bright_purple());
+        }
+    }
+
     for (i, child) in node.children.iter().enumerate() {
         if i < PRINT_MAX_CHILDREN {
             print_debug_node_colored(child, indent + 1);
@@ -61,4 +72,33 @@ pub fn print_debug_node_colored(node: &DebugNode, indent: usize) {
             break
         }
     }
-}
\ No newline at end of file
+}
+
+/// The node on a single line, e.g. `{ amount: 5, owner: 11111111111111111111111111111111 }`
+///
+/// Longer values are cut at `max_len` characters
+pub fn get_compact_value(node: &DebugNode, max_len: usize) -> String {
+    get_compact_str(&get_compact_value_inner(node), max_len)
+}
+
+/// A value on a single line, e.g. pretty-printed Debug output. Cut at `max_len` characters
+pub fn get_compact_str(value: &str, max_len: usize) -> String {
+    let value = value.lines().map(|line| line.trim()).collect::<Vec<_>>().join(" ");
+    match value.chars().count() > max_len {
+        true => format!("{}...", value.chars().take(max_len.saturating_sub(3)).collect::<String>()),
+        false => value,
+    }
+}
+
+fn get_compact_value_inner(node: &DebugNode) -> String {
+    if !node.value.is_empty() {
+        return node.value.clone();
+    }
+    if node.children.is_empty() {
+        return format!("({})", node.full_type);
+    }
+    let children: Vec<String> = node.children.iter()
+        .map(|child| format!("{}: {}", child.name, get_compact_value_inner(child)))
+        .collect();
+    format!("{{ {} }}", children.join(", "))
+}
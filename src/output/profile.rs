@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, HashSet};
+
+/// `sol_log` costs at least this, longer messages cost one unit per byte
+const LOG_BASE_COST: u64 = 100;
+
+/// Compute units measured with the probes of `inst_ast_profile`. The cost of the probes is subtracted
+#[derive(Debug, Default)]
+pub struct Profile {
+    /// Cost of one probe
+    pub probe_overhead: u64,
+    /// Folded call stacks for flamegraph tools: `outer;inner` to the units spent in `inner` itself
+    pub folded: BTreeMap<String, u64>,
+    /// By function and file
+    pub functions: BTreeMap<(String, String), FunctionProfile>,
+    /// Reported by the runtime for each instruction, including the probes
+    pub consumed: Vec<u64>,
+    /// Total cost of the probes
+    pub total_overhead: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct FunctionProfile {
+    pub calls: usize,
+    /// Including the functions it calls
+    pub inclusive: u64,
+    /// Only the function itself
+    pub exclusive: u64,
+    /// By line, including the functions called on it
+    pub lines_inclusive: BTreeMap<usize, u64>,
+}
+
+/// A probe: its marker, the remaining compute units and the program invocation it was in
+struct Probe {
+    marker: String,
+    remaining: u64,
+    depth: usize,
+}
+
+struct ProfileFrame {
+    function: String,
+    file: String,
+    line: Option<usize>,
+    depth: usize,
+}
+
+pub fn parse_profile(output: &[String]) -> Result<Profile, Box<dyn std::error::Error>> {
+    let mut profile = Profile::default();
+
+    // Pair each marker with the next `Program consumption: N units remaining`
+    let mut probes = Vec::new();
+    let mut marker = None;
+    let mut depth = 0;
+    for line in output {
+        if let Some(rest) = line.strip_prefix("Program log: -.!;P;") {
+            marker = Some(rest.to_string());
+        } else if let Some(rest) = line.strip_prefix("Program consumption: ") {
+            let remaining = rest.trim_end_matches(" units remaining").parse()?;
+            if let Some(marker) = marker.take() {
+                probes.push(Probe { marker, remaining, depth });
+            }
+        } else if let Some(rest) = line.strip_prefix("Program ").filter(|_| !line.starts_with("Program log:") && !line.starts_with("Program data:")) {
+            // E.g. `Program 11111111111111111111111111111111 invoke [1]`
+            if let Some((_, invoke_depth)) = rest.split_once(" invoke [") {
+                depth = invoke_depth.trim_end_matches(']').parse()?;
+            } else if rest.ends_with(" success") || rest.contains(" failed: ") {
+                depth = depth.saturating_sub(1);
+            } else if let Some((_, consumed)) = rest.split_once(" consumed ") {
+                // E.g. `Program X consumed 1234 of 200000 compute units`, for the instructions of the transaction
+                if depth == 1 {
+                    profile.consumed.push(consumed.split(' ').next().unwrap_or_default().parse()?);
+                }
+            }
+        }
+    }
+
+    // Two probes in a row cost the overhead of the second. Variations come from the length of the marker
+    profile.probe_overhead = probes.windows(2)
+        .filter(|pair| pair[0].marker == "CAL" && pair[0].remaining >= pair[1].remaining)
+        .map(|pair| (pair[0].remaining - pair[1].remaining).saturating_sub(get_marker_cost(&pair[1].marker)))
+        .min()
+        .unwrap_or(0);
+
+    let mut stack: Vec<ProfileFrame> = Vec::new();
+    let mut previous_remaining: Option<u64> = None;
+    for probe in probes {
+        let overhead = profile.probe_overhead + get_marker_cost(&probe.marker);
+        profile.total_overhead += overhead;
+
+        // Frames of an invocation that stopped without leaving them, e.g. it panicked
+        while stack.last().is_some_and(|frame| frame.depth > probe.depth) {
+            stack.pop();
+        }
+
+        // The remaining units go up for the next instruction of the transaction
+        if let Some(previous) = previous_remaining.filter(|previous| *previous >= probe.remaining) {
+            let cost = (previous - probe.remaining).saturating_sub(overhead);
+            add_cost(&mut profile, &stack, cost);
+        }
+        previous_remaining = Some(probe.remaining);
+
+        let split: Vec<&str> = probe.marker.splitn(3, ';').collect();
+        match split[0] {
+            "ENTER" if split.len() == 3 => {
+                let (function, file) = (split[1].to_string(), split[2].to_string());
+                profile.functions.entry((function.clone(), file.clone())).or_default().calls += 1;
+                stack.push(ProfileFrame { function, file, line: None, depth: probe.depth });
+            }
+            "EXIT" => {
+                stack.pop();
+            }
+            "L" if split.len() == 2 => {
+                if let Some(frame) = stack.last_mut() {
+                    frame.line = Some(split[1].parse()?);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(profile)
+}
+
+/// The part of the marker's `sol_log` that is not included in the overhead
+fn get_marker_cost(marker: &str) -> u64 {
+    // The prefix `-.!;P;` is removed
+    let len = marker.len() as u64 + 6;
+    len.saturating_sub(LOG_BASE_COST)
+}
+
+/// Attribute the cost to the innermost frame, and to all frames for the inclusive costs. Recursive functions count once
+fn add_cost(profile: &mut Profile, stack: &[ProfileFrame], cost: u64) {
+    let Some(top) = stack.last() else {
+        return;
+    };
+    let folded_stack = stack.iter().map(|frame| frame.function.as_str()).collect::<Vec<_>>().join(";");
+    *profile.folded.entry(folded_stack).or_default() += cost;
+
+    profile.functions.entry((top.function.clone(), top.file.clone())).or_default().exclusive += cost;
+
+    let mut seen_functions = HashSet::new();
+    let mut seen_lines = HashSet::new();
+    for frame in stack {
+        let key = (frame.function.clone(), frame.file.clone());
+        let function_profile = profile.functions.entry(key.clone()).or_default();
+        if seen_functions.insert(key.clone()) {
+            function_profile.inclusive += cost;
+        }
+        if let Some(line) = frame.line {
+            if seen_lines.insert((key, line)) {
+                *function_profile.lines_inclusive.entry(line).or_default() += cost;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The logs of the probes, each with the remaining units after it
+    fn get_logs(probes: &[(&str, u64)]) -> Vec<String> {
+        probes.iter().flat_map(|(marker, remaining)| match marker.strip_prefix("Program ") {
+            Some(_) => vec![marker.to_string()],
+            None => vec![format!("Program log: -.!;P;{}", marker), format!("Program consumption: {} units remaining", remaining)],
+        }).collect()
+    }
+
+    fn get_function<'a>(profile: &'a Profile, function: &str) -> &'a FunctionProfile {
+        &profile.functions[&(function.to_string(), "src/lib.rs".to_string())]
+    }
+
+    #[test]
+    fn test_nested_calls() {
+        let logs = get_logs(&[
+            ("Program 11111111111111111111111111111111 invoke [1]", 0),
+            ("CAL", 10000),
+            ("ENTER;main;src/lib.rs", 9900),
+            ("L;5", 9700),
+            ("CAL", 9500),
+            ("ENTER;helper;src/lib.rs", 9400),
+            ("L;10", 9100),
+            ("EXIT", 8900),
+            ("L;6", 8700),
+            ("EXIT", 8600),
+            ("Program 11111111111111111111111111111111 consumed 1500 of 200000 compute units", 0),
+            ("Program 11111111111111111111111111111111 success", 0),
+        ]);
+        let profile = parse_profile(&logs).unwrap();
+
+        assert_eq!(profile.probe_overhead, 100);
+        assert_eq!(profile.total_overhead, 900);
+        assert_eq!(profile.consumed, vec![1500]);
+
+        let main = get_function(&profile, "main");
+        assert_eq!((main.calls, main.inclusive, main.exclusive), (1, 600, 300));
+        assert_eq!(main.lines_inclusive, BTreeMap::from([(5, 500), (6, 0)]));
+        let helper = get_function(&profile, "helper");
+        assert_eq!((helper.calls, helper.inclusive, helper.exclusive), (1, 300, 300));
+        assert_eq!(helper.lines_inclusive, BTreeMap::from([(10, 100)]));
+
+        assert_eq!(profile.folded, BTreeMap::from([("main".to_string(), 300), ("main;helper".to_string(), 300)]));
+    }
+
+    #[test]
+    fn test_recursion_counts_once() {
+        let logs = get_logs(&[
+            ("Program 11111111111111111111111111111111 invoke [1]", 0),
+            ("CAL", 1000),
+            ("ENTER;f;src/lib.rs", 900),
+            ("L;1", 800),
+            ("CAL", 700),
+            ("ENTER;f;src/lib.rs", 600),
+            ("L;2", 400),
+            ("EXIT", 300),
+            ("EXIT", 200),
+        ]);
+        let profile = parse_profile(&logs).unwrap();
+
+        let f = get_function(&profile, "f");
+        assert_eq!((f.calls, f.inclusive, f.exclusive), (2, 100, 100));
+        assert_eq!(f.lines_inclusive[&1], 100);
+        assert_eq!(profile.folded["f;f"], 100);
+    }
+
+    #[test]
+    fn test_failed_invocation_leaves_frames() {
+        let logs = get_logs(&[
+            ("Program 11111111111111111111111111111111 invoke [1]", 0),
+            ("CAL", 1000),
+            ("ENTER;f;src/lib.rs", 900),
+            ("L;3", 700),
+            ("Program 22222222222222222222222222222222 invoke [2]", 0),
+            ("CAL", 600),
+            ("ENTER;g;src/lib.rs", 500),
+            ("L;1", 300),
+            ("Program 22222222222222222222222222222222 failed: custom program error: 0x1", 0),
+            ("L;4", 100),
+        ]);
+        let profile = parse_profile(&logs).unwrap();
+
+        // The frame of `g` is left when `f` probes again
+        let f = get_function(&profile, "f");
+        assert_eq!((f.inclusive, f.exclusive), (300, 200));
+        assert_eq!(f.lines_inclusive[&3], 200);
+        let g = get_function(&profile, "g");
+        assert_eq!((g.calls, g.exclusive), (1, 100));
+    }
+}
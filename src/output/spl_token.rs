@@ -0,0 +1,579 @@
+use solana_sdk::pubkey::Pubkey;
+use crate::output::parse::{DebugNode, DebugNodeType};
+
+const SPL_TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const SPL_TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+
+const MINT_LEN: usize = 82;
+const ACCOUNT_LEN: usize = 165;
+const MULTISIG_LEN: usize = 355;
+
+/// Token-2022 extension types, indexed by their discriminator
+const EXTENSION_TYPE_NAMES: [&str; 28] = [
+    "Uninitialized",
+    "TransferFeeConfig",
+    "TransferFeeAmount",
+    "MintCloseAuthority",
+    "ConfidentialTransferMint",
+    "ConfidentialTransferAccount",
+    "DefaultAccountState",
+    "ImmutableOwner",
+    "MemoTransfer",
+    "NonTransferable",
+    "InterestBearingConfig",
+    "CpiGuard",
+    "PermanentDelegate",
+    "NonTransferableAccount",
+    "TransferHook",
+    "TransferHookAccount",
+    "ConfidentialTransferFeeConfig",
+    "ConfidentialTransferFeeAmount",
+    "MetadataPointer",
+    "TokenMetadata",
+    "GroupPointer",
+    "TokenGroup",
+    "GroupMemberPointer",
+    "TokenGroupMember",
+    "ConfidentialMintBurn",
+    "ScaledUiAmount",
+    "Pausable",
+    "PausableAccount",
+];
+
+/// Add a `token_state` child to every `AccountInfo` node (at any depth) that is owned by the SPL Token or Token-2022 program
+pub fn decode_token_accounts(node: &mut DebugNode) {
+    if let Some(token_state) = get_token_state_node(node) {
+        node.children.push(token_state);
+        return;
+    }
+    for child in node.children.iter_mut() {
+        decode_token_accounts(child);
+    }
+}
+
+fn get_token_state_node(node: &DebugNode) -> Option<DebugNode> {
+    if !node.full_type.contains("AccountInfo") {
+        return None;
+    }
+    let owner = get_leaf_value(node.children.iter().find(|n| n.name == "owner")?)?;
+    let data = get_byte_array(node.children.iter().find(|n| n.name == "data")?)?;
+    get_token_state(owner, &data)
+}
+
+/// Decode the data of an account owned by the SPL Token or Token-2022 program, e.g. to show the accounts after the transaction
+///
+/// `None` for other owners, or if the data is not a `Mint`, `Account` or `Multisig`
+pub fn get_token_state(owner: &str, data: &[u8]) -> Option<DebugNode> {
+    let program_name = match owner {
+        SPL_TOKEN_PROGRAM_ID => "spl_token",
+        SPL_TOKEN_2022_PROGRAM_ID => "spl_token_2022",
+        _ => return None,
+    };
+
+    let (state_name, children) = match data.len() {
+        MINT_LEN => ("Mint", decode_mint(&mut TokenDataReader::new(data))?),
+        ACCOUNT_LEN => ("Account", decode_account(&mut TokenDataReader::new(data))?),
+        MULTISIG_LEN => ("Multisig", decode_multisig(&mut TokenDataReader::new(data))?),
+        len if len > ACCOUNT_LEN && program_name == "spl_token_2022" => {
+            // Mints are padded to the length of an account, then comes the account type and the extensions
+            let (state_name, mut children) = match data[ACCOUNT_LEN] {
+                1 => ("Mint", decode_mint(&mut TokenDataReader::new(&data[..MINT_LEN]))?),
+                2 => ("Account", decode_account(&mut TokenDataReader::new(&data[..ACCOUNT_LEN]))?),
+                _ => return None,
+            };
+            children.push(decode_extensions(&data[ACCOUNT_LEN + 1..]));
+            (state_name, children)
+        }
+        _ => return None,
+    };
+
+    Some(new_complex_node("token_state", &format!("{}::state::{}", program_name, state_name), children))
+}
+
+/// Follow a chain of references (e.g. `&Pubkey`) to the value at its end
+fn get_leaf_value(node: &DebugNode) -> Option<&str> {
+    if node.children.is_empty() {
+        return Some(&node.value);
+    }
+    match node.children.as_slice() {
+        [child] if child.name == "value" => get_leaf_value(child),
+        _ => None,
+    }
+}
+
+/// Find the byte array inside the `Rc<RefCell<&mut [u8]>>` of an `AccountInfo`
+fn get_byte_array(node: &DebugNode) -> Option<Vec<u8>> {
+    if node.value.starts_with("len=") {
+        return node.children.iter().map(|n| n.value.parse::<u8>().ok()).collect();
+    }
+    node.children.iter()
+        .filter(|n| n.name == "value")
+        .find_map(get_byte_array)
+}
+
+fn decode_mint(reader: &mut TokenDataReader) -> Option<Vec<DebugNode>> {
+    Some(vec![
+        new_primitive_node("mint_authority", "COption<Pubkey>", reader.read_coption_pubkey()?),
+        new_primitive_node("supply", "u64", reader.read_u64()?.to_string()),
+        new_primitive_node("decimals", "u8", reader.read_u8()?.to_string()),
+        new_primitive_node("is_initialized", "bool", reader.read_bool()?.to_string()),
+        new_primitive_node("freeze_authority", "COption<Pubkey>", reader.read_coption_pubkey()?),
+    ])
+}
+
+fn decode_account(reader: &mut TokenDataReader) -> Option<Vec<DebugNode>> {
+    Some(vec![
+        new_primitive_node("mint", "Pubkey", reader.read_pubkey()?),
+        new_primitive_node("owner", "Pubkey", reader.read_pubkey()?),
+        new_primitive_node("amount", "u64", reader.read_u64()?.to_string()),
+        new_primitive_node("delegate", "COption<Pubkey>", reader.read_coption_pubkey()?),
+        new_primitive_node("state", "AccountState", get_account_state_str(reader.read_u8()?).to_string()),
+        new_primitive_node("is_native", "COption<u64>", reader.read_coption_u64()?),
+        new_primitive_node("delegated_amount", "u64", reader.read_u64()?.to_string()),
+        new_primitive_node("close_authority", "COption<Pubkey>", reader.read_coption_pubkey()?),
+    ])
+}
+
+fn decode_multisig(reader: &mut TokenDataReader) -> Option<Vec<DebugNode>> {
+    let m = reader.read_u8()?;
+    let n = reader.read_u8()?;
+    let is_initialized = reader.read_bool()?;
+    let mut signers = Vec::new();
+    for i in 0..n.min(11) {
+        signers.push(new_primitive_node(&i.to_string(), "Pubkey", reader.read_pubkey()?));
+    }
+    let mut signers_node = new_complex_node("signers", "[Pubkey; 11]", signers);
+    signers_node.value = format!("len={}", n);
+
+    Some(vec![
+        new_primitive_node("m", "u8", m.to_string()),
+        new_primitive_node("n", "u8", n.to_string()),
+        new_primitive_node("is_initialized", "bool", is_initialized.to_string()),
+        signers_node,
+    ])
+}
+
+/// Decode the TLV entries that follow the account type
+fn decode_extensions(data: &[u8]) -> DebugNode {
+    let mut reader = TokenDataReader::new(data);
+    let mut extensions = Vec::new();
+    while let (Some(ext_type), Some(ext_len)) = (reader.read_u16(), reader.read_u16()) {
+        let Some(value) = reader.read_bytes(ext_len as usize) else {
+            break;
+        };
+        // The rest of the data is zeroed
+        if ext_type == 0 {
+            break;
+        }
+        let ext_name = EXTENSION_TYPE_NAMES.get(ext_type as usize).copied().unwrap_or("Unknown");
+        let children = decode_extension(ext_name, &mut TokenDataReader::new(value));
+        let mut ext_node = new_complex_node(ext_name, "", children.unwrap_or_default());
+        if ext_node.children.is_empty() && !value.is_empty() {
+            ext_node.value = format!("len={}", value.len());
+        }
+        extensions.push(ext_node);
+    }
+    let mut node = new_complex_node("extensions", "", extensions);
+    node.value = format!("len={}", node.children.len());
+    node
+}
+
+/// Decode the extensions with a simple layout. For all others, only the name is shown
+fn decode_extension(ext_name: &str, reader: &mut TokenDataReader) -> Option<Vec<DebugNode>> {
+    let result = match ext_name {
+        "TransferFeeConfig" => {
+            let mut nodes = vec![
+                new_primitive_node("transfer_fee_config_authority", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+                new_primitive_node("withdraw_withheld_authority", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+                new_primitive_node("withheld_amount", "u64", reader.read_u64()?.to_string()),
+            ];
+            for name in ["older_transfer_fee", "newer_transfer_fee"] {
+                nodes.push(new_complex_node(name, "TransferFee", vec![
+                    new_primitive_node("epoch", "u64", reader.read_u64()?.to_string()),
+                    new_primitive_node("maximum_fee", "u64", reader.read_u64()?.to_string()),
+                    new_primitive_node("transfer_fee_basis_points", "u16", reader.read_u16()?.to_string()),
+                ]));
+            }
+            nodes
+        }
+        "TransferFeeAmount" => vec![
+            new_primitive_node("withheld_amount", "u64", reader.read_u64()?.to_string()),
+        ],
+        "MintCloseAuthority" => vec![
+            new_primitive_node("close_authority", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+        ],
+        "DefaultAccountState" => vec![
+            new_primitive_node("state", "AccountState", get_account_state_str(reader.read_u8()?).to_string()),
+        ],
+        "MemoTransfer" => vec![
+            new_primitive_node("require_incoming_transfer_memos", "bool", reader.read_bool()?.to_string()),
+        ],
+        "InterestBearingConfig" => vec![
+            new_primitive_node("rate_authority", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+            new_primitive_node("initialization_timestamp", "i64", reader.read_i64()?.to_string()),
+            new_primitive_node("pre_update_average_rate", "i16", reader.read_i16()?.to_string()),
+            new_primitive_node("last_update_timestamp", "i64", reader.read_i64()?.to_string()),
+            new_primitive_node("current_rate", "i16", reader.read_i16()?.to_string()),
+        ],
+        "CpiGuard" => vec![
+            new_primitive_node("lock_cpi", "bool", reader.read_bool()?.to_string()),
+        ],
+        "PermanentDelegate" => vec![
+            new_primitive_node("delegate", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+        ],
+        "TransferHook" => vec![
+            new_primitive_node("authority", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+            new_primitive_node("program_id", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+        ],
+        "TransferHookAccount" => vec![
+            new_primitive_node("transferring", "bool", reader.read_bool()?.to_string()),
+        ],
+        "MetadataPointer" => vec![
+            new_primitive_node("authority", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+            new_primitive_node("metadata_address", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+        ],
+        "GroupPointer" => vec![
+            new_primitive_node("authority", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+            new_primitive_node("group_address", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+        ],
+        "GroupMemberPointer" => vec![
+            new_primitive_node("authority", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+            new_primitive_node("member_address", "OptionalNonZeroPubkey", reader.read_optional_pubkey()?),
+        ],
+        "TokenMetadata" => {
+            let update_authority = reader.read_optional_pubkey()?;
+            let mint = reader.read_pubkey()?;
+            let name = reader.read_borsh_string()?;
+            let symbol = reader.read_borsh_string()?;
+            let uri = reader.read_borsh_string()?;
+            let additional_len = reader.read_u32()?;
+            let mut additional_metadata = Vec::new();
+            for _ in 0..additional_len {
+                let key = reader.read_borsh_string()?;
+                let value = reader.read_borsh_string()?;
+                additional_metadata.push(new_primitive_node(&key, "String", format!(r#""{}""#, value)));
+            }
+            let mut additional_node = new_complex_node("additional_metadata", "Vec<(String, String)>", additional_metadata);
+            additional_node.value = format!("len={}", additional_len);
+            vec![
+                new_primitive_node("update_authority", "OptionalNonZeroPubkey", update_authority),
+                new_primitive_node("mint", "Pubkey", mint),
+                new_primitive_node("name", "String", format!(r#""{}""#, name)),
+                new_primitive_node("symbol", "String", format!(r#""{}""#, symbol)),
+                new_primitive_node("uri", "String", format!(r#""{}""#, uri)),
+                additional_node,
+            ]
+        }
+        _ => vec![],
+    };
+    Some(result)
+}
+
+fn get_account_state_str(state: u8) -> &'static str {
+    match state {
+        0 => "Uninitialized",
+        1 => "Initialized",
+        2 => "Frozen",
+        _ => "Invalid",
+    }
+}
+
+fn new_primitive_node(name: &str, full_type: &str, value: String) -> DebugNode {
+    DebugNode {
+        node_type: DebugNodeType::Primitive,
+        name: name.to_string(),
+        full_type: full_type.to_string(),
+        value,
+        children: vec![],
+    }
+}
+
+fn new_complex_node(name: &str, full_type: &str, children: Vec<DebugNode>) -> DebugNode {
+    DebugNode {
+        node_type: DebugNodeType::Complex,
+        name: name.to_string(),
+        full_type: full_type.to_string(),
+        value: "".to_string(),
+        children,
+    }
+}
+
+/// Reads little-endian values from account data. All methods return None if there's not enough data left
+struct TokenDataReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> TokenDataReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        TokenDataReader { data, pos: 0 }
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.read_bytes(N)?.try_into().ok()
+    }
+
+    fn read_u8(&mut self) -> Option<u8> {
+        Some(self.read_array::<1>()?[0])
+    }
+
+    fn read_bool(&mut self) -> Option<bool> {
+        Some(self.read_u8()? != 0)
+    }
+
+    fn read_u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_i16(&mut self) -> Option<i16> {
+        Some(i16::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_i64(&mut self) -> Option<i64> {
+        Some(i64::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_pubkey(&mut self) -> Option<String> {
+        Some(Pubkey::from(self.read_array::<32>()?).to_string())
+    }
+
+    /// `COption<Pubkey>` has a 4 byte tag
+    fn read_coption_pubkey(&mut self) -> Option<String> {
+        let tag = self.read_u32()?;
+        let pubkey = self.read_pubkey()?;
+        Some(if tag == 0 { "None".to_string() } else { pubkey })
+    }
+
+    /// `COption<u64>` has a 4 byte tag
+    fn read_coption_u64(&mut self) -> Option<String> {
+        let tag = self.read_u32()?;
+        let value = self.read_u64()?;
+        Some(if tag == 0 { "None".to_string() } else { value.to_string() })
+    }
+
+    /// `OptionalNonZeroPubkey` uses the zero pubkey as None
+    fn read_optional_pubkey(&mut self) -> Option<String> {
+        let bytes = self.read_array::<32>()?;
+        Some(if bytes == [0; 32] { "None".to_string() } else { Pubkey::from(bytes).to_string() })
+    }
+
+    fn read_borsh_string(&mut self) -> Option<String> {
+        let len = self.read_u32()?;
+        let bytes = self.read_bytes(len as usize)?;
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pubkey(n: u8) -> String {
+        Pubkey::from([n; 32]).to_string()
+    }
+
+    fn get_child<'a>(node: &'a DebugNode, path: &str) -> &'a DebugNode {
+        path.split('.').fold(node, |node, name| {
+            node.children.iter().find(|child| child.name == name).unwrap_or_else(|| panic!("No {} in {}", name, node.name))
+        })
+    }
+
+    fn get_value<'a>(node: &'a DebugNode, path: &str) -> &'a str {
+        &get_child(node, path).value
+    }
+
+    fn get_mint_data() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(1u32.to_le_bytes());
+        data.extend([1; 32]);
+        data.extend(1_000_000u64.to_le_bytes());
+        data.push(6);
+        data.push(1);
+        data.extend(0u32.to_le_bytes());
+        data.extend([0; 32]);
+        data
+    }
+
+    fn get_account_data() -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend([2; 32]);
+        data.extend([3; 32]);
+        data.extend(500u64.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        data.extend([4; 32]);
+        data.push(2);
+        data.extend(1u32.to_le_bytes());
+        data.extend(2_039_280u64.to_le_bytes());
+        data.extend(7u64.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend([0; 32]);
+        data
+    }
+
+    fn push_extension(data: &mut Vec<u8>, ext_type: u16, value: &[u8]) {
+        data.extend(ext_type.to_le_bytes());
+        data.extend((value.len() as u16).to_le_bytes());
+        data.extend(value);
+    }
+
+    fn push_borsh_string(data: &mut Vec<u8>, value: &str) {
+        data.extend((value.len() as u32).to_le_bytes());
+        data.extend(value.as_bytes());
+    }
+
+    #[test]
+    fn test_mint() {
+        let data = get_mint_data();
+        assert_eq!(data.len(), MINT_LEN);
+        let state = get_token_state(SPL_TOKEN_PROGRAM_ID, &data).unwrap();
+        assert_eq!(state.full_type, "spl_token::state::Mint");
+        assert_eq!(get_value(&state, "mint_authority"), pubkey(1));
+        assert_eq!(get_value(&state, "supply"), "1000000");
+        assert_eq!(get_value(&state, "decimals"), "6");
+        assert_eq!(get_value(&state, "is_initialized"), "true");
+        assert_eq!(get_value(&state, "freeze_authority"), "None");
+    }
+
+    #[test]
+    fn test_account() {
+        let data = get_account_data();
+        assert_eq!(data.len(), ACCOUNT_LEN);
+        let state = get_token_state(SPL_TOKEN_PROGRAM_ID, &data).unwrap();
+        assert_eq!(state.full_type, "spl_token::state::Account");
+        assert_eq!(get_value(&state, "mint"), pubkey(2));
+        assert_eq!(get_value(&state, "owner"), pubkey(3));
+        assert_eq!(get_value(&state, "amount"), "500");
+        assert_eq!(get_value(&state, "delegate"), pubkey(4));
+        assert_eq!(get_value(&state, "state"), "Frozen");
+        assert_eq!(get_value(&state, "is_native"), "2039280");
+        assert_eq!(get_value(&state, "delegated_amount"), "7");
+        assert_eq!(get_value(&state, "close_authority"), "None");
+    }
+
+    #[test]
+    fn test_multisig() {
+        let mut data = vec![2, 3, 1];
+        for i in 0..11 {
+            data.extend([i + 10; 32]);
+        }
+        assert_eq!(data.len(), MULTISIG_LEN);
+        let state = get_token_state(SPL_TOKEN_PROGRAM_ID, &data).unwrap();
+        assert_eq!(get_value(&state, "m"), "2");
+        assert_eq!(get_value(&state, "n"), "3");
+        let signers = get_child(&state, "signers");
+        assert_eq!(signers.value, "len=3");
+        assert_eq!(signers.children.len(), 3);
+        assert_eq!(get_value(&state, "signers.2"), pubkey(12));
+    }
+
+    #[test]
+    fn test_token_2022_account_extensions() {
+        let mut data = get_account_data();
+        data.push(2);
+        push_extension(&mut data, 7, &[]);
+        push_extension(&mut data, 2, &42u64.to_le_bytes());
+        push_extension(&mut data, 8, &[1]);
+        // Zeroed rest of the data
+        data.extend([0; 8]);
+
+        // Only Token-2022 accounts have extensions
+        assert!(get_token_state(SPL_TOKEN_PROGRAM_ID, &data).is_none());
+
+        let state = get_token_state(SPL_TOKEN_2022_PROGRAM_ID, &data).unwrap();
+        assert_eq!(state.full_type, "spl_token_2022::state::Account");
+        assert_eq!(get_value(&state, "amount"), "500");
+        let extensions = get_child(&state, "extensions");
+        let names: Vec<&str> = extensions.children.iter().map(|ext| ext.name.as_str()).collect();
+        assert_eq!(names, vec!["ImmutableOwner", "TransferFeeAmount", "MemoTransfer"]);
+        assert_eq!(get_value(&state, "extensions.TransferFeeAmount.withheld_amount"), "42");
+        assert_eq!(get_value(&state, "extensions.MemoTransfer.require_incoming_transfer_memos"), "true");
+    }
+
+    #[test]
+    fn test_token_2022_mint_metadata() {
+        let mut data = get_mint_data();
+        data.resize(ACCOUNT_LEN, 0);
+        data.push(1);
+        push_extension(&mut data, 18, &[[5; 32], [6; 32]].concat());
+        let mut metadata = Vec::new();
+        metadata.extend([5; 32]);
+        metadata.extend([6; 32]);
+        push_borsh_string(&mut metadata, "Token");
+        push_borsh_string(&mut metadata, "TKN");
+        push_borsh_string(&mut metadata, "https://example.com");
+        metadata.extend(1u32.to_le_bytes());
+        push_borsh_string(&mut metadata, "key");
+        push_borsh_string(&mut metadata, "value");
+        push_extension(&mut data, 19, &metadata);
+
+        let state = get_token_state(SPL_TOKEN_2022_PROGRAM_ID, &data).unwrap();
+        assert_eq!(state.full_type, "spl_token_2022::state::Mint");
+        assert_eq!(get_value(&state, "supply"), "1000000");
+        assert_eq!(get_value(&state, "extensions.MetadataPointer.metadata_address"), pubkey(6));
+        assert_eq!(get_value(&state, "extensions.TokenMetadata.update_authority"), pubkey(5));
+        assert_eq!(get_value(&state, "extensions.TokenMetadata.name"), r#""Token""#);
+        assert_eq!(get_value(&state, "extensions.TokenMetadata.uri"), r#""https://example.com""#);
+        assert_eq!(get_value(&state, "extensions.TokenMetadata.additional_metadata.key"), r#""value""#);
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        // Not a token state
+        assert!(get_token_state(SPL_TOKEN_PROGRAM_ID, &get_account_data()[..100]).is_none());
+        assert!(get_token_state("11111111111111111111111111111111", &get_account_data()).is_none());
+
+        // The last extension is cut off
+        let mut data = get_account_data();
+        data.push(2);
+        push_extension(&mut data, 2, &42u64.to_le_bytes());
+        push_extension(&mut data, 8, &[1]);
+        data.truncate(data.len() - 1);
+        let state = get_token_state(SPL_TOKEN_2022_PROGRAM_ID, &data).unwrap();
+        assert_eq!(get_child(&state, "extensions").value, "len=1");
+
+        // The metadata strings are longer than the extension
+        let mut data = get_mint_data();
+        data.resize(ACCOUNT_LEN, 0);
+        data.push(1);
+        let mut metadata = [[5; 32], [6; 32]].concat();
+        metadata.extend(100u32.to_le_bytes());
+        metadata.extend(b"Tok");
+        push_extension(&mut data, 19, &metadata);
+        let state = get_token_state(SPL_TOKEN_2022_PROGRAM_ID, &data).unwrap();
+        let token_metadata = get_child(&state, "extensions.TokenMetadata");
+        assert!(token_metadata.children.is_empty());
+        assert_eq!(token_metadata.value, format!("len={}", metadata.len()));
+    }
+
+    #[test]
+    fn test_decode_account_info() {
+        fn new_node(name: &str, full_type: &str, value: &str, children: Vec<DebugNode>) -> DebugNode {
+            DebugNode { node_type: DebugNodeType::Complex, name: name.into(), full_type: full_type.into(), value: value.into(), children }
+        }
+        let data = get_account_data();
+        let bytes = data.iter().map(|byte| new_node("x", "u8", &byte.to_string(), vec![])).collect();
+        let data_node = new_node("data", "", "strong_count=1, weak_count=0", vec![
+            new_node("value", "", "", vec![new_node("value", "", &format!("len={}", data.len()), bytes)]),
+        ]);
+        let owner_node = new_node("owner", "&Pubkey", "", vec![new_node("value", "Pubkey", SPL_TOKEN_PROGRAM_ID, vec![])]);
+        let account_info = new_node("source", "solana_program::account_info::AccountInfo", "", vec![owner_node, data_node]);
+        let mut accounts = new_node("accounts", "&[AccountInfo]", "len=1", vec![account_info]);
+
+        decode_token_accounts(&mut accounts);
+        assert_eq!(get_value(&accounts, "source.token_state.amount"), "500");
+    }
+}
@@ -8,6 +8,8 @@ pub struct DebugeeProjectInfo {
     pub is_workspace: bool,
     pub target_directory: PathBuf,
     pub target_name: String,
+    /// The root folders of the packages in the workspace, including the program
+    pub member_paths: Vec<PathBuf>,
 }
 
 pub fn get_program_info(program_path: &Path) -> Result<DebugeeProjectInfo, Box<dyn std::error::Error>> {
@@ -39,6 +41,10 @@ pub fn get_program_info(program_path: &Path) -> Result<DebugeeProjectInfo, Box<d
     // For a workspace, this is usually $workspace_root/target
     let target_directory = PathBuf::from(metadata.target_directory);
     let target_name = target.name.clone();
+    let member_paths = metadata.packages.iter()
+        .filter(|package| metadata.workspace_members.contains(&package.id))
+        .filter_map(|package| package.manifest_path.parent().map(|path| path.as_std_path().to_path_buf()))
+        .collect();
 
     Ok(
         DebugeeProjectInfo {
@@ -47,6 +53,7 @@ pub fn get_program_info(program_path: &Path) -> Result<DebugeeProjectInfo, Box<d
             is_workspace,
             target_directory,
             target_name,
+            member_paths,
         }
     )
 }
\ No newline at end of file
@@ -91,6 +91,11 @@ pub fn get_config_path() -> PathBuf {
     get_cache_dir().join("config.json")
 }
 
+/// The last recording of `record`, see `Recording`
+pub fn get_recording_path() -> PathBuf {
+    get_cache_dir().join("recording.json")
+}
+
 pub fn ensure_cache_dir() {
     let cache_dir = get_cache_dir();
     fs::create_dir_all(&cache_dir).expect("Failed to create cache dir");
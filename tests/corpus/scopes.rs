@@ -0,0 +1,36 @@
+// Closures, trait default methods and nested functions in the style of a staking program
+//
+// A line ending with a `@bindings` comment lists the variables that must be in scope when breaking at that line
+
+pub trait StakeState {
+    fn lamports(&self) -> u64;
+
+    fn is_active(&self, current_epoch: u64) -> bool {
+        let lamports = self.lamports();
+        lamports > 0 && current_epoch > 0 // @bindings: self, current_epoch, lamports
+    }
+}
+
+pub fn process_withdraw(accounts: &[AccountInfo], amount: u64, epoch: u64) -> ProgramResult {
+    let stake_info = &accounts[0];
+    let fee = amount / 100;
+
+    fn compute_reward(stake: u64, rate: u64) -> u64 {
+        let reward = stake * rate; // @bindings: stake, rate
+        reward / 1000
+    }
+
+    let rewards: Vec<u64> = accounts.iter().map(|acc| {
+        let reward = compute_reward(acc.lamports(), epoch); // @bindings: epoch, fee, acc
+        reward + fee // @bindings: epoch, fee, acc, reward
+    }).collect();
+
+    let total = rewards.iter().fold(0, |sum, reward| sum + reward); // @bindings: accounts, amount, epoch, stake_info, fee, rewards
+
+    let log_total = move || {
+        msg!("{} {}", total, amount); // @bindings: amount, total
+    };
+    log_total(); // @bindings: accounts, amount, epoch, stake_info, fee, rewards, total, log_total
+
+    Ok(())
+}
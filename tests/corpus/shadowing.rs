@@ -0,0 +1,43 @@
+// Shadowed variables and unit variants in the style of a counter program
+//
+// A line ending with a `@bindings` comment lists the variables that must be in scope when breaking at that line
+
+const MAX_COUNT: u64 = 1000;
+
+pub enum CounterInstruction {
+    Create,
+    Increase { amount: u64 },
+    Reset,
+}
+
+pub fn process_increase_counter(accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+    let amount = amount.checked_mul(2).ok_or(ProgramError::ArithmeticOverflow)?;
+    let counter = 0u64; // @bindings: accounts, amount (line 14)
+    let amount = amount.checked_add(counter).ok_or(ProgramError::ArithmeticOverflow)?;
+    if amount > 0 {
+        let counter = counter + 1;
+        msg!("{}", counter); // @bindings: accounts, amount (line 16), counter (line 18)
+    }
+    match amount {
+        MAX_COUNT => msg!("Max"),
+        other => {
+            msg!("{}", other); // @bindings: accounts, amount (line 16), counter, other
+        }
+    }
+    Ok(()) // @bindings: accounts, amount (line 16), counter
+}
+
+pub fn process(instruction: CounterInstruction) -> ProgramResult {
+    match instruction {
+        Create | Reset => {
+            msg!("Create or Reset"); // @bindings: instruction
+        }
+        None => {
+            msg!("None"); // @bindings: instruction
+        }
+        CounterInstruction::Increase { amount } => {
+            msg!("{}", amount); // @bindings: instruction, amount
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+// Handler code in the style of the SPL Token and Governance programs
+//
+// A line ending with a `@bindings` comment lists the variables that must be in scope when breaking at that line
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+
+pub enum TokenInstruction {
+    InitializeMint { decimals: u8, mint_authority: Pubkey, freeze_authority: Option<Pubkey> },
+    Transfer { amount: u64 },
+    Approve { amount: u64 },
+    Revoke,
+    SetAuthority { authority_type: u8, new_authority: Option<Pubkey> },
+    MintTo(u64),
+    CloseAccount,
+}
+
+pub struct Processor {}
+
+impl Processor {
+    pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> ProgramResult {
+        let instruction = TokenInstruction::unpack(input)?;
+        match instruction {
+            TokenInstruction::InitializeMint { decimals, ref mint_authority, freeze_authority } => {
+                msg!("Instruction: InitializeMint"); // @bindings: program_id, accounts, input, instruction, decimals, mint_authority, freeze_authority
+                Self::process_initialize_mint(accounts, decimals, *mint_authority, freeze_authority)
+            }
+            TokenInstruction::Transfer { amount } | TokenInstruction::Approve { amount } => {
+                msg!("Instruction: Transfer or Approve"); // @bindings: program_id, accounts, input, instruction, amount
+                Self::process_transfer(program_id, accounts, amount)
+            }
+            TokenInstruction::MintTo(amount) if amount > 0 => {
+                msg!("Instruction: MintTo"); // @bindings: program_id, accounts, input, instruction, amount
+                Ok(())
+            }
+            TokenInstruction::SetAuthority { authority_type: kind @ 0..=3, new_authority: Some(new_authority) } => {
+                msg!("Instruction: SetAuthority"); // @bindings: program_id, accounts, input, instruction, kind, new_authority
+                Ok(())
+            }
+            TokenInstruction::Revoke | TokenInstruction::CloseAccount => {
+                msg!("Instruction: Revoke or CloseAccount"); // @bindings: program_id, accounts, input, instruction
+                Ok(())
+            }
+            _ => Err(ProgramError::InvalidInstructionData),
+        }
+    }
+
+    fn process_initialize_mint(
+        accounts: &[AccountInfo],
+        decimals: u8,
+        mint_authority: Pubkey,
+        freeze_authority: Option<Pubkey>,
+    ) -> ProgramResult {
+        let [mint_info, rent_sysvar_info, ..] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+        let mint_data_len = mint_info.data_len(); // @bindings: accounts, decimals, mint_authority, freeze_authority, mint_info, rent_sysvar_info
+        let mut mint = Mint::unpack_unchecked(&mint_info.data.borrow())?;
+        if let (Some(ref authority), true) = (freeze_authority, mint.is_initialized) {
+            msg!("Already initialized with {}", authority); // @bindings: accounts, decimals, mint_authority, freeze_authority, mint_info, rent_sysvar_info, mint_data_len, mint, authority
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+        mint.mint_authority = COption::Some(mint_authority);
+        Ok(())
+    }
+
+    fn process_transfer(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+        let account_info_iter = &mut accounts.iter();
+        let source_account_info = next_account_info(account_info_iter)?;
+        let destination_account_info = next_account_info(account_info_iter)?;
+        let (source_amount, destination_amount) = (source_account_info.lamports(), destination_account_info.lamports());
+
+        for (index, &AccountInfo { key, is_signer, .. }) in accounts.iter().enumerate() {
+            msg!("Signer {}", index); // @bindings: program_id, accounts, amount, account_info_iter, source_account_info, destination_account_info, source_amount, destination_amount, index, key, is_signer
+        }
+
+        let mut remaining = &accounts[2..];
+        while let [first, rest @ ..] = remaining {
+            remaining = rest; // @bindings: program_id, accounts, amount, account_info_iter, source_account_info, destination_account_info, source_amount, destination_amount, remaining, first, rest
+        }
+
+        let total: u64 = accounts.iter().map(|acc| {
+            let lamports = acc.lamports();
+            lamports // @bindings: acc, lamports
+        }).sum();
+
+        let owners = accounts.iter().filter(|&&AccountInfo { ref owner, .. }| {
+            let is_token = **owner == crate::id(); // @bindings: owner
+            is_token
+        });
+
+        let (mut checked, ((lo, hi), [first_byte, ..])) = (0u64, ((1u8, 2u8), [0u8; 4]));
+        checked += u64::from(lo) + u64::from(hi) + u64::from(first_byte); // @bindings: program_id, accounts, amount, account_info_iter, source_account_info, destination_account_info, source_amount, destination_amount, remaining, total, owners, checked, lo, hi, first_byte
+
+        if amount > source_amount {
+            return Err(ProgramError::InsufficientFunds); // @bindings: program_id, accounts, amount, account_info_iter, source_account_info, destination_account_info, source_amount, destination_amount, remaining, total, owners, checked, lo, hi, first_byte
+        }
+
+        Ok(())
+    }
+}